@@ -0,0 +1,91 @@
+//! Compares the size and load time of blocks stored as JSON and as binary records.
+//!
+//! Run with `cargo bench --bench block_storage`.
+
+use rocksdb::DB;
+use simple_blockchain_rs::{
+    address::Address, block::Block, client::Client, codec, config::Network, storage,
+    transaction::Transaction,
+};
+use std::time::{Duration, Instant};
+
+const BLOCKS: usize = 50;
+const TRANSACTIONS_PER_BLOCK: usize = 200;
+
+fn make_blocks() -> Vec<Block> {
+    let sender = Client::new();
+    let receiver = Address::from_public_key(&Client::new().public_key, Network::Mainnet);
+
+    (0..BLOCKS)
+        .map(|index| {
+            let mut block = Block::new(index, format!("{:064x}", index));
+            block.verified_transactions = (0..TRANSACTIONS_PER_BLOCK)
+                .map(|i| {
+                    let nonce = (index * TRANSACTIONS_PER_BLOCK + i) as u64;
+                    Transaction::signed_new(
+                        &sender,
+                        receiver.into(),
+                        i as f64 + 0.5,
+                        0.01,
+                        Some(nonce),
+                    )
+                })
+                .collect();
+            block.block_hash = block.calculate_hash();
+            block
+        })
+        .collect()
+}
+
+fn measure<F: FnMut()>(mut f: F) -> Duration {
+    let start = Instant::now();
+    f();
+    start.elapsed()
+}
+
+fn bench_format(name: &str, blocks: &[Block], encode: &dyn Fn(&Block) -> Vec<u8>) {
+    let path = std::env::temp_dir().join(format!(
+        "block-storage-bench-{}-{}",
+        name,
+        std::process::id()
+    ));
+    let db = DB::open_default(&path).expect("Failed to open bench database");
+
+    let mut bytes = 0;
+    let write_time = measure(|| {
+        for block in blocks {
+            let record = encode(block);
+            bytes += record.len();
+            db.put(&block.block_hash, record).unwrap();
+        }
+    });
+    let load_time = measure(|| {
+        for block in blocks {
+            storage::get_block(&db, &block.block_hash).unwrap().unwrap();
+        }
+    });
+
+    println!(
+        "{:<12} {:>10} bytes {:>10.2?} write {:>10.2?} load",
+        name, bytes, write_time, load_time
+    );
+
+    drop(db);
+    let _ = DB::destroy(&rocksdb::Options::default(), &path);
+}
+
+fn main() {
+    let blocks = make_blocks();
+    println!(
+        "{} blocks of {} transactions",
+        BLOCKS, TRANSACTIONS_PER_BLOCK
+    );
+
+    bench_format("json", &blocks, &|block| serde_json::to_vec(block).unwrap());
+    bench_format("binary", &blocks, &|block| {
+        codec::encode_block(block, false).unwrap()
+    });
+    bench_format("compressed", &blocks, &|block| {
+        codec::encode_block(block, true).unwrap()
+    });
+}
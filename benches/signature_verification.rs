@@ -0,0 +1,77 @@
+//! Compares the time taken to check the signatures of a 10k transaction block one by one,
+//! in parallel, and once they are in the signature cache.
+//!
+//! Run with `cargo bench --bench signature_verification`.
+
+use simple_blockchain_rs::{
+    address::Address,
+    client::{Client, Secp256k1, SignatureScheme},
+    config::Network,
+    sigcache::SignatureCache,
+    transaction::Transaction,
+};
+use std::time::{Duration, Instant};
+
+const TRANSACTIONS: usize = 10_000;
+const SENDERS: usize = 100;
+
+fn make_transactions(scheme: SignatureScheme) -> Vec<Transaction> {
+    let senders: Vec<_> = (0..SENDERS).map(|_| Client::new()).collect();
+    let receiver = Address::from_public_key(&Client::new().public_key, Network::Mainnet);
+
+    (0..TRANSACTIONS)
+        .map(|i| {
+            let sender = &senders[i % SENDERS];
+            let mut transaction =
+                Transaction::new(Some(sender.public_key.into()), receiver.into(), 1.0, None);
+            transaction.fee = 0.01;
+            transaction.nonce = Some((i / SENDERS) as u64);
+            transaction.scheme = scheme;
+            transaction.sign_transaction(sender);
+            transaction
+        })
+        .collect()
+}
+
+fn measure<F: FnMut()>(mut f: F) -> Duration {
+    let start = Instant::now();
+    f();
+    start.elapsed()
+}
+
+fn bench_scheme(scheme: SignatureScheme) {
+    let transactions = make_transactions(scheme);
+
+    // Every check used to create its own verification context
+    let fresh_contexts = measure(|| {
+        for transaction in &transactions {
+            let _secp = Secp256k1::verification_only();
+            transaction.is_valid_transaction().unwrap();
+        }
+    });
+    let sequential = measure(|| {
+        for transaction in &transactions {
+            transaction.is_valid_transaction().unwrap();
+        }
+    });
+    let cache = SignatureCache::new(TRANSACTIONS);
+    let parallel = measure(|| cache.verify_transactions(&transactions).unwrap());
+    let cached = measure(|| cache.verify_transactions(&transactions).unwrap());
+
+    println!(
+        "{:<8} {:>10.2?} fresh contexts {:>10.2?} sequential {:>10.2?} parallel {:>10.2?} cached",
+        scheme, fresh_contexts, sequential, parallel, cached
+    );
+}
+
+fn main() {
+    println!(
+        "{} transactions from {} senders on {} threads",
+        TRANSACTIONS,
+        SENDERS,
+        std::thread::available_parallelism().map_or(1, usize::from)
+    );
+
+    bench_scheme(SignatureScheme::Ecdsa);
+    bench_scheme(SignatureScheme::Schnorr);
+}
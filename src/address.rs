@@ -0,0 +1,292 @@
+use crate::{
+    client::PublicKey,
+    codec::Reader,
+    config::Network,
+    error::{Error, Result},
+    multisig::Multisig,
+};
+use bitcoin_hashes::{hash160, sha256d, Hash};
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
+use std::{fmt, str::FromStr};
+
+const BASE58_ALPHABET: &[u8; 58] = b"123456789ABCDEFGHJKLMNPQRSTUVWXYZabcdefghijkmnopqrstuvwxyz";
+const HASH_LEN: usize = 20;
+const CHECKSUM_LEN: usize = 4;
+
+/// The address of an account: the hash of its public key, or of the multisig policy
+/// spending from it, written in Base58Check after the version byte of its network.
+///
+/// `version` contains the version byte of the network the address belongs to.
+/// `hash` contains the RIPEMD160 hash of the SHA256 hash of the compressed public key or of
+/// the encoded multisig policy.
+#[derive(Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct Address {
+    version: u8,
+    hash: [u8; HASH_LEN],
+}
+
+impl Address {
+    /// This method returns the address of a public key on a network.
+    pub fn from_public_key(public_key: &PublicKey, network: Network) -> Self {
+        Self {
+            version: network.address_version(),
+            hash: hash160::Hash::hash(&public_key.serialize()).to_byte_array(),
+        }
+    }
+
+    /// This method returns the address of a multisig policy on a network.
+    pub fn from_multisig(multisig: &Multisig, network: Network) -> Self {
+        let mut policy = Vec::new();
+        multisig.encode(&mut policy);
+        Self {
+            version: network.multisig_address_version(),
+            hash: hash160::Hash::hash(&policy).to_byte_array(),
+        }
+    }
+
+    /// This method parses an address, checking it belongs to `network`.
+    pub fn parse(address: &str, network: Network) -> Result<Self> {
+        let address = Self::from_str(address)?;
+        if address.network() != Some(network) {
+            return Err(Error::Serialization(match address.network() {
+                Some(other) => format!("Address belongs to network {}", other),
+                None => String::from("Address version is unknown"),
+            }));
+        }
+        Ok(address)
+    }
+
+    /// This method parses an address, or a public key stored in its place before addresses
+    /// existed, which is converted to its address on `network`.
+    pub fn from_legacy(account: &str, network: Network) -> Result<Self> {
+        match PublicKey::from_str(account) {
+            Ok(public_key) => Ok(Self::from_public_key(&public_key, network)),
+            Err(_) => Self::from_str(account),
+        }
+    }
+
+    /// This method returns the network the address belongs to, `None` for an unknown
+    /// version byte.
+    pub fn network(&self) -> Option<Network> {
+        Network::from_address_version(self.version)
+    }
+
+    /// This method checks whether the address is the one of a multisig policy.
+    pub fn is_multisig(&self) -> bool {
+        self.network()
+            .is_some_and(|network| network.multisig_address_version() == self.version)
+    }
+
+    /// This method checks whether the address is the one of a public key, on any network.
+    pub fn is_address_of(&self, public_key: &PublicKey) -> bool {
+        !self.is_multisig()
+            && self.hash == hash160::Hash::hash(&public_key.serialize()).to_byte_array()
+    }
+
+    /// This method appends the binary encoding of the address to `buffer`.
+    pub fn encode(&self, buffer: &mut Vec<u8>) {
+        buffer.push(self.version);
+        buffer.extend(self.hash);
+    }
+
+    /// This method decodes an address written by `encode`.
+    pub fn decode(reader: &mut Reader) -> Result<Self> {
+        let version = reader.u8()?;
+        let mut hash = [0; HASH_LEN];
+        hash.copy_from_slice(reader.take(HASH_LEN)?);
+        Ok(Self { version, hash })
+    }
+}
+
+impl fmt::Display for Address {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let mut payload = Vec::with_capacity(1 + HASH_LEN + CHECKSUM_LEN);
+        payload.push(self.version);
+        payload.extend(self.hash);
+        let checksum = checksum(&payload);
+        payload.extend(checksum);
+        f.write_str(&encode_base58(&payload))
+    }
+}
+
+impl fmt::Debug for Address {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "Address({})", self)
+    }
+}
+
+impl FromStr for Address {
+    type Err = Error;
+
+    /// This method parses an address of any network, checking its checksum.
+    fn from_str(s: &str) -> Result<Self> {
+        let payload = decode_base58(s)
+            .ok_or_else(|| Error::Serialization(String::from("Address is not valid Base58")))?;
+        if payload.len() != 1 + HASH_LEN + CHECKSUM_LEN {
+            return Err(Error::Serialization(String::from(
+                "Address length is not valid",
+            )));
+        }
+        let (data, expected) = payload.split_at(1 + HASH_LEN);
+        if checksum(data) != expected {
+            return Err(Error::Serialization(String::from(
+                "Address checksum is not valid",
+            )));
+        }
+
+        let mut hash = [0; HASH_LEN];
+        hash.copy_from_slice(&data[1..]);
+        Ok(Self {
+            version: data[0],
+            hash,
+        })
+    }
+}
+
+impl Serialize for Address {
+    fn serialize<S: Serializer>(&self, serializer: S) -> std::result::Result<S::Ok, S::Error> {
+        serializer.collect_str(self)
+    }
+}
+
+impl<'de> Deserialize<'de> for Address {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> std::result::Result<Self, D::Error> {
+        let address = String::deserialize(deserializer)?;
+        Address::from_str(&address).map_err(serde::de::Error::custom)
+    }
+}
+
+/// This method returns the first bytes of the double SHA256 hash of `data`.
+fn checksum(data: &[u8]) -> [u8; CHECKSUM_LEN] {
+    let hash = sha256d::Hash::hash(data).to_byte_array();
+    let mut checksum = [0; CHECKSUM_LEN];
+    checksum.copy_from_slice(&hash[..CHECKSUM_LEN]);
+    checksum
+}
+
+/// This method encodes bytes in Base58, every leading zero byte becoming a leading `1`.
+fn encode_base58(bytes: &[u8]) -> String {
+    let zeros = bytes.iter().take_while(|byte| **byte == 0).count();
+    // Little endian base 58 digits of the bytes read as a big endian number
+    let mut digits: Vec<u8> = Vec::new();
+    for byte in &bytes[zeros..] {
+        let mut carry = *byte as u32;
+        for digit in digits.iter_mut() {
+            carry += (*digit as u32) << 8;
+            *digit = (carry % 58) as u8;
+            carry /= 58;
+        }
+        while carry > 0 {
+            digits.push((carry % 58) as u8);
+            carry /= 58;
+        }
+    }
+
+    std::iter::repeat_n('1', zeros)
+        .chain(
+            digits
+                .iter()
+                .rev()
+                .map(|digit| BASE58_ALPHABET[*digit as usize] as char),
+        )
+        .collect()
+}
+
+/// This method decodes a Base58 string, `None` when it holds a character outside of the
+/// alphabet.
+fn decode_base58(s: &str) -> Option<Vec<u8>> {
+    let zeros = s.bytes().take_while(|c| *c == b'1').count();
+    // Little endian bytes of the number
+    let mut bytes: Vec<u8> = Vec::new();
+    for c in s.bytes().skip(zeros) {
+        let mut carry = BASE58_ALPHABET.iter().position(|a| *a == c)? as u32;
+        for byte in bytes.iter_mut() {
+            carry += *byte as u32 * 58;
+            *byte = carry as u8;
+            carry >>= 8;
+        }
+        while carry > 0 {
+            bytes.push(carry as u8);
+            carry >>= 8;
+        }
+    }
+
+    let mut decoded = vec![0; zeros];
+    decoded.extend(bytes.iter().rev());
+    Some(decoded)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::codec;
+
+    /// The public key of the secret key 1.
+    const GENERATOR: &str = "0279be667ef9dcbbac55a06295ce870b07029bfcdb2dce28d959f2815b16f81798";
+
+    #[test]
+    fn base58_vectors() {
+        for (hex, base58) in [
+            ("", ""),
+            ("0000287fb4cd", "11233QC4"),
+            ("48656c6c6f20576f726c6421", "2NEpo7TZRRrLZSi2U"),
+            (
+                "00eb15231dfceb60925886b67d065299925915aeb172c06647",
+                "1NS17iag9jJgTHD1VXjvLCEnZuQ3rJDE9L",
+            ),
+        ] {
+            let bytes = codec::decode_hex(hex).unwrap();
+            assert_eq!(encode_base58(&bytes), base58);
+            assert_eq!(decode_base58(base58).unwrap(), bytes);
+        }
+        assert!(decode_base58("0OIl").is_none());
+    }
+
+    #[test]
+    fn address_of_a_public_key() {
+        let public_key = PublicKey::from_str(GENERATOR).unwrap();
+        let mainnet = Address::from_public_key(&public_key, Network::Mainnet);
+        let testnet = Address::from_public_key(&public_key, Network::Testnet);
+
+        assert_eq!(mainnet.to_string(), "1BgGZ9tcN4rm9KBzDn7KprQz87SZ26SAMH");
+        assert_eq!(testnet.to_string(), "mrCDrCybB6J1vRfbwM5hemdJz73FwDBC8r");
+        assert!(mainnet.is_address_of(&public_key));
+        assert!(!mainnet.is_multisig());
+        assert_eq!(Address::from_str(&mainnet.to_string()).unwrap(), mainnet);
+    }
+
+    #[test]
+    fn checksum_is_checked() {
+        let public_key = PublicKey::from_str(GENERATOR).unwrap();
+        let address = Address::from_public_key(&public_key, Network::Mainnet).to_string();
+        let mut typo = address.into_bytes();
+        typo[10] = if typo[10] == b'2' { b'3' } else { b'2' };
+
+        assert!(matches!(
+            Address::from_str(std::str::from_utf8(&typo).unwrap()),
+            Err(Error::Serialization(_))
+        ));
+        assert!(Address::from_str("1BgGZ9tcN4rm9KBzDn7KprQz87SZ26SA").is_err());
+    }
+
+    #[test]
+    fn network_is_checked() {
+        let public_key = PublicKey::from_str(GENERATOR).unwrap();
+        let testnet = Address::from_public_key(&public_key, Network::Testnet).to_string();
+
+        assert!(Address::parse(&testnet, Network::Testnet).is_ok());
+        assert!(matches!(
+            Address::parse(&testnet, Network::Mainnet),
+            Err(Error::Serialization(_))
+        ));
+    }
+
+    #[test]
+    fn legacy_public_key_is_converted() {
+        let public_key = PublicKey::from_str(GENERATOR).unwrap();
+        assert_eq!(
+            Address::from_legacy(GENERATOR, Network::Mainnet).unwrap(),
+            Address::from_public_key(&public_key, Network::Mainnet)
+        );
+    }
+}
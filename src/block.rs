@@ -1,4 +1,11 @@
-use super::transaction::*;
+use super::{
+    codec::{self, Reader},
+    config::{BlockLimits, Network},
+    error::{Error, Result},
+    sigcache::SignatureCache,
+    transaction::*,
+};
+use chrono::{DateTime, TimeZone, Utc};
 use serde::{Deserialize, Serialize};
 
 const DIFFICULTY_STRING: &str = "0";
@@ -12,6 +19,8 @@ pub const GENESIS_BLOCK_HASH: &str =
 /// `previous_block_hash` contains the hash of the previous block.
 /// `hash` contains the hash of the block.
 /// `verified_transactions` contains the transactions that are verified in the block.
+/// `time` contains the time the block was mined at, `None` for blocks mined before block
+/// times existed.
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct Block {
     pub index: usize,
@@ -19,6 +28,27 @@ pub struct Block {
     pub previous_block_hash: String,
     pub block_hash: String,
     pub verified_transactions: Vec<Transaction>,
+    #[serde(default)]
+    pub time: Option<DateTime<Utc>>,
+}
+
+/// The header of a block, kept after the block body has been pruned.
+///
+/// `index` contains the index of the block.
+/// `nonce` contains the nonce of the block.
+/// `previous_block_hash` contains the hash of the previous block.
+/// `block_hash` contains the hash of the block.
+/// `transaction_count` contains the number of transactions in the block.
+/// `time` contains the time the block was mined at.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct BlockHeader {
+    pub index: usize,
+    pub nonce: usize,
+    pub previous_block_hash: String,
+    pub block_hash: String,
+    pub transaction_count: usize,
+    #[serde(default)]
+    pub time: Option<DateTime<Utc>>,
 }
 
 impl Block {
@@ -30,72 +60,215 @@ impl Block {
             previous_block_hash,
             block_hash: String::new(),
             verified_transactions: vec![],
+            time: None,
         }
     }
 
-    pub fn get_block_reward(index: usize) -> f64 {
-        let block_factor = 100; /* bitcoin has block factor of 210000 */
-        50.0 / (2.0_f64.powi(index as i32 / block_factor) as f64)
+    /// This method returns the header of the block.
+    pub fn header(&self) -> BlockHeader {
+        BlockHeader {
+            index: self.index,
+            nonce: self.nonce,
+            previous_block_hash: self.previous_block_hash.clone(),
+            block_hash: self.block_hash.clone(),
+            transaction_count: self.verified_transactions.len(),
+            time: self.time,
+        }
     }
 
-    pub fn verify_block(&self) -> Result<(), &'static str> {
-        if !self.block_hash.starts_with(DIFFICULTY_STRING) {
-            return Err("Block verification failed: PoW is not valid");
+    pub fn verify_block(&self, difficulty_level: usize) -> Result<()> {
+        if !self
+            .block_hash
+            .starts_with(&DIFFICULTY_STRING.repeat(difficulty_level))
+        {
+            return Err(Error::consensus(&self.block_hash, "PoW is not valid"));
         }
 
         if self.calculate_hash() != self.block_hash {
-            return Err("Block verification failed: Block hash is not valid");
+            return Err(Error::consensus(
+                &self.block_hash,
+                "Block hash is not valid",
+            ));
         }
 
         Ok(())
     }
 
-    /// This method verifies the coinbase transaction of genesis block.
-    pub fn verify_coinbase_transaction(&self) -> Result<(), &'static str> {
-        if self.verified_transactions.len() == 0 {
-            return Err("Block verification failed: No coinbase transaction");
+    /// This method returns the size of the binary encoding of the block.
+    pub fn size(&self) -> usize {
+        let mut buffer = Vec::new();
+        self.encode(&mut buffer);
+        buffer.len()
+    }
+
+    /// This method verifies that the block is within the size and transaction count limits.
+    pub fn verify_limits(&self, limits: &BlockLimits) -> Result<()> {
+        if self.verified_transactions.len() > limits.max_transactions {
+            return Err(Error::consensus(
+                &self.block_hash,
+                "Block has too many transactions",
+            ));
         }
-        if self.verified_transactions.iter().next().unwrap().amount != Self::get_block_reward(0) {
-            return Err("Block verification failed: Coinbase transaction amount is not valid");
+        if self.size() > limits.max_bytes {
+            return Err(Error::consensus(
+                &self.block_hash,
+                "Block is larger than the size limit",
+            ));
         }
 
         Ok(())
     }
 
-    /// This method verifies the transactions inside the block.
-    pub fn has_valid_transactions(&self) -> Result<(), &'static str> {
-        if self.index == 0 {
-            return self.verify_coinbase_transaction();
+    /// This method verifies that the block starts with a coinbase transaction paying `reward`.
+    pub fn verify_coinbase_transaction(&self, reward: f64) -> Result<()> {
+        if self.verified_transactions.is_empty() {
+            return Err(Error::consensus(
+                &self.block_hash,
+                "No coinbase transaction",
+            ));
+        }
+        let coinbase = &self.verified_transactions[0];
+        if !coinbase
+            .sender
+            .as_ref()
+            .and_then(Sender::public_key)
+            .is_some_and(|sender| coinbase.receiver.is_paid_to(&sender))
+        {
+            return Err(Error::consensus(
+                &self.block_hash,
+                "Coinbase transaction must pay its signer",
+            ));
+        }
+        if coinbase.amount != reward || coinbase.fee != 0.0 {
+            return Err(Error::consensus(
+                &self.block_hash,
+                "Coinbase transaction amount is not valid",
+            ));
         }
 
-        for transaction in self.verified_transactions.iter() {
-            transaction.is_valid_transaction()?;
+        Ok(())
+    }
+
+    /// This method returns the fees paid by the transactions of the block.
+    pub fn total_fees(&self) -> f64 {
+        self.verified_transactions
+            .iter()
+            .skip(1)
+            .map(|transaction| transaction.fee)
+            .sum()
+    }
+
+    /// This method verifies the coinbase and the signatures of the transactions inside the block.
+    ///
+    /// The coinbase collects the block `reward` and the fees of the other transactions, no
+    /// transaction may be past its expiry height and every receiver address must belong to
+    /// the `network` of the chain. Signatures are checked in parallel, leaving out the
+    /// transactions the signature `cache` already found valid.
+    pub fn has_valid_transactions(
+        &self,
+        reward: f64,
+        network: Network,
+        cache: &SignatureCache,
+    ) -> Result<()> {
+        self.verify_coinbase_transaction(reward + self.total_fees())?;
+        cache
+            .verify_transactions(&self.verified_transactions)
+            .map_err(|e| e.in_block(&self.block_hash))?;
+
+        for (i, transaction) in self.verified_transactions.iter().enumerate() {
+            transaction
+                .receiver
+                .verify_network(network)
+                .map_err(|e| e.at_tx(i).in_block(&self.block_hash))?;
+            if transaction.is_expired(self.index) {
+                return Err(Error::validation("Transaction is expired")
+                    .at_tx(i)
+                    .in_block(&self.block_hash));
+            }
         }
         Ok(())
     }
 
     /// This method serializes the block into a string.
+    ///
+    /// The time is only part of the serialization when it is set, so blocks mined before
+    /// block times existed keep their hash.
     pub fn serialize_block(&self) -> String {
         let transactions = self
             .verified_transactions
             .iter()
             .fold(String::new(), |acc, t| acc + &t.serialize_transaction());
+        let time = match self.time {
+            Some(time) => format!("|{}", time),
+            None => String::new(),
+        };
 
         format!(
-            "{}{}{}{}",
-            self.index, self.nonce, self.previous_block_hash, transactions
+            "{}{}{}{}{}",
+            self.index, self.nonce, self.previous_block_hash, transactions, time
         )
     }
 
+    /// This method appends the binary encoding of the block to `buffer`.
+    ///
+    /// The time comes last and is left out for blocks without one.
+    pub fn encode(&self, buffer: &mut Vec<u8>) {
+        codec::put_u64(buffer, self.index as u64);
+        codec::put_u64(buffer, self.nonce as u64);
+        codec::put_hex(buffer, &self.previous_block_hash);
+        codec::put_hex(buffer, &self.block_hash);
+        codec::put_u32(buffer, self.verified_transactions.len() as u32);
+        for transaction in self.verified_transactions.iter() {
+            transaction.encode(buffer);
+        }
+        if let Some(time) = self.time {
+            codec::put_i64(buffer, time.timestamp());
+            codec::put_u32(buffer, time.timestamp_subsec_nanos());
+        }
+    }
+
+    /// This method decodes a block written by `encode`, whose transactions store their
+    /// signature scheme when `has_schemes` is true.
+    pub fn decode(reader: &mut Reader, has_schemes: bool) -> Result<Self> {
+        let index = reader.u64()? as usize;
+        let nonce = reader.u64()? as usize;
+        let previous_block_hash = reader.hex()?;
+        let block_hash = reader.hex()?;
+        let transaction_count = reader.u32()?;
+        let verified_transactions = (0..transaction_count)
+            .map(|_| Transaction::decode(reader, has_schemes))
+            .collect::<Result<_>>()?;
+        let time = if reader.is_empty() {
+            None
+        } else {
+            let seconds = reader.i64()?;
+            let nanoseconds = reader.u32()?;
+            Some(
+                Utc.timestamp_opt(seconds, nanoseconds)
+                    .single()
+                    .ok_or_else(|| Error::Serialization(String::from("Invalid block time")))?,
+            )
+        };
+
+        Ok(Self {
+            index,
+            nonce,
+            previous_block_hash,
+            block_hash,
+            verified_transactions,
+            time,
+        })
+    }
+
     /// This method calculates the hash of the block using SHA256.
     pub fn calculate_hash(&self) -> String {
         crypto_hash::hex_digest(
             crypto_hash::Algorithm::SHA256,
-            &self.serialize_block().as_bytes(),
+            self.serialize_block().as_bytes(),
         )
     }
 
-    pub fn mine_block(self, difficulty_level: usize) -> Result<Block, &'static str> {
+    pub fn mine_block(self, difficulty_level: usize) -> Result<Block> {
         let mut nonce = 0;
         let mut block = self.clone();
 
@@ -109,9 +282,9 @@ impl Block {
             }
 
             if nonce > 10000 {
-                return Err("Difficulty is too high! block mining failed.");
+                return Err(Error::Mining(String::from("Difficulty is too high")));
             }
-            nonce = nonce + 1;
+            nonce += 1;
             block.nonce = nonce;
         }
         Ok(block)
@@ -1,195 +1,1695 @@
-use crate::{block::*, client::*, storage, transaction::*};
+use crate::{
+    address::Address,
+    block::*,
+    blockfile::{BlockFileReader, BlockFileWriter},
+    client::*,
+    clock::{Clock, SystemClock},
+    config::{BlockchainConfig, Network},
+    error::{Error, Result},
+    hd,
+    mempool::{Expired, Mempool},
+    migration,
+    sigcache::SignatureCache,
+    snapshot::Snapshot,
+    storage,
+    transaction::*,
+    wallet::Wallet,
+};
+use chrono::{DateTime, Duration, Utc};
 use rocksdb::DB;
-use std::{fs::create_dir_all, path::Path};
+use std::{
+    collections::{hash_map::Entry, BTreeMap, HashMap, HashSet, VecDeque},
+    fs::{create_dir_all, rename, File},
+    io::{BufReader, BufWriter},
+    path::Path,
+    sync::Arc,
+};
 
-const DIFFICULTY_LEVEL: usize = 2;
-const BLOCKS_DB_PATH: &'static str = "db/_blocks";
-const BLOCKS_METADATA_DB_PATH: &'static str = "db/_blocks_metadata";
-const BALANCES_DB_PATH: &'static str = "db/_balances";
-const WALLET_PATH: &'static str = "db/_wallets";
-const DATA_DIR: &'static str = "db";
+/// The number of previous blocks whose median time a block time must exceed.
+const MEDIAN_TIME_SPAN: usize = 11;
+
+/// A transaction confirmed by a block of the chain.
+///
+/// `height` contains the height of the block.
+/// `is_coinbase` tells whether the transaction is the coinbase of the block.
+/// `transaction` contains the transaction.
+#[derive(Debug, Clone)]
+pub struct ConfirmedTransaction {
+    pub height: usize,
+    pub is_coinbase: bool,
+    pub transaction: Transaction,
+}
+
+/// The balances of an account.
+///
+/// `confirmed` contains the confirmed balance that can be spent, leaving out immature
+/// coinbase rewards.
+/// `pending` contains the change the pending transactions bring to the balance.
+/// `immature` contains the coinbase rewards that cannot be spent yet.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct AccountBalances {
+    pub confirmed: f64,
+    pub pending: f64,
+    pub immature: f64,
+}
+
+/// A transaction sending from or paying to an account.
+///
+/// `height` contains the height of the confirming block, `None` while it is pending.
+/// `confirmations` contains the number of blocks from the confirming block to the tip,
+/// 0 while it is pending.
+/// `is_coinbase` tells whether the transaction is the coinbase of its block.
+/// `amount` contains the change the transaction brings to the balance of the account.
+/// `transaction` contains the transaction.
+#[derive(Debug, Clone)]
+pub struct WalletTransaction {
+    pub height: Option<usize>,
+    pub confirmations: usize,
+    pub is_coinbase: bool,
+    pub amount: f64,
+    pub transaction: Transaction,
+}
+
+/// The reason a pending payment of the wallet left the mempool without being confirmed.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DropReason {
+    Expired,
+    ParentExpired,
+    Replaced,
+    Evicted,
+}
+
+/// A pending payment of the wallet that left the mempool without being confirmed.
+///
+/// `reason` contains the reason it was dropped.
+/// `transaction` contains the payment.
+#[derive(Debug, Clone)]
+pub struct DroppedPayment {
+    pub reason: DropReason,
+    pub transaction: Transaction,
+}
 
 /// A blockchain is a collection of blocks.
 pub struct Blockchain {
+    pub config: BlockchainConfig,
     pub blocks: DB,
     pub block_metadata: DB,
     pub balances: DB,
-    pub mempool: Vec<Transaction>,
-    pub client: Client,
+    pub mempool: Mempool,
+    pub wallet: Wallet,
+    signature_cache: SignatureCache,
+    clock: Arc<dyn Clock>,
+    dropped_payments: Vec<DroppedPayment>,
 }
 
 impl Blockchain {
-    /// This method creates a new blockchain.
-    pub fn new() -> Self {
-        create_dir_all(DATA_DIR).unwrap();
+    /// This method creates a new blockchain stored under the configured data directory,
+    /// upgrading the stores written by older versions.
+    pub fn new(config: BlockchainConfig) -> Result<Self> {
+        Self::with_clock(config, Arc::new(SystemClock))
+    }
 
-        Blockchain {
-            blocks: DB::open_default(BLOCKS_DB_PATH).unwrap(),
-            block_metadata: DB::open_default(BLOCKS_METADATA_DB_PATH).unwrap(),
-            balances: DB::open_default(BALANCES_DB_PATH).unwrap(),
-            mempool: Vec::new(),
-            client: Blockchain::create_client().expect("Failed to create client"),
+    /// This method creates a new blockchain reading the current time from `clock`.
+    /// Pruning needs the snapshots the chain base is moved to, so a prune depth without a
+    /// snapshot interval is refused.
+    pub fn with_clock(config: BlockchainConfig, clock: Arc<dyn Clock>) -> Result<Self> {
+        if config.prune_depth.is_some() && config.snapshot_interval.is_none() {
+            return Err(Error::Storage(String::from(
+                "Pruning requires a snapshot interval",
+            )));
         }
+        migration::migrate_legacy_layout(&config)?;
+        create_dir_all(config.chain_dir())?;
+
+        let mut blockchain = Blockchain {
+            blocks: DB::open_default(config.blocks_path())?,
+            block_metadata: DB::open_default(config.blocks_metadata_path())?,
+            balances: DB::open_default(config.balances_path())?,
+            mempool: Mempool::new(config.mempool.clone()),
+            wallet: Wallet::open(&config.wallet_path())?,
+            signature_cache: SignatureCache::new(config.signature_cache_size),
+            config,
+            clock,
+            dropped_payments: Vec::new(),
+        };
+        migration::migrate(&mut blockchain)?;
+        blockchain.load_mempool()?;
+
+        Ok(blockchain)
     }
 
-    pub fn create_client() -> Result<Client, &'static str> {
-        if Path::new(WALLET_PATH).exists() {
-            let key = std::fs::read_to_string(WALLET_PATH).unwrap();
-            Ok(Client::from(key).unwrap())
-        } else {
-            let client = Client::new();
-            std::fs::write(WALLET_PATH, client.identify()).unwrap();
-            Ok(client)
-        }
+    /// This method creates a transaction signed by a key of the wallet, timestamped by the
+    /// clock. The wallet must be unlocked.
+    fn wallet_transaction(
+        &self,
+        sender: &PublicKey,
+        receiver: Address,
+        amount: f64,
+        fee: f64,
+        nonce: Option<u64>,
+        expiry_height: Option<usize>,
+    ) -> Result<Transaction> {
+        let client = self.wallet.key_client(sender)?;
+        let mut transaction = Transaction::new(
+            Some(client.public_key.into()),
+            receiver.into(),
+            amount,
+            None,
+        );
+        transaction.fee = fee;
+        transaction.nonce = nonce;
+        transaction.expiry_height = expiry_height;
+        transaction.scheme = self.config.signature_scheme;
+        transaction.set_time(self.clock.now());
+        transaction.sign_transaction(client);
+        Ok(transaction)
     }
 
-    pub fn coinbase_transaction(&self) -> Transaction {
-        Transaction::signed_new(
-            &self.client,
-            self.client.public_key,
-            Block::get_block_reward(
-                storage::get_latest_block_number(&self.block_metadata).unwrap() + 1,
-            ),
-        )
+    /// This method creates a coinbase transaction paying `reward` to the address of the
+    /// current receive key of the coinbase account of the wallet, signed by that key.
+    pub fn coinbase_transaction(&self, reward: f64) -> Result<Transaction> {
+        let receiver = self
+            .wallet
+            .account_public_key(self.wallet.coinbase_account())?;
+        self.wallet_transaction(&receiver, self.address(&receiver), reward, 0.0, None, None)
     }
 
     /// This method generates genesis block.
-    pub fn genesis_block(&self) -> Block {
-        let mut genesis_block = Block::new(0, String::from(GENESIS_BLOCK_HASH));
+    pub fn genesis_block(&self) -> Result<Block> {
+        let genesis = &self.config.genesis;
+        let mut genesis_block = Block::new(0, genesis.previous_block_hash.clone());
+        genesis_block.time = Some(self.clock.now());
 
         genesis_block
             .verified_transactions
-            .push(self.coinbase_transaction());
+            .push(self.coinbase_transaction(genesis.reward)?);
 
-        genesis_block.block_hash = String::from(GENESIS_BLOCK_HASH);
-        genesis_block
+        genesis_block.block_hash = genesis.previous_block_hash.clone();
+        Ok(genesis_block)
     }
 
-    fn get_latest_block(&self) -> Result<Option<Block>, &'static str> {
+    fn get_latest_block(&self) -> Result<Option<Block>> {
         let block_hash = match storage::get_latest_block_hash(&self.block_metadata)? {
             Some(block_hash) => block_hash,
             None => return Ok(None),
         };
-        let block = storage::get_block(&self.blocks, &block_hash)?;
-        return Ok(block);
+        storage::get_block(&self.blocks, &block_hash)
     }
 
-    fn verify_transaction(&self, transaction: &Transaction) -> Result<(), &'static str> {
-        transaction.is_valid_transaction()?;
+    /// This method returns the block of the chain at the given height.
+    pub fn get_block_at_height(&self, height: usize) -> Result<Option<Block>> {
+        match storage::get_block_hash(&self.block_metadata, height)? {
+            Some(block_hash) => storage::get_block(&self.blocks, &block_hash),
+            None => Ok(None),
+        }
+    }
 
-        let balance = storage::get_balance(&self.balances, transaction.sender.unwrap())?;
-        if balance.unwrap_or(0.0) < transaction.amount {
-            return Err("Transaction verification failed: Insufficient funds");
+    /// This method returns the height of the next block.
+    fn next_height(&self) -> Result<usize> {
+        Ok(storage::get_latest_block_number(&self.block_metadata)? + 1)
+    }
+
+    /// This method returns the address of a public key on the network of the chain.
+    pub fn address(&self, public_key: &PublicKey) -> Address {
+        Address::from_public_key(public_key, self.config.network)
+    }
+
+    /// This method returns the coinbase rewards that cannot be spent yet by the block at
+    /// `height`, by receiver, leaving out the reward of that block itself.
+    ///
+    /// The genesis reward can never be disconnected and is always mature.
+    fn immature_balances_at(&self, height: usize) -> Result<Balances> {
+        let first_immature = (height + 1)
+            .saturating_sub(self.config.coinbase_maturity)
+            .max(1);
+        let mut immature = Balances::new();
+        for coinbase_height in first_immature..height {
+            if let Some((receiver, amount)) =
+                storage::get_coinbase(&self.block_metadata, coinbase_height)?
+            {
+                *immature.entry(receiver).or_insert(0.0) += amount;
+            }
         }
+        Ok(immature)
+    }
 
+    /// This method returns the coinbase rewards that cannot be spent yet by the next block,
+    /// by receiver.
+    pub fn immature_balances(&self) -> Result<HashMap<Address, f64>> {
+        self.immature_balances_at(self.next_height()?)
+    }
+
+    /// This method returns the balance of an account that can be spent by the next block.
+    fn spendable_balance(&self, address: Address) -> Result<f64> {
+        let balance = storage::get_balance(&self.balances, address)?.unwrap_or(0.0);
+        let immature = self.immature_balances()?;
+        Ok(balance - immature.get(&address).copied().unwrap_or(0.0))
+    }
+
+    /// This method validates a transaction and adds it to the mempool, checking it against
+    /// the confirmed balance and nonce of the sender and its pending transactions.
+    ///
+    /// Coinbase rewards that are not mature yet cannot be spent, and every transaction must
+    /// have a nonce so it cannot be replayed. Transactions older than the mempool keeps are
+    /// refused rather than evicted right away. Valid signatures are remembered so they are
+    /// not checked again when the block including them arrives.
+    fn add_to_mempool(&mut self, transaction: Transaction) -> Result<()> {
+        self.signature_cache.verify(&transaction)?;
+        if transaction.nonce.is_none() {
+            return Err(Error::validation("Transaction must have a nonce"));
+        }
+        let next_height = self.next_height()?;
+        if transaction.is_expired(next_height) {
+            return Err(Error::validation("Transaction is expired"));
+        }
+
+        let sender = transaction
+            .sender
+            .as_ref()
+            .ok_or_else(|| Error::validation("No sender found"))?;
+        transaction.receiver.verify_network(self.config.network)?;
+        let address = sender.address(self.config.network);
+        let balance = self.spendable_balance(address)?;
+        if balance < transaction.total_spent() {
+            let total_balance = storage::get_balance(&self.balances, address)?.unwrap_or(0.0);
+            if total_balance >= transaction.total_spent() {
+                return Err(Error::validation("Coinbase reward is not mature"));
+            }
+            return Err(Error::validation("Insufficient funds"));
+        }
+        let nonce = storage::get_nonce(&self.block_metadata, sender)?;
+
+        if transaction.time() > self.clock.now() + self.max_future_drift() {
+            return Err(Error::validation(
+                "Transaction time is too far in the future",
+            ));
+        }
+        let max_age = Duration::from_std(self.config.mempool.max_age).unwrap_or(Duration::MAX);
+        if self.clock.now() - transaction.time() > max_age {
+            return Err(Error::validation(
+                "Transaction is older than the mempool keeps",
+            ));
+        }
+
+        let expired = self.mempool.evict_expired(self.clock.now());
+        self.notify_expired(expired);
+        let expired = self.mempool.remove_expired(next_height);
+        self.notify_expired(expired);
+        let displaced = self.mempool.insert(transaction, balance, nonce)?;
+        self.notify_dropped(
+            displaced.replaced.into_iter().collect(),
+            DropReason::Replaced,
+        );
+        self.notify_dropped(displaced.evicted, DropReason::Evicted);
         Ok(())
     }
 
+    /// This method records the payments of the wallet among transactions dropped from the
+    /// mempool for `reason`.
+    fn notify_dropped(&mut self, transactions: Vec<Transaction>, reason: DropReason) {
+        let wallet = &self.wallet;
+        self.dropped_payments.extend(
+            transactions
+                .into_iter()
+                .filter(|transaction| {
+                    transaction
+                        .sender
+                        .as_ref()
+                        .and_then(Sender::public_key)
+                        .is_some_and(|sender| wallet.account_of(&sender).is_some())
+                })
+                .map(|transaction| DroppedPayment {
+                    reason,
+                    transaction,
+                }),
+        );
+    }
+
+    /// This method records the payments of the wallet among expired transactions and the
+    /// transactions removed with them.
+    fn notify_expired(&mut self, expired: Expired) {
+        self.notify_dropped(expired.expired, DropReason::Expired);
+        self.notify_dropped(expired.dependents, DropReason::ParentExpired);
+    }
+
+    /// This method returns the payments of the wallet that left the mempool without being
+    /// confirmed since the last call.
+    pub fn take_dropped_payments(&mut self) -> Vec<DroppedPayment> {
+        std::mem::take(&mut self.dropped_payments)
+    }
+
+    /// This method returns the nonce the next transaction of a sender must use, taking its
+    /// pending transactions into account.
+    pub fn next_nonce(&self, sender: &Sender) -> Result<u64> {
+        let nonce = storage::get_nonce(&self.block_metadata, sender)?;
+        Ok(self.mempool.next_nonce(sender, nonce))
+    }
+
+    /// This method creates a payment from the active account of the wallet and adds it to
+    /// the mempool.
+    ///
+    /// A payment with an expiry height can only be confirmed up to that height.
     pub fn send_transaction(
         &mut self,
-        public_key: PublicKey,
+        address: Address,
         amount: f64,
-    ) -> Result<Transaction, &'static str> {
-        let tx = Transaction::signed_new(&self.client, public_key, amount);
-        self.verify_transaction(&tx)?;
-        self.mempool.push(tx.clone());
-        return Ok(tx);
+        fee: f64,
+        expiry_height: Option<usize>,
+    ) -> Result<Transaction> {
+        let account = self.wallet.active_account().to_string();
+        self.send_transaction_from(&account, address, amount, fee, expiry_height)
     }
 
-    fn process_block_transactions(&mut self, block: &Block) -> Result<(), &'static str> {
-        for (i, transaction) in block.verified_transactions.iter().enumerate() {
-            let empty: f64 = 0.0;
-            // i = 0 => Skip when coinbase transaction
-            // Process: Sender => Receiver (Deduct amount from balance)
-            if i > 0 {
-                if let Some(sender_balance) =
-                    storage::get_balance(&self.balances, transaction.sender.unwrap())
-                        .unwrap_or(Some(empty))
+    /// This method creates a payment from an account of the wallet and adds it to the
+    /// mempool.
+    pub fn send_transaction_from(
+        &mut self,
+        account: &str,
+        address: Address,
+        amount: f64,
+        fee: f64,
+        expiry_height: Option<usize>,
+    ) -> Result<Transaction> {
+        let sender = self.paying_key(account, amount + fee)?;
+        let nonce = self.next_nonce(&sender.into())?;
+        self.submit_transaction(&sender, address, amount, fee, Some(nonce), expiry_height)
+    }
+
+    /// This method returns the balance of every key or address of an account that can be
+    /// spent by the next block, leaving out its pending transactions.
+    pub fn account_balance(&self, account: &str) -> Result<f64> {
+        let immature = self.immature_balances()?;
+        let mut balance = 0.0;
+        for address in self
+            .wallet
+            .account_addresses(account, self.config.network)?
+        {
+            balance += self.available_balance(address, &immature)?;
+        }
+        Ok(balance)
+    }
+
+    /// This method returns the balance of an address that can be spent by the next block,
+    /// leaving out its pending transactions.
+    fn available_balance(&self, address: Address, immature: &Balances) -> Result<f64> {
+        let balance = storage::get_balance(&self.balances, address)?.unwrap_or(0.0);
+        Ok(balance
+            - immature.get(&address).copied().unwrap_or(0.0)
+            - self
+                .mempool
+                .address_pending_spend(address, self.config.network))
+    }
+
+    /// This method returns the confirmed transactions debiting or crediting an address,
+    /// oldest first. Transactions of pruned blocks are left out.
+    pub fn address_transactions(&self, address: Address) -> Result<Vec<ConfirmedTransaction>> {
+        let mut transactions = Vec::new();
+        for (height, txid) in storage::get_address_transactions(&self.block_metadata, address)? {
+            let block = match self.get_block_at_height(height)? {
+                Some(block) => block,
+                None => continue,
+            };
+            transactions.extend(
+                block
+                    .verified_transactions
+                    .into_iter()
+                    .enumerate()
+                    .filter(|(_, transaction)| transaction.txid() == txid)
+                    .map(|(i, transaction)| ConfirmedTransaction {
+                        height,
+                        is_coinbase: i == 0,
+                        transaction,
+                    }),
+            );
+        }
+        Ok(transactions)
+    }
+
+    /// This method returns the confirmed payments received by the keys or addresses of an
+    /// account, oldest first: mining rewards and payments from anyone else.
+    pub fn received_payments(&self, account: &str) -> Result<Vec<ConfirmedTransaction>> {
+        let network = self.config.network;
+        let addresses = self.wallet.account_addresses(account, network)?;
+        let mut payments = Vec::new();
+        for address in &addresses {
+            for confirmed in self.address_transactions(*address)? {
+                let transaction = &confirmed.transaction;
+                let from_account = transaction
+                    .sender
+                    .as_ref()
+                    .is_some_and(|sender| addresses.contains(&sender.address(network)));
+                if transaction.receiver.address(network) == *address
+                    && (confirmed.is_coinbase || !from_account)
                 {
-                    storage::set_balance(
-                        &self.balances,
-                        transaction.sender.unwrap(),
-                        sender_balance - transaction.amount,
-                    )?;
-                } else {
-                    return Err("Transaction verification failed: Sender balance not found");
+                    payments.push(confirmed);
                 }
             }
+        }
+        payments.sort_by_key(|confirmed| confirmed.height);
+        Ok(payments)
+    }
+
+    /// This method returns the confirmed, pending and immature balances of the keys or
+    /// addresses of an account.
+    pub fn account_balances(&self, account: &str) -> Result<AccountBalances> {
+        let network = self.config.network;
+        let addresses = self.wallet.account_addresses(account, network)?;
+        let immature = self.immature_balances()?;
+        let mut balances = AccountBalances::default();
+        for address in &addresses {
+            let balance = storage::get_balance(&self.balances, *address)?.unwrap_or(0.0);
+            let address_immature = immature.get(address).copied().unwrap_or(0.0);
+            balances.confirmed += balance - address_immature;
+            balances.immature += address_immature;
+        }
+        balances.pending = self
+            .mempool
+            .transactions()
+            .iter()
+            .fold(0.0, |pending, transaction| {
+                pending + account_change(transaction, false, &addresses, network)
+            });
+        Ok(balances)
+    }
+
+    /// This method returns the transactions sending from or paying to the keys or addresses
+    /// of an account: the confirmed ones oldest first, then the pending ones.
+    ///
+    /// Transactions of pruned blocks are left out.
+    pub fn account_transactions(&self, account: &str) -> Result<Vec<WalletTransaction>> {
+        let network = self.config.network;
+        let addresses = self.wallet.account_addresses(account, network)?;
+        let tip_height = storage::get_latest_block_number(&self.block_metadata)?;
+        let mut confirmed = Vec::new();
+        for address in &addresses {
+            confirmed.extend(self.address_transactions(*address)?);
+        }
+        confirmed.sort_by_key(|confirmed| confirmed.height);
+
+        let mut seen = HashSet::new();
+        let mut transactions: Vec<_> = confirmed
+            .into_iter()
+            .filter(|confirmed| seen.insert(confirmed.transaction.txid()))
+            .map(|confirmed| WalletTransaction {
+                height: Some(confirmed.height),
+                confirmations: tip_height + 1 - confirmed.height,
+                is_coinbase: confirmed.is_coinbase,
+                amount: account_change(
+                    &confirmed.transaction,
+                    confirmed.is_coinbase,
+                    &addresses,
+                    network,
+                ),
+                transaction: confirmed.transaction,
+            })
+            .collect();
+        transactions.extend(
+            self.mempool
+                .transactions()
+                .into_iter()
+                .filter(|transaction| {
+                    addresses.contains(&transaction.receiver.address(network))
+                        || transaction
+                            .sender
+                            .as_ref()
+                            .is_some_and(|sender| addresses.contains(&sender.address(network)))
+                })
+                .map(|transaction| WalletTransaction {
+                    height: None,
+                    confirmations: 0,
+                    is_coinbase: false,
+                    amount: account_change(&transaction, false, &addresses, network),
+                    transaction,
+                }),
+        );
+        Ok(transactions)
+    }
+
+    /// This method replaces the wallet with the one of a mnemonic, protected by
+    /// `passphrase`, and returns the number of its accounts.
+    ///
+    /// The chain state is scanned for the keys of the restored accounts: every key that
+    /// holds a balance or sent a transaction is restored along with its balance.
+    pub fn restore_wallet(&mut self, mnemonic: &str, passphrase: &str) -> Result<usize> {
+        let mnemonic = hd::parse_mnemonic(mnemonic)?;
+        let balances = storage::get_balances(&self.balances)?;
+        let nonces = storage::get_nonces(&self.block_metadata)?;
+        let network = self.config.network;
+        self.wallet = Wallet::restore(&self.config.wallet_path(), &mnemonic, passphrase, |key| {
+            balances.contains_key(&Address::from_public_key(key, network))
+                || nonces.contains_key(&(*key).into())
+        })?;
+        Ok(self.wallet.accounts().count())
+    }
+
+    /// This method chooses the key of an account paying `total`.
+    ///
+    /// Each key has a balance of its own, so the payment comes from the oldest key able to
+    /// pay it alone, or from the richest key when none can, to report why it cannot.
+    fn paying_key(&self, account: &str, total: f64) -> Result<PublicKey> {
+        let immature = self.immature_balances()?;
+        let mut richest = None;
+        for key in self.wallet.account_keys(account)? {
+            let balance = self.available_balance(self.address(key), &immature)?;
+            if balance >= total {
+                return Ok(*key);
+            }
+            if richest.is_none_or(|(_, richest_balance)| balance > richest_balance) {
+                richest = Some((*key, balance));
+            }
+        }
+        richest.map(|(key, _)| key).ok_or_else(|| {
+            Error::Wallet(format!("No key of account {} can pay {}", account, total))
+        })
+    }
+
+    /// This method signs a transaction from a key of the wallet and adds it to the mempool.
+    fn submit_transaction(
+        &mut self,
+        sender: &PublicKey,
+        receiver: Address,
+        amount: f64,
+        fee: f64,
+        nonce: Option<u64>,
+        expiry_height: Option<usize>,
+    ) -> Result<Transaction> {
+        let tx = self.wallet_transaction(sender, receiver, amount, fee, nonce, expiry_height)?;
+        self.add_to_mempool(tx.clone())?;
+        self.save_mempool()?;
+        Ok(tx)
+    }
 
-            // Process: Receiver <= Sender (Insert amount into balance)
-            if let Some(receiver_balance) =
-                storage::get_balance(&self.balances, transaction.receiver).unwrap_or(Some(empty))
+    /// This method creates a transaction from any sender without signing it, so it can be
+    /// signed offline by the keys of the sender. It uses the next nonce of the sender and
+    /// must be affordable by its spendable balance after its pending transactions.
+    pub fn unsigned_transaction(
+        &self,
+        sender: Sender,
+        receiver: Address,
+        amount: f64,
+        fee: f64,
+        expiry_height: Option<usize>,
+    ) -> Result<Transaction> {
+        let available = self.spendable_balance(sender.address(self.config.network))?
+            - self.mempool.pending_spend(&sender);
+        if available < amount + fee {
+            return Err(Error::validation("Insufficient funds"));
+        }
+
+        let nonce = self.next_nonce(&sender)?;
+        let mut transaction = Transaction::new(Some(sender), receiver.into(), amount, None);
+        transaction.fee = fee;
+        transaction.nonce = Some(nonce);
+        transaction.expiry_height = expiry_height;
+        transaction.scheme = self.config.signature_scheme;
+        transaction.set_time(self.clock.now());
+        Ok(transaction)
+    }
+
+    /// This method adds a transaction signed outside of the node to the mempool.
+    pub fn broadcast_transaction(&mut self, transaction: Transaction) -> Result<()> {
+        self.add_to_mempool(transaction)?;
+        self.save_mempool()
+    }
+
+    /// This method replaces a pending transaction of the wallet with one paying a higher fee.
+    ///
+    /// The fee is raised by the minimum fee bump of the mempool unless `fee` is given.
+    pub fn bump_fee(&mut self, txid: &str, fee: Option<f64>) -> Result<Transaction> {
+        let (sender, pending) = self.pending_wallet_transaction(txid)?;
+        let fee = fee.unwrap_or(pending.fee + self.mempool.limits().min_fee_bump);
+        self.submit_transaction(
+            &sender,
+            pending.receiver.address(self.config.network),
+            pending.amount,
+            fee,
+            pending.nonce,
+            pending.expiry_height,
+        )
+    }
+
+    /// This method cancels a pending transaction of the wallet by replacing it with a
+    /// transaction paying nothing back to the wallet, with a fee raised by the minimum
+    /// fee bump of the mempool.
+    pub fn cancel_transaction(&mut self, txid: &str) -> Result<Transaction> {
+        let (sender, pending) = self.pending_wallet_transaction(txid)?;
+        let fee = pending.fee + self.mempool.limits().min_fee_bump;
+        self.submit_transaction(
+            &sender,
+            self.address(&sender),
+            0.0,
+            fee,
+            pending.nonce,
+            pending.expiry_height,
+        )
+    }
+
+    /// This method returns a pending transaction sent by the wallet, with the key that sent
+    /// it.
+    fn pending_wallet_transaction(&self, txid: &str) -> Result<(PublicKey, Transaction)> {
+        let pending = self
+            .mempool
+            .get(txid)
+            .ok_or_else(|| Error::validation("Transaction not found in the mempool"))?;
+        let sender = pending
+            .sender
+            .as_ref()
+            .and_then(Sender::public_key)
+            .filter(|sender| self.wallet.account_of(sender).is_some())
+            .ok_or_else(|| {
+                Error::validation("Only transactions sent by this wallet can be replaced")
+            })?;
+        if pending.nonce.is_none() {
+            return Err(Error::validation(
+                "Transactions without a nonce cannot be replaced",
+            ));
+        }
+
+        Ok((sender, pending.clone()))
+    }
+
+    /// This method reloads the mempool saved by a previous run.
+    ///
+    /// Every transaction is validated again against the current state, the ones that
+    /// were confirmed in the meantime or are no longer valid are dropped.
+    fn load_mempool(&mut self) -> Result<()> {
+        let path = self.config.mempool_path();
+        if !path.exists() {
+            return Ok(());
+        }
+
+        let transactions: Vec<Transaction> = serde_json::from_slice(&std::fs::read(path)?)?;
+        for transaction in transactions {
+            if storage::get_transaction_block(&self.block_metadata, &transaction.txid())?.is_none()
             {
-                storage::set_balance(
-                    &self.balances,
-                    transaction.receiver,
-                    receiver_balance + transaction.amount,
-                )?;
-            } else {
-                storage::set_balance(&self.balances, transaction.receiver, transaction.amount)?;
+                self.return_to_mempool(transaction)?;
+            }
+        }
+
+        self.save_mempool()
+    }
+
+    /// This method adds a transaction that was valid before back to the mempool, dropping
+    /// it when it is no longer valid.
+    fn return_to_mempool(&mut self, transaction: Transaction) -> Result<()> {
+        if transaction.is_expired(self.next_height()?) {
+            self.notify_dropped(vec![transaction], DropReason::Expired);
+            return Ok(());
+        }
+
+        match self.add_to_mempool(transaction) {
+            Ok(()) | Err(Error::Validation { .. }) => Ok(()),
+            Err(e) => Err(e),
+        }
+    }
+
+    /// This method saves the mempool so it survives a restart.
+    fn save_mempool(&self) -> Result<()> {
+        let path = self.config.mempool_path();
+        let temporary_path = path.with_extension("json.tmp");
+        std::fs::write(
+            &temporary_path,
+            serde_json::to_vec(&self.mempool.transactions())?,
+        )?;
+        rename(temporary_path, path)?;
+        Ok(())
+    }
+
+    /// This method removes the transactions confirmed by a block from the mempool, the
+    /// pending transactions of its senders that conflict with them or that the senders
+    /// can no longer afford, and the transactions that expired with the block.
+    fn remove_confirmed_transactions(&mut self, block: &Block) -> Result<()> {
+        let mut removed = self.mempool.remove_block_transactions(block);
+
+        let senders: HashSet<_> = block
+            .verified_transactions
+            .iter()
+            .filter_map(|transaction| transaction.sender.clone())
+            .collect();
+        for sender in senders {
+            let nonce = storage::get_nonce(&self.block_metadata, &sender)?;
+            removed += self.mempool.remove_used_nonces(&sender, nonce).len();
+            let balance = self.spendable_balance(sender.address(self.config.network))?;
+            removed += self.mempool.trim_sender(&sender, balance).len();
+        }
+        let expired = self.mempool.remove_expired(block.index + 1);
+        removed += expired.expired.len() + expired.dependents.len();
+        self.notify_expired(expired);
+
+        if removed > 0 {
+            self.save_mempool()?;
+        }
+        Ok(())
+    }
+
+    /// This method applies the transactions of a block to the stored balances, their sum
+    /// and the nonces, and keeps the balances they replaced as undo data.
+    ///
+    /// The nonces replaced by a block need no undo data: they are the lowest nonce each
+    /// sender used in the block. The coinbase reward of the block is recorded so it can
+    /// mature. Blocks below the maturity height, accepted before the maturity rule existed,
+    /// may spend immature rewards.
+    fn process_block_transactions(&mut self, block: &Block) -> Result<()> {
+        let mut previous_balances = HashMap::new();
+        let mut nonces = HashMap::new();
+        for transaction in block.verified_transactions.iter() {
+            let sender = transaction
+                .sender
+                .as_ref()
+                .map(|sender| sender.address(self.config.network));
+            let receiver = transaction.receiver.address(self.config.network);
+            for address in sender.into_iter().chain([receiver]) {
+                if let Entry::Vacant(entry) = previous_balances.entry(address) {
+                    entry.insert(storage::get_balance(&self.balances, address)?);
+                }
             }
+            if let (Some(sender), Some(_)) = (&transaction.sender, transaction.nonce) {
+                if let Entry::Vacant(entry) = nonces.entry(sender.clone()) {
+                    entry.insert(storage::get_nonce(&self.block_metadata, sender)?);
+                }
+            }
+        }
+
+        let mut balances = previous_balances
+            .iter()
+            .filter_map(|(address, balance)| balance.map(|balance| (*address, balance)))
+            .collect();
+        let network = self.config.network;
+        let immature = if block.index < storage::get_maturity_height(&self.block_metadata)? {
+            Balances::new()
+        } else {
+            let mut immature = self.immature_balances_at(block.index)?;
+            if self.config.coinbase_maturity > 0 {
+                if let Some((receiver, amount)) = coinbase_credit(block, network) {
+                    *immature.entry(receiver).or_insert(0.0) += amount;
+                }
+            }
+            immature
+        };
+        apply_block_transactions(&mut balances, &mut nonces, &immature, block, network)?;
+        let total = self.total_balance()? - previous_balances.values().flatten().sum::<f64>()
+            + balances.values().sum::<f64>();
+        check_supply(total, self.config.supply(block.index), &block.block_hash)?;
+
+        for (address, balance) in balances {
+            storage::set_balance(&self.balances, address, balance)?;
+        }
+        for (sender, nonce) in nonces {
+            storage::set_nonce(&self.block_metadata, &sender, nonce)?;
+        }
+        storage::set_total_balance(&self.block_metadata, total)?;
+        let undo: Vec<_> = previous_balances.into_iter().collect();
+        storage::set_undo(&self.block_metadata, &block.block_hash, &undo)?;
+        if let Some((receiver, amount)) = coinbase_credit(block, network) {
+            storage::set_coinbase(&self.block_metadata, block.index, receiver, amount)?;
         }
 
         Ok(())
     }
 
-    fn validate_last_block(&self, block: &Block) -> Result<(), &'static str> {
+    /// This method returns the sum of the stored balances, adding them up once for
+    /// databases written before the sum was recorded.
+    fn total_balance(&self) -> Result<f64> {
+        match storage::get_total_balance(&self.block_metadata)? {
+            Some(total) => Ok(total),
+            None => Ok(storage::get_balances(&self.balances)?.values().sum()),
+        }
+    }
+
+    fn validate_last_block(&self, block: &Block) -> Result<()> {
         let previous_block = self.get_latest_block()?;
-        let previous_block_hash =
-            previous_block.map_or(GENESIS_BLOCK_HASH.to_string(), |b| b.block_hash.clone());
+        let (previous_block_hash, index) = previous_block
+            .map_or((self.config.genesis.previous_block_hash.clone(), 0), |b| {
+                (b.block_hash, b.index + 1)
+            });
 
+        if block.index != index {
+            return Err(Error::consensus(
+                &block.block_hash,
+                "Block index is not valid",
+            ));
+        }
         if block.previous_block_hash != previous_block_hash {
-            return Err("Block verification: Must reference previous block's hash");
+            return Err(Error::consensus(
+                &block.block_hash,
+                "Must reference previous block's hash",
+            ));
+        }
+
+        Ok(())
+    }
+
+    fn max_future_drift(&self) -> Duration {
+        Duration::from_std(self.config.max_future_drift).unwrap_or(Duration::MAX)
+    }
+
+    /// This method returns the header of the block of the chain at the given height.
+    fn get_header_at_height(&self, height: usize) -> Result<Option<BlockHeader>> {
+        match storage::get_block_hash(&self.block_metadata, height)? {
+            Some(block_hash) => storage::get_header(&self.block_metadata, &block_hash),
+            None => Ok(None),
+        }
+    }
+
+    /// This method returns the median time of the blocks preceding the given height, `None`
+    /// when none of them has a time.
+    fn median_time_past(&self, height: usize) -> Result<Option<DateTime<Utc>>> {
+        let mut times = Vec::new();
+        for height in height.saturating_sub(MEDIAN_TIME_SPAN)..height {
+            if let Some(time) = self.get_header_at_height(height)?.and_then(|h| h.time) {
+                times.push(time);
+            }
+        }
+
+        times.sort();
+        Ok(times.get(times.len() / 2).copied())
+    }
+
+    /// This method checks the time of a block against the blocks before it and the clock,
+    /// and the times of its transactions against the time of the block.
+    ///
+    /// Once a block has a time, every block after it must have one too.
+    fn validate_block_time(&self, block: &Block) -> Result<()> {
+        let time = match block.time {
+            Some(time) => time,
+            None => {
+                let parent_time = match block.index.checked_sub(1) {
+                    Some(height) => self.get_header_at_height(height)?.and_then(|h| h.time),
+                    None => None,
+                };
+                if parent_time.is_some() {
+                    return Err(Error::consensus(&block.block_hash, "Block time is missing"));
+                }
+                return Ok(());
+            }
+        };
+
+        if time > self.clock.now() + self.max_future_drift() {
+            return Err(Error::consensus(
+                &block.block_hash,
+                "Block time is too far in the future",
+            ));
+        }
+        if let Some(median_time) = self.median_time_past(block.index)? {
+            if time <= median_time {
+                return Err(Error::consensus(
+                    &block.block_hash,
+                    "Block time must be after the median time of the previous blocks",
+                ));
+            }
+        }
+        for (i, transaction) in block.verified_transactions.iter().enumerate() {
+            if transaction.time() > time + self.max_future_drift() {
+                return Err(
+                    Error::validation("Transaction time is after the block time")
+                        .at_tx(i)
+                        .in_block(&block.block_hash),
+                );
+            }
         }
 
         Ok(())
     }
 
+    /// This method returns the reward the coinbase of the block at `height` must pay.
+    /// Blocks below `maturity_height` were mined before the emission schedule and paid
+    /// the reward of the next height, which they keep being checked against.
+    fn expected_reward(&self, height: usize, maturity_height: usize) -> f64 {
+        if height > 0 && height < maturity_height {
+            self.config.block_reward(height + 1)
+        } else {
+            self.config.block_reward(height)
+        }
+    }
+
     /// This method process a block in blockchain
-    fn process_block(&mut self, block: &Block) -> Result<(), &'static str> {
-        block.verify_block()?;
-        self.validate_last_block(&block)?;
-        self.process_block_transactions(&block)?;
+    pub fn process_block(&mut self, block: &Block) -> Result<()> {
+        block.verify_block(self.config.difficulty)?;
+        block.verify_limits(&self.config.block_limits)?;
+        self.validate_last_block(block)?;
+        self.validate_block_time(block)?;
+        let maturity_height = storage::get_maturity_height(&self.block_metadata)?;
+        block.has_valid_transactions(
+            self.expected_reward(block.index, maturity_height),
+            self.config.network,
+            &self.signature_cache,
+        )?;
+        self.validate_replays(block)?;
+        self.process_block_transactions(block)?;
+
+        storage::add_block(&self.blocks, block, self.config.compress_blocks)?;
+        storage::add_header(&self.block_metadata, &block.header())?;
+        storage::add_transactions(&self.block_metadata, block, self.config.network)?;
+        storage::set_latest_block(&self.block_metadata, &block.block_hash, block.index)?;
+        self.remove_confirmed_transactions(block)?;
+
+        if let Some(interval) = self.config.snapshot_interval {
+            if block.index > 0 && block.index.is_multiple_of(interval) {
+                self.create_snapshot()?;
+            }
+        }
+        self.prune(block.index)?;
+
+        Ok(())
+    }
+
+    /// This method checks that no transaction of a block without a nonce was confirmed
+    /// before, by an earlier block or earlier in the block itself. Transactions with a
+    /// nonce cannot be replayed since their nonce is used up.
+    fn validate_replays(&self, block: &Block) -> Result<()> {
+        let mut txids = HashSet::new();
+        for (i, transaction) in block.verified_transactions.iter().enumerate().skip(1) {
+            if transaction.nonce.is_some() {
+                continue;
+            }
+            let txid = transaction.txid();
+            if storage::get_transaction_block(&self.block_metadata, &txid)?.is_some()
+                || !txids.insert(txid)
+            {
+                return Err(Error::validation("Transaction was already confirmed")
+                    .at_tx(i)
+                    .in_block(&block.block_hash));
+            }
+        }
+        Ok(())
+    }
+
+    /// This method deletes the block bodies and undo data deeper than the prune depth.
+    ///
+    /// The chain base is moved up to the newest committed snapshot that is deep enough,
+    /// so the full account state at the new base stays known. Headers are always kept.
+    fn prune(&mut self, latest_height: usize) -> Result<()> {
+        let (depth, interval) = match (self.config.prune_depth, self.config.snapshot_interval) {
+            (Some(depth), Some(interval)) => (depth, interval),
+            _ => return Ok(()),
+        };
+
+        let base_height = storage::get_base_height(&self.block_metadata)?;
+        let prune_height = latest_height.saturating_sub(depth);
+        let new_base_height = prune_height - prune_height % interval;
+        if new_base_height <= base_height
+            || storage::get_snapshot_hash(&self.block_metadata, new_base_height)?.is_none()
+        {
+            return Ok(());
+        }
+
+        for height in base_height..new_base_height {
+            if let Some(block_hash) = storage::get_block_hash(&self.block_metadata, height)? {
+                storage::delete_block(&self.blocks, &block_hash)?;
+                storage::delete_undo(&self.block_metadata, &block_hash)?;
+            }
+            let snapshot_path = self.config.snapshot_path(height);
+            if snapshot_path.exists() {
+                std::fs::remove_file(snapshot_path)?;
+            }
+        }
+        storage::set_base_height(&self.block_metadata, new_base_height)
+    }
+
+    /// This method disconnects the latest block using its undo data and returns it.
+    ///
+    /// The transactions of the block, except the coinbase, are returned to the mempool.
+    /// Only blocks above the base of the chain can be disconnected.
+    pub fn disconnect_tip(&mut self) -> Result<Block> {
+        let block = self
+            .get_latest_block()?
+            .ok_or_else(|| Error::Storage(String::from("Latest block not found")))?;
+        if block.index <= storage::get_base_height(&self.block_metadata)? {
+            return Err(Error::Storage(String::from(
+                "The base of the chain cannot be disconnected",
+            )));
+        }
+
+        let undo =
+            storage::get_undo(&self.block_metadata, &block.block_hash)?.ok_or_else(|| {
+                Error::Storage(format!("Undo data of block {} not found", block.block_hash))
+            })?;
+        let mut total = self.total_balance()?;
+        for (address, balance) in undo {
+            total -= storage::get_balance(&self.balances, address)?.unwrap_or(0.0);
+            total += balance.unwrap_or(0.0);
+            match balance {
+                Some(balance) => storage::set_balance(&self.balances, address, balance)?,
+                None => storage::delete_balance(&self.balances, address)?,
+            }
+        }
+        for (sender, nonce) in first_nonces(&block) {
+            match nonce {
+                0 => storage::delete_nonce(&self.block_metadata, &sender)?,
+                nonce => storage::set_nonce(&self.block_metadata, &sender, nonce)?,
+            }
+        }
+
+        storage::set_total_balance(&self.block_metadata, total)?;
+        storage::delete_undo(&self.block_metadata, &block.block_hash)?;
+        storage::delete_coinbase(&self.block_metadata, block.index)?;
+        storage::delete_header(&self.block_metadata, &block.block_hash)?;
+        storage::delete_transactions(&self.block_metadata, &block, self.config.network)?;
+        storage::delete_block_height(&self.block_metadata, &block.block_hash, block.index)?;
+        storage::set_latest_block(
+            &self.block_metadata,
+            &block.previous_block_hash,
+            block.index - 1,
+        )?;
+        storage::delete_block(&self.blocks, &block.block_hash)?;
+        for transaction in block.verified_transactions.iter().skip(1) {
+            self.return_to_mempool(transaction.clone())?;
+        }
+        self.save_mempool()?;
+
+        Ok(block)
+    }
+
+    /// This method stores a snapshot in the snapshots directory and commits its state hash.
+    fn commit_snapshot(&self, snapshot: &Snapshot) -> Result<()> {
+        create_dir_all(self.config.snapshots_dir())?;
+        snapshot.write(&self.config.snapshot_path(snapshot.height()))?;
+        storage::set_snapshot_hash(
+            &self.block_metadata,
+            snapshot.height(),
+            &snapshot.state_hash,
+        )
+    }
+
+    /// This method takes a snapshot of the account state at the latest block.
+    pub fn create_snapshot(&self) -> Result<Snapshot> {
+        let latest_block = self
+            .get_latest_block()?
+            .ok_or_else(|| Error::Storage(String::from("Latest block not found")))?;
+        let balances = storage::get_balances(&self.balances)?;
+        let nonces = storage::get_nonces(&self.block_metadata)?;
+        let mut coinbases = BTreeMap::new();
+        for height in self.maturing_heights(latest_block.index) {
+            if let Some(coinbase) = storage::get_coinbase(&self.block_metadata, height)? {
+                coinbases.insert(height, coinbase);
+            }
+        }
+        let snapshot = Snapshot::new(
+            self.config.network,
+            latest_block,
+            &balances,
+            &nonces,
+            &coinbases,
+        );
+        self.commit_snapshot(&snapshot)?;
+
+        Ok(snapshot)
+    }
 
-        let previous_block_number = storage::get_latest_block_number(&self.block_metadata)?;
-        storage::add_block(&self.blocks, block)?;
+    /// This method returns the heights up to `height` whose coinbase rewards may still be
+    /// maturing for the blocks after it. The genesis reward is always mature.
+    fn maturing_heights(&self, height: usize) -> std::ops::RangeInclusive<usize> {
+        (height + 1)
+            .saturating_sub(self.config.coinbase_maturity)
+            .max(1)..=height
+    }
+
+    /// This method starts an empty chain from a snapshot whose state hash must be
+    /// `expected_state_hash`, obtained from a trusted source.
+    ///
+    /// The account state is taken from the snapshot and the blocks after it are validated
+    /// normally. The snapshot becomes the base of the chain: verification and reindexing
+    /// start from it instead of genesis. The snapshot must not hold more coins than were
+    /// issued up to its block, and must carry the coinbase rewards still maturing.
+    pub fn load_snapshot(&mut self, snapshot: &Snapshot, expected_state_hash: &str) -> Result<()> {
+        if storage::get_latest_block_hash(&self.block_metadata)?.is_some() {
+            return Err(Error::Storage(String::from(
+                "A snapshot can only be loaded into an empty chain",
+            )));
+        }
+        if snapshot.height() == 0 {
+            return Err(Error::Storage(String::from(
+                "A snapshot must be taken after the genesis block",
+            )));
+        }
+        snapshot.verify(self.config.network, expected_state_hash)?;
+        snapshot.block.verify_block(self.config.difficulty)?;
+        let balances = snapshot.get_balances()?;
+        let total = balances.values().sum();
+        check_supply(
+            total,
+            self.config.supply(snapshot.height()),
+            &snapshot.block.block_hash,
+        )?;
+        let coinbases = snapshot.get_coinbases()?;
+        if let Some(height) = self
+            .maturing_heights(snapshot.height())
+            .find(|height| !coinbases.contains_key(height))
+        {
+            return Err(Error::consensus(
+                &snapshot.block.block_hash,
+                format!(
+                    "Snapshot lacks the maturing coinbase reward of block {}",
+                    height
+                ),
+            ));
+        }
+
+        for (address, balance) in balances {
+            storage::set_balance(&self.balances, address, balance)?;
+        }
+        storage::set_total_balance(&self.block_metadata, total)?;
+        for (sender, nonce) in snapshot.get_nonces()? {
+            storage::set_nonce(&self.block_metadata, &sender, nonce)?;
+        }
+        for (height, (receiver, amount)) in coinbases {
+            storage::set_coinbase(&self.block_metadata, height, receiver, amount)?;
+        }
+        storage::add_block(&self.blocks, &snapshot.block, self.config.compress_blocks)?;
+        storage::add_header(&self.block_metadata, &snapshot.block.header())?;
+        storage::add_transactions(&self.block_metadata, &snapshot.block, self.config.network)?;
         storage::set_latest_block(
             &self.block_metadata,
-            &block.block_hash,
-            previous_block_number + 1,
+            &snapshot.block.block_hash,
+            snapshot.height(),
+        )?;
+        storage::set_base_height(&self.block_metadata, snapshot.height())?;
+        self.commit_snapshot(snapshot)
+    }
+
+    /// This method returns the balances, nonces and maturing coinbase rewards the chain
+    /// starts from: none for a chain started from genesis, or those of the committed
+    /// snapshot it was started from.
+    fn base_state(&self, base_height: usize) -> Result<(Balances, Nonces, Coinbases)> {
+        if base_height == 0 {
+            return Ok((HashMap::new(), HashMap::new(), BTreeMap::new()));
+        }
+
+        let snapshot = Snapshot::read(&self.config.snapshot_path(base_height))?;
+        let state_hash = storage::get_snapshot_hash(&self.block_metadata, base_height)?
+            .ok_or_else(|| {
+                Error::Storage(format!(
+                    "Snapshot at height {} has no commitment",
+                    base_height
+                ))
+            })?;
+        snapshot.verify(self.config.network, &state_hash)?;
+
+        Ok((
+            snapshot.get_balances()?,
+            snapshot.get_nonces()?,
+            snapshot.get_coinbases()?,
+        ))
+    }
+
+    /// This method returns the hashes of the blocks from the base of the chain up to the
+    /// latest block, following the links stored in the blocks themselves.
+    fn chain_hashes(&self, base_height: usize) -> Result<Vec<String>> {
+        let mut hashes = Vec::new();
+        let mut next_hash = storage::get_latest_block_hash(&self.block_metadata)?;
+
+        while let Some(block_hash) = next_hash {
+            let block = storage::get_block(&self.blocks, &block_hash)?
+                .ok_or_else(|| Error::Storage(format!("Block {} not found", block_hash)))?;
+            next_hash = if block.index <= base_height {
+                None
+            } else {
+                Some(block.previous_block_hash)
+            };
+            hashes.push(block_hash);
+        }
+
+        hashes.reverse();
+        Ok(hashes)
+    }
+
+    /// This method checks the linkage of the headers kept below the base of the chain.
+    fn verify_headers(&self, base_height: usize) -> Result<()> {
+        let base_block_hash = storage::get_block_hash(&self.block_metadata, base_height)?
+            .ok_or_else(|| Error::Storage(format!("Block at height {} not found", base_height)))?;
+        let mut header =
+            storage::get_header(&self.block_metadata, &base_block_hash)?.ok_or_else(|| {
+                Error::Storage(format!("Header of block {} not found", base_block_hash))
+            })?;
+
+        while header.index > 0 {
+            let previous =
+                match storage::get_header(&self.block_metadata, &header.previous_block_hash)? {
+                    Some(previous) => previous,
+                    // A chain started from a snapshot does not know the headers before it
+                    None if header.index == base_height => return Ok(()),
+                    None => {
+                        return Err(Error::Storage(format!(
+                            "Header of block {} not found",
+                            header.previous_block_hash
+                        )))
+                    }
+                };
+            if previous.index + 1 != header.index {
+                return Err(Error::consensus(
+                    &header.block_hash,
+                    "Block index is not valid",
+                ));
+            }
+            header = previous;
+        }
+
+        if header.previous_block_hash != self.config.genesis.previous_block_hash {
+            return Err(Error::consensus(
+                &header.block_hash,
+                "Must reference previous block's hash",
+            ));
+        }
+
+        Ok(())
+    }
+
+    /// This method re-validates the stored chain starting from genesis, or from the
+    /// snapshot the chain was started from or pruned to.
+    ///
+    /// Headers below the base of the chain are checked for linkage only. Block linkage
+    /// is checked for every block while PoW, signatures and coinbase rules are
+    /// checked for the last `depth` blocks only (or every block when `depth` is `None`).
+    /// Balances are recomputed from scratch and compared with the `balances` store.
+    /// Returns the number of blocks that were fully checked.
+    pub fn verify_chain(&self, depth: Option<usize>) -> Result<usize> {
+        let base_height = storage::get_base_height(&self.block_metadata)?;
+        if base_height > 0 {
+            self.verify_headers(base_height)?;
+        }
+        let hashes = self.chain_hashes(base_height)?;
+        let first_checked = depth.map_or(0, |depth| hashes.len().saturating_sub(depth));
+        let mut previous_block_hash = self.config.genesis.previous_block_hash.clone();
+        let (mut balances, mut nonces, base_coinbases) = self.base_state(base_height)?;
+        let mut total: f64 = balances.values().sum();
+        let maturity = self.config.coinbase_maturity;
+        let maturity_height = storage::get_maturity_height(&self.block_metadata)?;
+        let mut coinbases: VecDeque<_> = base_coinbases.into_iter().collect();
+
+        for (offset, block_hash) in hashes.iter().enumerate() {
+            let block = storage::get_block(&self.blocks, block_hash)?
+                .ok_or_else(|| Error::Storage(format!("Block {} not found", block_hash)))?;
+
+            if block.index != base_height + offset {
+                return Err(Error::consensus(block_hash, "Block index is not valid"));
+            }
+            // The block a snapshot was taken at has an unknown parent and its
+            // transactions are already part of the snapshot state
+            let is_snapshot_block = base_height > 0 && block.index == base_height;
+            if !is_snapshot_block && block.previous_block_hash != previous_block_hash {
+                return Err(Error::consensus(
+                    block_hash,
+                    "Must reference previous block's hash",
+                ));
+            }
+            if offset >= first_checked {
+                block.verify_block(self.config.difficulty)?;
+                block.verify_limits(&self.config.block_limits)?;
+                block.has_valid_transactions(
+                    self.expected_reward(block.index, maturity_height),
+                    self.config.network,
+                    &self.signature_cache,
+                )?;
+                if !is_snapshot_block {
+                    self.validate_block_time(&block)?;
+                }
+            }
+
+            if !is_snapshot_block {
+                if maturity > 0 {
+                    coinbases.extend(
+                        coinbase_credit(&block, self.config.network)
+                            .map(|credit| (block.index, credit)),
+                    );
+                }
+                while coinbases
+                    .front()
+                    .is_some_and(|(height, _)| height + maturity <= block.index)
+                {
+                    coinbases.pop_front();
+                }
+                let mut immature = Balances::new();
+                if block.index >= maturity_height {
+                    for (_, (receiver, amount)) in coinbases.iter() {
+                        *immature.entry(*receiver).or_insert(0.0) += amount;
+                    }
+                }
+                apply_block_transactions(
+                    &mut balances,
+                    &mut nonces,
+                    &immature,
+                    &block,
+                    self.config.network,
+                )?;
+                total += block_issuance(&block);
+                check_supply(total, self.config.supply(block.index), &block.block_hash)?;
+            }
+            previous_block_hash = block.block_hash;
+        }
+
+        let stored_balances = storage::get_balances(&self.balances)?;
+        if stored_balances != balances {
+            return Err(Error::Storage(String::from(
+                "Stored balances do not match the chain, reindex is required",
+            )));
+        }
+        if storage::get_nonces(&self.block_metadata)? != nonces {
+            return Err(Error::Storage(String::from(
+                "Stored nonces do not match the chain, reindex is required",
+            )));
+        }
+
+        Ok(hashes.len() - first_checked)
+    }
+
+    /// This method rebuilds the block and transaction indexes, undo data and balances from
+    /// the stored blocks.
+    /// Returns the number of blocks that were replayed.
+    pub fn reindex(&mut self) -> Result<usize> {
+        let base_height = storage::get_base_height(&self.block_metadata)?;
+        let hashes = self.chain_hashes(base_height)?;
+        let (base_balances, base_nonces, _) = self.base_state(base_height)?;
+
+        storage::clear(&self.balances)?;
+        storage::set_total_balance(&self.block_metadata, base_balances.values().sum())?;
+        for (address, balance) in base_balances {
+            storage::set_balance(&self.balances, address, balance)?;
+        }
+        for sender in storage::get_nonces(&self.block_metadata)?.into_keys() {
+            storage::delete_nonce(&self.block_metadata, &sender)?;
+        }
+        for (sender, nonce) in base_nonces {
+            storage::set_nonce(&self.block_metadata, &sender, nonce)?;
+        }
+
+        for block_hash in hashes.iter() {
+            let block = storage::get_block(&self.blocks, block_hash)?
+                .ok_or_else(|| Error::Storage(format!("Block {} not found", block_hash)))?;
+            if base_height == 0 || block.index > base_height {
+                self.process_block_transactions(&block)?;
+            }
+            storage::add_header(&self.block_metadata, &block.header())?;
+            storage::add_transactions(&self.block_metadata, &block, self.config.network)?;
+            storage::set_latest_block(&self.block_metadata, block_hash, block.index)?;
+        }
+
+        Ok(hashes.len())
+    }
+
+    /// This method writes the blocks between heights `from` and `to` (inclusive) to a block file.
+    /// Returns the number of blocks written.
+    pub fn export_blocks(&self, from: usize, to: Option<usize>, path: &Path) -> Result<usize> {
+        let latest = storage::get_latest_block_number(&self.block_metadata)?;
+        let to = to.unwrap_or(latest);
+        let base_height = storage::get_base_height(&self.block_metadata)?;
+        if from < base_height {
+            return Err(Error::Storage(format!(
+                "Blocks below height {} are not available",
+                base_height
+            )));
+        }
+        if from > to || to > latest {
+            return Err(Error::Storage(format!(
+                "Invalid export range {}..={}, latest block is {}",
+                from, to, latest
+            )));
+        }
+
+        let mut writer =
+            BlockFileWriter::new(BufWriter::new(File::create(path)?), self.config.network)?;
+        for height in from..=to {
+            let block = self
+                .get_block_at_height(height)?
+                .ok_or_else(|| Error::Storage(format!("Block at height {} not found", height)))?;
+            writer.write_block(&block)?;
+        }
+        writer.finish()?;
+
+        Ok(to - from + 1)
+    }
+
+    /// This method reads a block file and processes its blocks in order.
+    ///
+    /// Blocks that are already part of the chain are skipped. Returns the number of blocks
+    /// that were added.
+    pub fn import_blocks(&mut self, path: &Path) -> Result<usize> {
+        let reader = BlockFileReader::new(
+            BufReader::new(File::open(path)?),
+            self.config.network,
+            self.config.block_limits.max_bytes,
         )?;
+        let mut imported = 0;
+
+        for block in reader {
+            let block = block?;
+            if storage::get_block_hash(&self.block_metadata, block.index)?.as_ref()
+                == Some(&block.block_hash)
+            {
+                continue;
+            }
+            self.process_block(&block)?;
+            imported += 1;
+        }
 
-        return Ok(());
+        Ok(imported)
     }
 
     /// This method is used to start a new blockchain with genesis block included
-    pub fn start_blockchain(&mut self) -> Result<Block, &'static str> {
-        if let Ok(Some(latest_block)) = self.get_latest_block().map_err(|e| e) {
-            return Ok(latest_block);
+    pub fn start_blockchain(&mut self) -> Result<Block> {
+        if let Some(latest_block) = self.get_latest_block()? {
+            Ok(latest_block)
         } else {
-            let genesis_block = self
-                .genesis_block()
-                .mine_block(DIFFICULTY_LEVEL)
-                .expect("Failed to mine genesis block");
+            let genesis_block = self.genesis_block()?.mine_block(self.config.difficulty)?;
             self.process_block(&genesis_block)?;
 
-            return Ok(genesis_block);
+            Ok(genesis_block)
         }
     }
 
-    pub fn mine(&mut self) -> Result<Block, &'static str> {
+    /// This method returns the pending transactions that can be confirmed in the block at
+    /// `height`: those that are not expired and whose nonce follows the confirmed nonce of
+    /// their sender, as long as they fit in `max_bytes` and `max_transactions`.
+    fn mineable_transactions(
+        &self,
+        height: usize,
+        max_bytes: usize,
+        max_transactions: usize,
+    ) -> Result<Vec<Transaction>> {
+        let mut nonces = HashMap::new();
         let mut transactions = Vec::new();
-        transactions.push(self.coinbase_transaction());
-        transactions.extend(self.mempool.clone());
+        let mut bytes = 0;
+        for transaction in self.mempool.transactions() {
+            if transactions.len() >= max_transactions {
+                break;
+            }
+            if transaction.is_expired(height) {
+                continue;
+            }
+            let next_nonce = match (&transaction.sender, transaction.nonce) {
+                (Some(sender), Some(nonce)) => {
+                    let next_nonce = match nonces.get_mut(sender) {
+                        Some(next_nonce) => next_nonce,
+                        None => nonces
+                            .entry(sender.clone())
+                            .or_insert(storage::get_nonce(&self.block_metadata, sender)?),
+                    };
+                    if nonce != *next_nonce {
+                        continue;
+                    }
+                    Some(next_nonce)
+                }
+                _ => None,
+            };
+            // A transaction that does not fit leaves room for smaller ones, but the later
+            // transactions of its sender can no longer follow its nonce
+            let size = transaction.size();
+            if bytes + size > max_bytes {
+                continue;
+            }
+            if let Some(next_nonce) = next_nonce {
+                *next_nonce += 1;
+            }
+            bytes += size;
+            transactions.push(transaction);
+        }
+
+        Ok(transactions)
+    }
 
-        let last_block = self.get_latest_block()?.unwrap();
+    pub fn mine(&mut self) -> Result<Block> {
+        let last_block = self
+            .get_latest_block()?
+            .ok_or_else(|| Error::Storage(String::from("Latest block not found")))?;
         let mut new_block = Block::new(last_block.index + 1, last_block.block_hash.clone());
-        new_block.verified_transactions = transactions;
+        let now = self.clock.now();
+        new_block.time = match self.median_time_past(new_block.index)? {
+            Some(median_time) if now <= median_time => Some(median_time + Duration::seconds(1)),
+            _ => Some(now),
+        };
+
+        // The header and the coinbase take the same room whatever the coinbase amount is
+        new_block.verified_transactions = vec![self.coinbase_transaction(0.0)?];
+        let limits = self.config.block_limits;
+        let mut pending = self.mineable_transactions(
+            new_block.index,
+            limits.max_bytes.saturating_sub(new_block.size()),
+            limits.max_transactions.saturating_sub(1),
+        )?;
+        // Signatures vary in length by a few bytes, so the signed coinbase may not fit
+        loop {
+            let fees: f64 = pending.iter().map(|transaction| transaction.fee).sum();
+            new_block.verified_transactions =
+                vec![self.coinbase_transaction(self.config.block_reward(new_block.index) + fees)?];
+            new_block
+                .verified_transactions
+                .extend(pending.iter().cloned());
+            if pending.is_empty() || new_block.size() <= limits.max_bytes {
+                break;
+            }
+            pending.pop();
+        }
 
-        let mined_block = new_block.mine_block(DIFFICULTY_LEVEL)?;
+        let mined_block = new_block.mine_block(self.config.difficulty)?;
         self.process_block(&mined_block)?;
-        self.mempool.clear();
 
         Ok(mined_block)
     }
 }
+
+/// This method returns the lowest nonce every sender used in a block, which is the nonce
+/// the sender had before the block.
+fn first_nonces(block: &Block) -> Nonces {
+    let mut nonces = Nonces::new();
+    for transaction in block.verified_transactions.iter() {
+        if let (Some(sender), Some(nonce)) = (&transaction.sender, transaction.nonce) {
+            let first_nonce = nonces.entry(sender.clone()).or_insert(nonce);
+            *first_nonce = (*first_nonce).min(nonce);
+        }
+    }
+    nonces
+}
+
+/// This method returns the receiver and the amount of the coinbase reward of a block,
+/// `None` for the genesis block whose reward is always mature.
+fn coinbase_credit(block: &Block, network: Network) -> Option<(Address, f64)> {
+    match block.verified_transactions.first() {
+        Some(coinbase) if block.index > 0 => {
+            Some((coinbase.receiver.address(network), coinbase.amount))
+        }
+        _ => None,
+    }
+}
+
+/// This method returns the change a transaction brings to the balance of the `addresses`
+/// of an account on `network`: the amount it pays to them, less the amount and fee they
+/// send. A coinbase only credits its receiver.
+fn account_change(
+    transaction: &Transaction,
+    is_coinbase: bool,
+    addresses: &[Address],
+    network: Network,
+) -> f64 {
+    let mut change = 0.0;
+    if addresses.contains(&transaction.receiver.address(network)) {
+        change += transaction.amount;
+    }
+    if !is_coinbase
+        && transaction
+            .sender
+            .as_ref()
+            .is_some_and(|sender| addresses.contains(&sender.address(network)))
+    {
+        change -= transaction.total_spent();
+    }
+    change
+}
+
+/// This method checks that the balances of all accounts together do not exceed the coins
+/// issued up to a block.
+fn check_supply(total_balance: f64, supply: f64, block_hash: &str) -> Result<()> {
+    // Both sides are sums of floating point amounts, added up in a different order
+    if total_balance > supply + supply.abs() * 1e-9 {
+        return Err(Error::consensus(
+            block_hash,
+            "Balances exceed the issued supply",
+        ));
+    }
+    Ok(())
+}
+
+/// This method returns the change a block brings to the sum of the balances: the amount
+/// of its coinbase, less the fees its transactions pay into it.
+fn block_issuance(block: &Block) -> f64 {
+    block
+        .verified_transactions
+        .iter()
+        .enumerate()
+        .map(|(i, transaction)| {
+            if i == 0 {
+                transaction.amount
+            } else {
+                -transaction.fee
+            }
+        })
+        .sum()
+}
+
+/// The balance of every account, by address.
+type Balances = HashMap<Address, f64>;
+/// The nonce the next transaction of every account must use, for accounts that sent
+/// transactions with a nonce.
+type Nonces = HashMap<Sender, u64>;
+/// The receiver and amount of the coinbase rewards that may not have matured yet, by height.
+type Coinbases = BTreeMap<usize, (Address, f64)>;
+
+/// This method applies the transactions of a block to the given balances and nonces.
+///
+/// The first transaction is the coinbase and only credits its receiver, every other
+/// transaction moves its amount from the sender to the receiver and its fee from the
+/// sender to the coinbase. A transaction with a nonce must use the next nonce of its sender,
+/// and the `immature` coinbase rewards of its sender cannot be spent. Senders are credited
+/// and debited on their address on `network`.
+fn apply_block_transactions(
+    balances: &mut Balances,
+    nonces: &mut Nonces,
+    immature: &Balances,
+    block: &Block,
+    network: Network,
+) -> Result<()> {
+    for (i, transaction) in block.verified_transactions.iter().enumerate() {
+        // i = 0 => Skip when coinbase transaction
+        // Process: Sender => Receiver (Deduct amount from balance)
+        if i > 0 {
+            let sender = transaction.sender.as_ref().ok_or_else(|| {
+                Error::validation("No sender found")
+                    .at_tx(i)
+                    .in_block(&block.block_hash)
+            })?;
+            let sender_address = sender.address(network);
+            let sender_balance = balances.get_mut(&sender_address).ok_or_else(|| {
+                Error::validation("Sender balance not found")
+                    .at_tx(i)
+                    .in_block(&block.block_hash)
+            })?;
+            if *sender_balance < transaction.total_spent() {
+                return Err(Error::validation("Insufficient funds")
+                    .at_tx(i)
+                    .in_block(&block.block_hash));
+            }
+            let immature_balance = immature.get(&sender_address).copied().unwrap_or(0.0);
+            if *sender_balance - immature_balance < transaction.total_spent() {
+                return Err(Error::validation("Coinbase reward is not mature")
+                    .at_tx(i)
+                    .in_block(&block.block_hash));
+            }
+            *sender_balance -= transaction.total_spent();
+
+            if let Some(nonce) = transaction.nonce {
+                let next_nonce = nonces.entry(sender.clone()).or_insert(0);
+                if nonce != *next_nonce {
+                    return Err(Error::validation("Nonce is not the next one of the sender")
+                        .at_tx(i)
+                        .in_block(&block.block_hash));
+                }
+                *next_nonce += 1;
+            }
+        }
+
+        // Process: Receiver <= Sender (Insert amount into balance)
+        *balances
+            .entry(transaction.receiver.address(network))
+            .or_insert(0.0) += transaction.amount;
+    }
+
+    Ok(())
+}
@@ -0,0 +1,222 @@
+use crate::{
+    block::Block,
+    codec,
+    config::Network,
+    error::{Error, Result},
+};
+use std::io::{ErrorKind, Read, Write};
+
+const MAGIC: &[u8; 4] = b"SBLK";
+/// Version 1 files hold JSON blocks, version 2 files hold binary blocks.
+const VERSION: u8 = 2;
+const CHECKSUM_LEN: usize = 4;
+const MAX_RECORD_LEN: usize = 32 * 1024 * 1024;
+
+/// This method computes the checksum of a record: the first bytes of its SHA256 digest.
+fn checksum(payload: &[u8]) -> Vec<u8> {
+    let mut digest = crypto_hash::digest(crypto_hash::Algorithm::SHA256, payload);
+    digest.truncate(CHECKSUM_LEN);
+    digest
+}
+
+/// A writer of portable block files.
+///
+/// The file starts with a header made of the `SBLK` magic, the format version and the
+/// network name. Every block follows as a record made of its length as a little endian
+/// `u32`, the encoded block and a checksum of the encoded block.
+pub struct BlockFileWriter<W: Write> {
+    writer: W,
+}
+
+impl<W: Write> BlockFileWriter<W> {
+    /// This method creates a new writer and writes the file header.
+    pub fn new(mut writer: W, network: Network) -> Result<Self> {
+        let name = network.name().as_bytes();
+        writer.write_all(MAGIC)?;
+        writer.write_all(&[VERSION, name.len() as u8])?;
+        writer.write_all(name)?;
+        Ok(Self { writer })
+    }
+
+    /// This method appends a block record to the file.
+    pub fn write_block(&mut self, block: &Block) -> Result<()> {
+        let payload = codec::encode_block(block, false)?;
+        self.writer
+            .write_all(&(payload.len() as u32).to_le_bytes())?;
+        self.writer.write_all(&payload)?;
+        self.writer.write_all(&checksum(&payload))?;
+        Ok(())
+    }
+
+    /// This method flushes the file and returns the underlying writer.
+    pub fn finish(mut self) -> Result<W> {
+        self.writer.flush()?;
+        Ok(self.writer)
+    }
+}
+
+/// A reader of portable block files, yielding the blocks in the order they were written.
+pub struct BlockFileReader<R: Read> {
+    reader: R,
+    max_block_bytes: usize,
+}
+
+impl<R: Read> BlockFileReader<R> {
+    /// This method creates a new reader and checks the file header against the network.
+    /// Compressed records are not decompressed beyond `max_block_bytes`.
+    pub fn new(mut reader: R, network: Network, max_block_bytes: usize) -> Result<Self> {
+        let mut header = [0u8; 6];
+        reader.read_exact(&mut header)?;
+        if &header[..4] != MAGIC {
+            return Err(Error::Serialization(String::from("Not a block file")));
+        }
+        if header[4] == 0 || header[4] > VERSION {
+            return Err(Error::Serialization(format!(
+                "Unsupported block file version {}",
+                header[4]
+            )));
+        }
+
+        let mut name = vec![0u8; header[5] as usize];
+        reader.read_exact(&mut name)?;
+        if name != network.name().as_bytes() {
+            return Err(Error::Serialization(format!(
+                "Block file belongs to network {}, expected {}",
+                String::from_utf8_lossy(&name),
+                network
+            )));
+        }
+
+        Ok(Self {
+            reader,
+            max_block_bytes,
+        })
+    }
+
+    fn read_block(&mut self) -> Result<Option<Block>> {
+        // The file may only end between records, not inside a record length
+        let mut length = [0u8; 4];
+        let mut filled = 0;
+        while filled < length.len() {
+            match self.reader.read(&mut length[filled..]) {
+                Ok(0) => break,
+                Ok(read) => filled += read,
+                Err(e) if e.kind() == ErrorKind::Interrupted => {}
+                Err(e) => return Err(e.into()),
+            }
+        }
+        match filled {
+            0 => return Ok(None),
+            4 => {}
+            _ => {
+                return Err(Error::Serialization(String::from(
+                    "Block file ends inside a record length",
+                )))
+            }
+        }
+
+        let length = u32::from_le_bytes(length) as usize;
+        if length > MAX_RECORD_LEN {
+            return Err(Error::Serialization(format!(
+                "Block record of {} bytes is too large",
+                length
+            )));
+        }
+
+        let mut payload = vec![0u8; length];
+        self.reader.read_exact(&mut payload)?;
+        let mut expected = [0u8; CHECKSUM_LEN];
+        self.reader.read_exact(&mut expected)?;
+        if checksum(&payload) != expected {
+            return Err(Error::Serialization(String::from(
+                "Block record checksum mismatch",
+            )));
+        }
+
+        Ok(Some(codec::decode_block_within(
+            &payload,
+            self.max_block_bytes,
+        )?))
+    }
+}
+
+impl<R: Read> Iterator for BlockFileReader<R> {
+    type Item = Result<Block>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        self.read_block().transpose()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn blocks() -> Vec<Block> {
+        (1..4)
+            .map(|index| Block::new(index, "00".repeat(32)).mine_block(1).unwrap())
+            .collect()
+    }
+
+    fn write_file(blocks: &[Block], network: Network) -> Vec<u8> {
+        let mut writer = BlockFileWriter::new(Vec::new(), network).unwrap();
+        for block in blocks {
+            writer.write_block(block).unwrap();
+        }
+        writer.finish().unwrap()
+    }
+
+    #[test]
+    fn block_file_round_trip() {
+        let blocks = blocks();
+        let file = write_file(&blocks, Network::Regtest);
+        let read: Vec<_> = BlockFileReader::new(&file[..], Network::Regtest, usize::MAX)
+            .unwrap()
+            .collect::<Result<_>>()
+            .unwrap();
+
+        let hashes = |blocks: &[Block]| -> Vec<String> {
+            blocks
+                .iter()
+                .map(|block| block.block_hash.clone())
+                .collect()
+        };
+        assert_eq!(hashes(&read), hashes(&blocks));
+    }
+
+    #[test]
+    fn other_network_is_refused() {
+        let file = write_file(&blocks(), Network::Testnet);
+        assert!(matches!(
+            BlockFileReader::new(&file[..], Network::Mainnet, usize::MAX),
+            Err(Error::Serialization(_))
+        ));
+    }
+
+    #[test]
+    fn corrupted_record_is_refused() {
+        let mut file = write_file(&blocks(), Network::Regtest);
+        let last = file.len() - CHECKSUM_LEN - 1;
+        file[last] ^= 0xff;
+
+        let results: Vec<_> = BlockFileReader::new(&file[..], Network::Regtest, usize::MAX)
+            .unwrap()
+            .collect();
+        assert!(results[0].is_ok());
+        assert!(matches!(results.last(), Some(Err(Error::Serialization(_)))));
+    }
+
+    #[test]
+    fn truncated_record_length_is_refused() {
+        let blocks = blocks();
+        let mut file = write_file(&blocks, Network::Regtest);
+        file.extend([0u8; 2]);
+
+        let results: Vec<_> = BlockFileReader::new(&file[..], Network::Regtest, usize::MAX)
+            .unwrap()
+            .collect();
+        assert_eq!(results.len(), blocks.len() + 1);
+        assert!(results[..blocks.len()].iter().all(Result::is_ok));
+        assert!(matches!(results.last(), Some(Err(Error::Serialization(_)))));
+    }
+}
@@ -1,9 +1,104 @@
-use std::str::FromStr;
+use crate::error::{Error, Result};
+use secp256k1::{schnorr, KeyPair, Message, Verification, VerifyOnly};
+use serde::{Deserialize, Serialize};
+use std::{fmt, str::FromStr, sync::OnceLock};
 
 pub use secp256k1::ecdsa::Signature;
 pub use secp256k1::{rand, All, Secp256k1};
 pub use secp256k1::{PublicKey, SecretKey};
 
+/// This method returns the verification context shared by every signature check, which
+/// is costly to create.
+pub fn verification_context() -> &'static Secp256k1<VerifyOnly> {
+    static CONTEXT: OnceLock<Secp256k1<VerifyOnly>> = OnceLock::new();
+    CONTEXT.get_or_init(Secp256k1::verification_only)
+}
+
+/// The scheme a transaction is signed with: ECDSA, or Schnorr as specified by BIP340.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum SignatureScheme {
+    #[default]
+    Ecdsa,
+    Schnorr,
+}
+
+impl SignatureScheme {
+    pub fn is_ecdsa(&self) -> bool {
+        *self == SignatureScheme::Ecdsa
+    }
+
+    /// This method returns the byte the scheme is stored as.
+    pub fn to_byte(self) -> u8 {
+        match self {
+            SignatureScheme::Ecdsa => 0,
+            SignatureScheme::Schnorr => 1,
+        }
+    }
+
+    /// This method returns the scheme stored as a byte.
+    pub fn from_byte(byte: u8) -> Result<Self> {
+        match byte {
+            0 => Ok(SignatureScheme::Ecdsa),
+            1 => Ok(SignatureScheme::Schnorr),
+            _ => Err(Error::Serialization(format!(
+                "Unknown signature scheme {}",
+                byte
+            ))),
+        }
+    }
+
+    /// This method verifies a hex encoded signature of a 32 byte message made with the
+    /// scheme by the secret key of `public_key`.
+    ///
+    /// Schnorr signatures are checked against the x-only form of the key.
+    pub fn verify<C: Verification>(
+        self,
+        secp: &Secp256k1<C>,
+        message: &Message,
+        signature: &str,
+        public_key: &PublicKey,
+    ) -> Result<()> {
+        let verified = match self {
+            SignatureScheme::Ecdsa => {
+                let signature = Signature::from_str(signature)
+                    .map_err(|_| Error::validation("Malformed signature"))?;
+                secp.verify_ecdsa(message, &signature, public_key)
+            }
+            SignatureScheme::Schnorr => {
+                let signature = schnorr::Signature::from_str(signature)
+                    .map_err(|_| Error::validation("Malformed signature"))?;
+                secp.verify_schnorr(&signature, message, &public_key.x_only_public_key().0)
+            }
+        };
+        verified.map_err(|_| Error::validation("Invalid signature"))
+    }
+}
+
+impl fmt::Display for SignatureScheme {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.pad(match self {
+            SignatureScheme::Ecdsa => "ecdsa",
+            SignatureScheme::Schnorr => "schnorr",
+        })
+    }
+}
+
+impl FromStr for SignatureScheme {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        match s {
+            "ecdsa" => Ok(SignatureScheme::Ecdsa),
+            "schnorr" => Ok(SignatureScheme::Schnorr),
+            _ => Err(Error::Serialization(format!(
+                "Unknown signature scheme {}, expected ecdsa or schnorr",
+                s
+            ))),
+        }
+    }
+}
+
 /// A client structure that can be used to interact with a blockchain.
 ///
 /// `public_key` contains the public key of the client.
@@ -15,6 +110,12 @@ pub struct Client {
     pub public_key: PublicKey,
 }
 
+impl Default for Client {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
 impl Client {
     /// This method creates a new client with a random key pair.
     pub fn new() -> Self {
@@ -28,16 +129,20 @@ impl Client {
         }
     }
 
-    pub fn from(key: String) -> Result<Self, secp256k1::Error> {
+    pub fn from(key: String) -> Result<Self> {
+        Ok(Self::from_secret_key(SecretKey::from_str(key.trim())?))
+    }
+
+    /// This method creates a client from its secret key.
+    pub fn from_secret_key(secret_key: SecretKey) -> Self {
         let secp = Secp256k1::new();
-        let secret_key = SecretKey::from_str(&key)?;
         let public_key = PublicKey::from_secret_key(&secp, &secret_key);
 
-        Ok(Self {
+        Self {
             secp,
             secret_key,
             public_key,
-        })
+        }
     }
 
     /// This method identifies the client to the blockchain.
@@ -47,7 +152,23 @@ impl Client {
 
     /// This method signs a transaction with the client's private key.
     pub fn sign(&self, transaction: &[u8]) -> Signature {
-        let message = secp256k1::Message::from_slice(transaction).unwrap();
+        let message = Message::from_slice(transaction).unwrap();
         self.secp.sign_ecdsa(&message, &self.secret_key)
     }
+
+    /// This method signs a transaction with the client's private key using BIP340 Schnorr.
+    pub fn sign_schnorr(&self, transaction: &[u8]) -> schnorr::Signature {
+        let message = Message::from_slice(transaction).unwrap();
+        let key_pair = KeyPair::from_secret_key(&self.secp, &self.secret_key);
+        self.secp.sign_schnorr(&message, &key_pair)
+    }
+
+    /// This method signs a transaction with the client's private key using a scheme and
+    /// returns the hex encoded signature.
+    pub fn sign_with(&self, scheme: SignatureScheme, transaction: &[u8]) -> String {
+        match scheme {
+            SignatureScheme::Ecdsa => self.sign(transaction).to_string(),
+            SignatureScheme::Schnorr => self.sign_schnorr(transaction).to_string(),
+        }
+    }
 }
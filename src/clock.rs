@@ -0,0 +1,50 @@
+use chrono::{DateTime, Duration, Utc};
+use std::sync::Mutex;
+
+/// A source of the current time.
+///
+/// Every time dependent rule reads the time from a clock, so it can be replaced to make
+/// those rules deterministic.
+pub trait Clock: Send + Sync {
+    fn now(&self) -> DateTime<Utc>;
+}
+
+/// The clock of the system.
+#[derive(Debug, Default, Clone, Copy)]
+pub struct SystemClock;
+
+impl Clock for SystemClock {
+    fn now(&self) -> DateTime<Utc> {
+        Utc::now()
+    }
+}
+
+/// A clock standing still at a time that is only changed explicitly.
+#[derive(Debug)]
+pub struct FixedClock {
+    time: Mutex<DateTime<Utc>>,
+}
+
+impl FixedClock {
+    /// This method creates a new clock standing at `time`.
+    pub fn new(time: DateTime<Utc>) -> Self {
+        Self {
+            time: Mutex::new(time),
+        }
+    }
+
+    pub fn set(&self, time: DateTime<Utc>) {
+        *self.time.lock().unwrap() = time;
+    }
+
+    /// This method moves the clock forward by `duration`.
+    pub fn advance(&self, duration: Duration) {
+        *self.time.lock().unwrap() += duration;
+    }
+}
+
+impl Clock for FixedClock {
+    fn now(&self) -> DateTime<Utc> {
+        *self.time.lock().unwrap()
+    }
+}
@@ -0,0 +1,304 @@
+use crate::{
+    block::Block,
+    error::{Error, Result},
+};
+use flate2::{read::DeflateDecoder, write::DeflateEncoder, Compression};
+use std::io::{Read, Write};
+
+/// The first byte of a block record stored as JSON, before binary records existed.
+const JSON_RECORD: u8 = b'{';
+/// The first byte of a block record stored in the binary format, before transactions
+/// stored their signature scheme.
+const BINARY_RECORD_V1: u8 = 0x01;
+/// The first byte of a block record stored in the binary format and deflate compressed,
+/// before transactions stored their signature scheme.
+const COMPRESSED_RECORD_V1: u8 = 0x02;
+/// The first byte of a block record stored in the binary format.
+const BINARY_RECORD: u8 = 0x03;
+/// The first byte of a block record stored in the binary format and deflate compressed.
+const COMPRESSED_RECORD: u8 = 0x04;
+
+/// This method encodes a block into a binary record, compressing it when asked to.
+pub fn encode_block(block: &Block, compress: bool) -> Result<Vec<u8>> {
+    let mut payload = Vec::new();
+    block.encode(&mut payload);
+
+    if !compress {
+        let mut record = Vec::with_capacity(payload.len() + 1);
+        record.push(BINARY_RECORD);
+        record.extend(payload);
+        return Ok(record);
+    }
+
+    let mut encoder = DeflateEncoder::new(vec![COMPRESSED_RECORD], Compression::default());
+    encoder.write_all(&payload)?;
+    Ok(encoder.finish()?)
+}
+
+/// This method decodes a block record in any of the formats it may be stored in.
+pub fn decode_block(record: &[u8]) -> Result<Block> {
+    decode_block_within(record, usize::MAX)
+}
+
+/// This method decodes a block record like `decode_block`, refusing a compressed record
+/// whose block is encoded in more than `max_bytes` without decompressing the rest of it.
+pub fn decode_block_within(record: &[u8], max_bytes: usize) -> Result<Block> {
+    match record.first() {
+        Some(&JSON_RECORD) => Ok(serde_json::from_slice(record)?),
+        Some(&BINARY_RECORD) => Block::decode(&mut Reader::new(&record[1..]), true),
+        Some(&BINARY_RECORD_V1) => Block::decode(&mut Reader::new(&record[1..]), false),
+        Some(&format @ (COMPRESSED_RECORD | COMPRESSED_RECORD_V1)) => {
+            let mut payload = Vec::new();
+            DeflateDecoder::new(&record[1..])
+                .take((max_bytes as u64).saturating_add(1))
+                .read_to_end(&mut payload)?;
+            if payload.len() > max_bytes {
+                return Err(Error::Serialization(format!(
+                    "Compressed block record exceeds {} bytes",
+                    max_bytes
+                )));
+            }
+            Block::decode(&mut Reader::new(&payload), format == COMPRESSED_RECORD)
+        }
+        _ => Err(Error::Serialization(String::from(
+            "Unknown block record format",
+        ))),
+    }
+}
+
+/// This method checks whether a block record is stored in the requested binary format.
+pub fn is_binary_record(record: &[u8], compress: bool) -> bool {
+    let expected = if compress {
+        COMPRESSED_RECORD
+    } else {
+        BINARY_RECORD
+    };
+    record.first() == Some(&expected)
+}
+
+pub fn put_u8(buffer: &mut Vec<u8>, value: u8) {
+    buffer.push(value);
+}
+
+pub fn put_u32(buffer: &mut Vec<u8>, value: u32) {
+    buffer.extend(value.to_le_bytes());
+}
+
+pub fn put_u64(buffer: &mut Vec<u8>, value: u64) {
+    buffer.extend(value.to_le_bytes());
+}
+
+pub fn put_i64(buffer: &mut Vec<u8>, value: i64) {
+    buffer.extend(value.to_le_bytes());
+}
+
+pub fn put_f64(buffer: &mut Vec<u8>, value: f64) {
+    buffer.extend(value.to_le_bytes());
+}
+
+/// This method writes a byte string prefixed with its length.
+pub fn put_bytes(buffer: &mut Vec<u8>, value: &[u8]) {
+    put_u32(buffer, value.len() as u32);
+    buffer.extend(value);
+}
+
+/// This method writes a hex string such as a hash or a signature. Lowercase hex is
+/// stored as the raw bytes it encodes, any other string is stored as is.
+pub fn put_hex(buffer: &mut Vec<u8>, hex: &str) {
+    match decode_hex(hex) {
+        Some(bytes) if encode_hex(&bytes) == hex => {
+            put_u8(buffer, 0);
+            put_bytes(buffer, &bytes);
+        }
+        _ => {
+            put_u8(buffer, 1);
+            put_bytes(buffer, hex.as_bytes());
+        }
+    }
+}
+
+/// This method encodes bytes as lowercase hex.
+pub fn encode_hex(bytes: &[u8]) -> String {
+    const DIGITS: &[u8; 16] = b"0123456789abcdef";
+    let mut hex = String::with_capacity(bytes.len() * 2);
+    for byte in bytes {
+        hex.push(DIGITS[(byte >> 4) as usize] as char);
+        hex.push(DIGITS[(byte & 0x0f) as usize] as char);
+    }
+    hex
+}
+
+/// This method decodes hex into bytes, `None` when it is not valid hex.
+pub fn decode_hex(hex: &str) -> Option<Vec<u8>> {
+    if !hex.len().is_multiple_of(2) {
+        return None;
+    }
+    (0..hex.len())
+        .step_by(2)
+        .map(|i| u8::from_str_radix(hex.get(i..i + 2)?, 16).ok())
+        .collect()
+}
+
+/// A reader over a binary record.
+pub struct Reader<'a> {
+    data: &'a [u8],
+}
+
+impl<'a> Reader<'a> {
+    pub fn new(data: &'a [u8]) -> Self {
+        Self { data }
+    }
+
+    /// This method checks whether the whole record was read.
+    pub fn is_empty(&self) -> bool {
+        self.data.is_empty()
+    }
+
+    /// This method reads the next `len` bytes of the record.
+    pub fn take(&mut self, len: usize) -> Result<&'a [u8]> {
+        if self.data.len() < len {
+            return Err(Error::Serialization(String::from(
+                "Unexpected end of record",
+            )));
+        }
+        let (head, tail) = self.data.split_at(len);
+        self.data = tail;
+        Ok(head)
+    }
+
+    pub fn u8(&mut self) -> Result<u8> {
+        Ok(self.take(1)?[0])
+    }
+
+    pub fn u32(&mut self) -> Result<u32> {
+        Ok(u32::from_le_bytes(self.take(4)?.try_into().unwrap()))
+    }
+
+    pub fn u64(&mut self) -> Result<u64> {
+        Ok(u64::from_le_bytes(self.take(8)?.try_into().unwrap()))
+    }
+
+    pub fn i64(&mut self) -> Result<i64> {
+        Ok(i64::from_le_bytes(self.take(8)?.try_into().unwrap()))
+    }
+
+    pub fn f64(&mut self) -> Result<f64> {
+        Ok(f64::from_le_bytes(self.take(8)?.try_into().unwrap()))
+    }
+
+    pub fn bytes(&mut self) -> Result<&'a [u8]> {
+        let len = self.u32()? as usize;
+        self.take(len)
+    }
+
+    pub fn string(&mut self) -> Result<String> {
+        Ok(std::str::from_utf8(self.bytes()?)?.to_string())
+    }
+
+    pub fn hex(&mut self) -> Result<String> {
+        match self.u8()? {
+            0 => Ok(encode_hex(self.bytes()?)),
+            1 => self.string(),
+            tag => Err(Error::Serialization(format!("Unknown hex tag {}", tag))),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{address::Address, client::Client, config::Network, transaction::Transaction};
+
+    fn block(transactions: usize) -> Block {
+        let receiver = Client::new().public_key;
+        let mut block = Block::new(1, "00".repeat(32));
+        block.verified_transactions = (0..transactions)
+            .map(|_| Transaction::new(None, receiver.into(), 1.0, None))
+            .collect();
+        block.mine_block(1).unwrap()
+    }
+
+    /// This method returns a block holding a coinbase and a signed payment using every
+    /// optional field.
+    fn signed_block() -> Block {
+        let sender = Client::new();
+        let receiver = Address::from_public_key(&Client::new().public_key, Network::Testnet);
+        let mut payment =
+            Transaction::new(Some(sender.public_key.into()), receiver.into(), 2.5, None);
+        payment.fee = 0.25;
+        payment.nonce = Some(3);
+        payment.expiry_height = Some(10);
+        payment.sign_transaction(&sender);
+
+        // Mining counts the nonce from 0 but first hashes the one the block holds
+        let mut block = block(1);
+        block.nonce = 0;
+        block.verified_transactions.push(payment);
+        block.mine_block(1).unwrap()
+    }
+
+    fn assert_same_block(decoded: &Block, block: &Block) {
+        assert_eq!(
+            serde_json::to_value(decoded).unwrap(),
+            serde_json::to_value(block).unwrap()
+        );
+        assert_eq!(decoded.calculate_hash(), block.block_hash);
+        for transaction in decoded.verified_transactions.iter().skip(1) {
+            transaction.is_valid_transaction().unwrap();
+        }
+    }
+
+    #[test]
+    fn block_record_round_trip() {
+        let block = signed_block();
+        for compress in [false, true] {
+            let record = encode_block(&block, compress).unwrap();
+            assert!(is_binary_record(&record, compress));
+            assert!(!is_binary_record(&record, !compress));
+            assert_same_block(&decode_block(&record).unwrap(), &block);
+        }
+    }
+
+    #[test]
+    fn json_record_is_decoded() {
+        let block = signed_block();
+        let record = serde_json::to_vec(&block).unwrap();
+        assert!(!is_binary_record(&record, false));
+        assert_same_block(&decode_block(&record).unwrap(), &block);
+    }
+
+    #[test]
+    fn malformed_records_are_refused() {
+        let record = encode_block(&signed_block(), false).unwrap();
+        assert!(decode_block(&record[..record.len() - 1]).is_err());
+        assert!(matches!(
+            decode_block(&[0xff]),
+            Err(Error::Serialization(_))
+        ));
+        assert!(decode_block(&[]).is_err());
+    }
+
+    #[test]
+    fn hex_round_trip() {
+        let bytes = [0x00, 0x7f, 0x80, 0xff];
+        assert_eq!(encode_hex(&bytes), "007f80ff");
+        assert_eq!(decode_hex("007f80ff").unwrap(), bytes);
+        assert_eq!(decode_hex("007F80FF").unwrap(), bytes);
+        assert!(decode_hex("0").is_none());
+        assert!(decode_hex("zz").is_none());
+    }
+
+    #[test]
+    fn compressed_record_beyond_the_limit_is_refused() {
+        let block = block(100);
+        let record = encode_block(&block, true).unwrap();
+        let mut payload = Vec::new();
+        block.encode(&mut payload);
+
+        assert!(decode_block_within(&record, payload.len()).is_ok());
+        assert!(matches!(
+            decode_block_within(&record, payload.len() - 1),
+            Err(Error::Serialization(_))
+        ));
+    }
+}
@@ -0,0 +1,419 @@
+use crate::{block::GENESIS_BLOCK_HASH, client::SignatureScheme};
+use std::{
+    fmt,
+    path::{Path, PathBuf},
+    str::FromStr,
+    time::Duration,
+};
+
+pub const DEFAULT_DATA_DIR: &str = "db";
+
+/// The network a blockchain belongs to.
+///
+/// Every network keeps its own independent chain inside the data directory.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Network {
+    Mainnet,
+    Testnet,
+    Regtest,
+}
+
+impl Network {
+    /// This method returns the name of the network, also used as its directory name.
+    pub fn name(&self) -> &'static str {
+        match self {
+            Network::Mainnet => "mainnet",
+            Network::Testnet => "testnet",
+            Network::Regtest => "regtest",
+        }
+    }
+
+    /// This method returns the default mining difficulty of the network.
+    pub fn default_difficulty(&self) -> usize {
+        match self {
+            Network::Mainnet | Network::Testnet => 2,
+            Network::Regtest => 1,
+        }
+    }
+
+    /// This method returns the number of blocks after which a coinbase reward can be spent
+    /// on the network.
+    pub fn default_coinbase_maturity(&self) -> usize {
+        match self {
+            Network::Mainnet | Network::Testnet => 10,
+            Network::Regtest => 1,
+        }
+    }
+
+    /// This method returns the version byte leading the addresses of the network, so an
+    /// address of one network cannot be paid on another.
+    pub fn address_version(&self) -> u8 {
+        match self {
+            Network::Mainnet => 0x00,
+            Network::Testnet => 0x6f,
+            Network::Regtest => 0x41,
+        }
+    }
+
+    /// This method returns the version byte leading the addresses of multisig policies on
+    /// the network, telling them apart from the addresses of single keys.
+    pub fn multisig_address_version(&self) -> u8 {
+        match self {
+            Network::Mainnet => 0x05,
+            Network::Testnet => 0xc4,
+            Network::Regtest => 0x3a,
+        }
+    }
+
+    /// This method returns the network whose addresses start with a version byte.
+    pub fn from_address_version(version: u8) -> Option<Self> {
+        [Network::Mainnet, Network::Testnet, Network::Regtest]
+            .into_iter()
+            .find(|network| {
+                network.address_version() == version
+                    || network.multisig_address_version() == version
+            })
+    }
+
+    /// This method returns the consensus limits on the blocks of the network.
+    pub fn default_block_limits(&self) -> BlockLimits {
+        match self {
+            Network::Mainnet | Network::Testnet => BlockLimits {
+                max_bytes: 1024 * 1024,
+                max_transactions: 5000,
+            },
+            Network::Regtest => BlockLimits {
+                max_bytes: 8 * 1024 * 1024,
+                max_transactions: 50_000,
+            },
+        }
+    }
+}
+
+impl fmt::Display for Network {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.name())
+    }
+}
+
+impl FromStr for Network {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "mainnet" => Ok(Network::Mainnet),
+            "testnet" => Ok(Network::Testnet),
+            "regtest" => Ok(Network::Regtest),
+            _ => Err(format!("Unknown network: {}", s)),
+        }
+    }
+}
+
+/// Parameters of the genesis block.
+///
+/// `previous_block_hash` contains the hash referenced by the genesis block.
+/// `reward` contains the amount paid by the genesis coinbase transaction.
+#[derive(Debug, Clone)]
+pub struct GenesisParams {
+    pub previous_block_hash: String,
+    pub reward: f64,
+}
+
+impl Default for GenesisParams {
+    fn default() -> Self {
+        Self {
+            previous_block_hash: String::from(GENESIS_BLOCK_HASH),
+            reward: 50.0,
+        }
+    }
+}
+
+/// The emission schedule of the rewards of the blocks after genesis.
+///
+/// `initial_reward` contains the reward of the first blocks.
+/// `halving_interval` contains the number of blocks after which the reward halves, `None`
+/// never halves it.
+/// `tail_emission` contains the reward the halvings never go below.
+/// `max_supply` contains the number of coins ever issued at most, genesis reward included,
+/// `None` for no limit.
+#[derive(Debug, Clone)]
+pub struct EmissionParams {
+    pub initial_reward: f64,
+    pub halving_interval: Option<usize>,
+    pub tail_emission: f64,
+    pub max_supply: Option<f64>,
+}
+
+impl Default for EmissionParams {
+    fn default() -> Self {
+        Self {
+            initial_reward: 50.0,
+            halving_interval: Some(100),
+            tail_emission: 0.0,
+            max_supply: None,
+        }
+    }
+}
+
+impl EmissionParams {
+    /// This method returns the reward of the block at `height` before the supply limit
+    /// is applied.
+    fn scheduled_reward(&self, height: usize) -> f64 {
+        let halvings = match self.halving_interval {
+            Some(interval) if interval > 0 => height / interval,
+            _ => 0,
+        };
+        // Past about a thousand halvings the reward is zero anyway
+        let reward = self.initial_reward / 2.0_f64.powi(halvings.min(2048) as i32);
+        reward.max(self.tail_emission)
+    }
+
+    /// This method returns the sum of the scheduled rewards of the blocks from height 1
+    /// up to `height`.
+    fn scheduled_issuance(&self, height: usize) -> f64 {
+        let interval = match self.halving_interval {
+            Some(interval) if interval > 0 => interval,
+            _ => return self.scheduled_reward(1) * height as f64,
+        };
+
+        let mut issued = 0.0;
+        let mut start = 1;
+        while start <= height {
+            let reward = self.scheduled_reward(start);
+            // Once the tail emission is reached the reward no longer changes
+            let end = if reward <= self.tail_emission {
+                height
+            } else {
+                ((start / interval + 1) * interval - 1).min(height)
+            };
+            issued += reward * (end - start + 1) as f64;
+            start = end + 1;
+        }
+        issued
+    }
+}
+
+/// Consensus limits on the blocks of a chain.
+///
+/// `max_bytes` contains the binary encoded size of a block at most, header included.
+/// `max_transactions` contains the number of transactions of a block at most, coinbase
+/// included.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct BlockLimits {
+    pub max_bytes: usize,
+    pub max_transactions: usize,
+}
+
+/// Limits of the mempool.
+///
+/// `max_transactions` contains the number of transactions the mempool holds at most.
+/// `max_bytes` contains the total encoded size of the transactions the mempool holds at most.
+/// `max_age` contains the age after which a pending transaction is evicted.
+/// `min_fee_bump` contains the amount by which a replacement must raise the fee of the
+/// transaction it replaces.
+#[derive(Debug, Clone)]
+pub struct MempoolLimits {
+    pub max_transactions: usize,
+    pub max_bytes: usize,
+    pub max_age: Duration,
+    pub min_fee_bump: f64,
+}
+
+impl Default for MempoolLimits {
+    fn default() -> Self {
+        Self {
+            max_transactions: 5000,
+            max_bytes: 1024 * 1024,
+            max_age: Duration::from_secs(14 * 24 * 60 * 60),
+            min_fee_bump: 0.001,
+        }
+    }
+}
+
+/// Configuration of a blockchain.
+///
+/// `data_dir` contains the directory under which every network stores its chain.
+/// `network` contains the network of the chain.
+/// `genesis` contains the parameters of the genesis block.
+/// `emission` contains the emission schedule of the block rewards.
+/// `difficulty` contains the number of leading zeros required in a block hash.
+/// `block_limits` contains the limits on the size of blocks.
+/// `coinbase_maturity` contains the number of blocks after which a coinbase reward can be
+/// spent: the reward of block `h` can be spent from block `h + coinbase_maturity` on.
+/// `snapshot_interval` contains the number of blocks between automatic state snapshots.
+/// `prune_depth` contains the depth after which block bodies are deleted, `None` keeps
+/// every block. Pruning requires a `snapshot_interval`.
+/// `compress_blocks` contains whether stored blocks are deflate compressed.
+/// `signature_scheme` contains the scheme the transactions of the wallet are signed with.
+/// `mempool` contains the limits of the mempool.
+/// `signature_cache_size` contains the number of transactions whose valid signatures are
+/// remembered, so they are not checked again when their block arrives.
+/// `max_future_drift` contains how far ahead of the clock a block time may be, and how far
+/// ahead of its block a transaction time may be.
+#[derive(Debug, Clone)]
+pub struct BlockchainConfig {
+    pub data_dir: PathBuf,
+    pub network: Network,
+    pub genesis: GenesisParams,
+    pub emission: EmissionParams,
+    pub difficulty: usize,
+    pub block_limits: BlockLimits,
+    pub coinbase_maturity: usize,
+    pub snapshot_interval: Option<usize>,
+    pub prune_depth: Option<usize>,
+    pub compress_blocks: bool,
+    pub signature_scheme: SignatureScheme,
+    pub mempool: MempoolLimits,
+    pub signature_cache_size: usize,
+    pub max_future_drift: Duration,
+}
+
+impl BlockchainConfig {
+    /// This method creates a new configuration with the defaults of the network.
+    pub fn new<P: AsRef<Path>>(data_dir: P, network: Network) -> Self {
+        Self {
+            data_dir: data_dir.as_ref().to_path_buf(),
+            network,
+            genesis: GenesisParams::default(),
+            emission: EmissionParams::default(),
+            difficulty: network.default_difficulty(),
+            block_limits: network.default_block_limits(),
+            coinbase_maturity: network.default_coinbase_maturity(),
+            snapshot_interval: Some(100),
+            prune_depth: None,
+            compress_blocks: false,
+            signature_scheme: SignatureScheme::Ecdsa,
+            mempool: MempoolLimits::default(),
+            signature_cache_size: 20_000,
+            max_future_drift: Duration::from_secs(2 * 60 * 60),
+        }
+    }
+
+    /// This method returns the number of coins issued by the blocks up to `height`, genesis
+    /// included.
+    pub fn supply(&self, height: usize) -> f64 {
+        let supply = self.genesis.reward + self.emission.scheduled_issuance(height);
+        match self.emission.max_supply {
+            Some(max_supply) => supply.min(max_supply),
+            None => supply,
+        }
+    }
+
+    /// This method returns the reward of the block at `height`, which is cut down to what
+    /// is left below the maximum supply.
+    pub fn block_reward(&self, height: usize) -> f64 {
+        if height == 0 {
+            return self.genesis.reward;
+        }
+
+        let reward = self.emission.scheduled_reward(height);
+        match self.emission.max_supply {
+            Some(max_supply)
+                if self.genesis.reward + self.emission.scheduled_issuance(height) > max_supply =>
+            {
+                (max_supply - self.supply(height - 1)).max(0.0)
+            }
+            _ => reward,
+        }
+    }
+
+    /// This method returns the directory holding the chain of the configured network.
+    pub fn chain_dir(&self) -> PathBuf {
+        self.data_dir.join(self.network.name())
+    }
+
+    pub fn blocks_path(&self) -> PathBuf {
+        self.chain_dir().join("_blocks")
+    }
+
+    pub fn blocks_metadata_path(&self) -> PathBuf {
+        self.chain_dir().join("_blocks_metadata")
+    }
+
+    pub fn balances_path(&self) -> PathBuf {
+        self.chain_dir().join("_balances")
+    }
+
+    pub fn wallet_path(&self) -> PathBuf {
+        self.chain_dir().join("_wallets")
+    }
+
+    pub fn mempool_path(&self) -> PathBuf {
+        self.chain_dir().join("_mempool.json")
+    }
+
+    pub fn snapshots_dir(&self) -> PathBuf {
+        self.chain_dir().join("_snapshots")
+    }
+
+    pub fn snapshot_path(&self, height: usize) -> PathBuf {
+        self.snapshots_dir()
+            .join(format!("snapshot_{}.json", height))
+    }
+}
+
+impl Default for BlockchainConfig {
+    fn default() -> Self {
+        Self::new(DEFAULT_DATA_DIR, Network::Mainnet)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn config(emission: EmissionParams) -> BlockchainConfig {
+        let mut config = BlockchainConfig::new("unused", Network::Regtest);
+        config.emission = emission;
+        config
+    }
+
+    fn issued(config: &BlockchainConfig, height: usize) -> f64 {
+        (0..=height).map(|height| config.block_reward(height)).sum()
+    }
+
+    #[test]
+    fn reward_halves_every_interval() {
+        let config = config(EmissionParams::default());
+        assert_eq!(config.block_reward(0), 50.0);
+        assert_eq!(config.block_reward(1), 50.0);
+        assert_eq!(config.block_reward(99), 50.0);
+        assert_eq!(config.block_reward(100), 25.0);
+        assert_eq!(config.block_reward(250), 12.5);
+        assert_eq!(config.block_reward(100 * 5000), 0.0);
+    }
+
+    #[test]
+    fn supply_is_the_sum_of_the_rewards() {
+        let config = config(EmissionParams::default());
+        for height in [0, 1, 99, 100, 101, 350] {
+            assert!((config.supply(height) - issued(&config, height)).abs() < 1e-9);
+        }
+    }
+
+    #[test]
+    fn tail_emission_is_a_floor() {
+        let config = config(EmissionParams {
+            tail_emission: 10.0,
+            ..EmissionParams::default()
+        });
+        assert_eq!(config.block_reward(250), 12.5);
+        assert_eq!(config.block_reward(300), 10.0);
+        assert_eq!(config.block_reward(100_000), 10.0);
+        assert!((config.supply(1000) - issued(&config, 1000)).abs() < 1e-9);
+    }
+
+    #[test]
+    fn max_supply_caps_the_rewards() {
+        let config = config(EmissionParams {
+            halving_interval: None,
+            max_supply: Some(175.0),
+            ..EmissionParams::default()
+        });
+        assert_eq!(config.block_reward(2), 50.0);
+        assert_eq!(config.block_reward(3), 25.0);
+        assert_eq!(config.block_reward(4), 0.0);
+        assert_eq!(config.supply(10), 175.0);
+        assert_eq!(issued(&config, 10), 175.0);
+    }
+}
@@ -0,0 +1,184 @@
+use std::fmt;
+
+/// A specialized `Result` type for blockchain operations.
+pub type Result<T> = std::result::Result<T, Error>;
+
+/// The error type returned by every fallible blockchain operation.
+///
+/// `Storage` contains a failure of the underlying RocksDB store or missing stored data.
+/// `UnsupportedSchema` contains the version of a database written by a newer binary.
+/// `Io` contains a failure to access a file on disk.
+/// `Serialization` contains data that could not be encoded or decoded.
+/// `Crypto` contains an invalid key or signature.
+/// `Mining` contains a failure to find a valid proof of work.
+/// `Wallet` contains a failure to use the wallet, such as a wrong passphrase or a locked
+/// wallet.
+/// `Consensus` contains a block that breaks the consensus rules.
+/// `Validation` contains a transaction that failed validation, with the block and
+/// position it was found at when known.
+#[derive(Debug)]
+pub enum Error {
+    Storage(String),
+    UnsupportedSchema {
+        found: u32,
+        supported: u32,
+    },
+    Io(std::io::Error),
+    Serialization(String),
+    Crypto(secp256k1::Error),
+    Mining(String),
+    Wallet(String),
+    Consensus {
+        block_hash: String,
+        reason: String,
+    },
+    Validation {
+        block_hash: Option<String>,
+        tx_index: Option<usize>,
+        reason: String,
+    },
+}
+
+impl Error {
+    /// This method creates a consensus error for the given block.
+    pub fn consensus<S: Into<String>>(block_hash: &str, reason: S) -> Self {
+        Error::Consensus {
+            block_hash: block_hash.to_string(),
+            reason: reason.into(),
+        }
+    }
+
+    /// This method creates a validation error without any context.
+    pub fn validation<S: Into<String>>(reason: S) -> Self {
+        Error::Validation {
+            block_hash: None,
+            tx_index: None,
+            reason: reason.into(),
+        }
+    }
+
+    /// This method attaches the hash of the block being processed to a validation error.
+    pub fn in_block(self, hash: &str) -> Self {
+        match self {
+            Error::Validation {
+                block_hash: None,
+                tx_index,
+                reason,
+            } => Error::Validation {
+                block_hash: Some(hash.to_string()),
+                tx_index,
+                reason,
+            },
+            error => error,
+        }
+    }
+
+    /// This method attaches the index of the transaction being processed to a validation error.
+    pub fn at_tx(self, index: usize) -> Self {
+        match self {
+            Error::Validation {
+                block_hash,
+                tx_index: None,
+                reason,
+            } => Error::Validation {
+                block_hash,
+                tx_index: Some(index),
+                reason,
+            },
+            error => error,
+        }
+    }
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Error::Storage(e) => write!(f, "Storage error: {}", e),
+            Error::UnsupportedSchema { found, supported } => write!(
+                f,
+                "Database schema version {} is newer than the supported version {}, a newer binary is required",
+                found, supported
+            ),
+            Error::Io(e) => write!(f, "I/O error: {}", e),
+            Error::Serialization(e) => write!(f, "Serialization error: {}", e),
+            Error::Crypto(e) => write!(f, "Crypto error: {}", e),
+            Error::Mining(reason) => write!(f, "Mining failed: {}", reason),
+            Error::Wallet(reason) => write!(f, "Wallet error: {}", reason),
+            Error::Consensus { block_hash, reason } => {
+                write!(f, "Block {} verification failed: {}", block_hash, reason)
+            }
+            Error::Validation {
+                block_hash,
+                tx_index,
+                reason,
+            } => {
+                write!(f, "Transaction verification failed")?;
+                if let Some(index) = tx_index {
+                    write!(f, " at index {}", index)?;
+                }
+                if let Some(hash) = block_hash {
+                    write!(f, " in block {}", hash)?;
+                }
+                write!(f, ": {}", reason)
+            }
+        }
+    }
+}
+
+impl std::error::Error for Error {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            Error::Io(e) => Some(e),
+            Error::Crypto(e) => Some(e),
+            _ => None,
+        }
+    }
+}
+
+impl From<rocksdb::Error> for Error {
+    fn from(e: rocksdb::Error) -> Self {
+        Error::Storage(e.into_string())
+    }
+}
+
+impl From<std::io::Error> for Error {
+    fn from(e: std::io::Error) -> Self {
+        Error::Io(e)
+    }
+}
+
+impl From<secp256k1::Error> for Error {
+    fn from(e: secp256k1::Error) -> Self {
+        Error::Crypto(e)
+    }
+}
+
+impl From<serde_json::Error> for Error {
+    fn from(e: serde_json::Error) -> Self {
+        Error::Serialization(e.to_string())
+    }
+}
+
+impl From<std::string::FromUtf8Error> for Error {
+    fn from(e: std::string::FromUtf8Error) -> Self {
+        Error::Serialization(e.to_string())
+    }
+}
+
+impl From<std::str::Utf8Error> for Error {
+    fn from(e: std::str::Utf8Error) -> Self {
+        Error::Serialization(e.to_string())
+    }
+}
+
+impl From<std::num::ParseIntError> for Error {
+    fn from(e: std::num::ParseIntError) -> Self {
+        Error::Serialization(e.to_string())
+    }
+}
+
+impl From<std::num::ParseFloatError> for Error {
+    fn from(e: std::num::ParseFloatError) -> Self {
+        Error::Serialization(e.to_string())
+    }
+}
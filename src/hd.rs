@@ -0,0 +1,236 @@
+use crate::{
+    client::{rand::RngCore, All, PublicKey, Secp256k1, SecretKey},
+    error::{Error, Result},
+};
+use bip39::Mnemonic;
+use hmac::{Hmac, Mac};
+use secp256k1::Scalar;
+use sha2::Sha512;
+
+/// The offset of hardened child indexes, whose keys cannot be derived from the parent
+/// public key.
+pub const HARDENED: u32 = 0x8000_0000;
+/// The number of bytes of entropy of a new mnemonic, encoded as 12 words.
+const MNEMONIC_ENTROPY_LEN: usize = 16;
+
+/// A secret key able to derive child keys, following BIP32.
+///
+/// `secret_key` contains the secret key of the node.
+/// `chain_code` contains the extra entropy mixed into the derivation of its children.
+#[derive(Debug, Clone, Copy)]
+pub struct ExtendedSecretKey {
+    pub secret_key: SecretKey,
+    pub chain_code: [u8; 32],
+}
+
+/// A public key able to derive the public keys of non-hardened children, following BIP32.
+///
+/// `public_key` contains the public key of the node.
+/// `chain_code` contains the extra entropy mixed into the derivation of its children.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ExtendedPublicKey {
+    pub public_key: PublicKey,
+    pub chain_code: [u8; 32],
+}
+
+impl ExtendedSecretKey {
+    /// This method derives the master key of a seed.
+    pub fn from_seed(seed: &[u8]) -> Result<Self> {
+        let (key, chain_code) = hmac_sha512(b"Bitcoin seed", &[seed]);
+        Ok(Self {
+            secret_key: SecretKey::from_slice(&key)?,
+            chain_code,
+        })
+    }
+
+    /// This method derives the child key at `index`, hardened when `index` is at least
+    /// `HARDENED`.
+    pub fn derive_child(&self, secp: &Secp256k1<All>, index: u32) -> Result<Self> {
+        let (tweak, chain_code) = if index >= HARDENED {
+            hmac_sha512(
+                &self.chain_code,
+                &[&[0], &self.secret_key.secret_bytes(), &index.to_be_bytes()],
+            )
+        } else {
+            let public_key = PublicKey::from_secret_key(secp, &self.secret_key);
+            hmac_sha512(
+                &self.chain_code,
+                &[&public_key.serialize(), &index.to_be_bytes()],
+            )
+        };
+
+        Ok(Self {
+            secret_key: self.secret_key.add_tweak(&scalar(tweak)?)?,
+            chain_code,
+        })
+    }
+
+    /// This method derives the key at the end of a path of child indexes.
+    pub fn derive_path(&self, secp: &Secp256k1<All>, path: &[u32]) -> Result<Self> {
+        path.iter()
+            .try_fold(*self, |key, index| key.derive_child(secp, *index))
+    }
+
+    pub fn public_key(&self, secp: &Secp256k1<All>) -> ExtendedPublicKey {
+        ExtendedPublicKey {
+            public_key: PublicKey::from_secret_key(secp, &self.secret_key),
+            chain_code: self.chain_code,
+        }
+    }
+}
+
+impl ExtendedPublicKey {
+    /// This method derives the public key of the non-hardened child at `index`, matching
+    /// the public key of the child derived from the secret key.
+    pub fn derive_child(&self, secp: &Secp256k1<All>, index: u32) -> Result<Self> {
+        if index >= HARDENED {
+            return Err(Error::Wallet(String::from(
+                "Hardened keys cannot be derived from a public key",
+            )));
+        }
+        let (tweak, chain_code) = hmac_sha512(
+            &self.chain_code,
+            &[&self.public_key.serialize(), &index.to_be_bytes()],
+        );
+
+        Ok(Self {
+            public_key: self.public_key.add_exp_tweak(secp, &scalar(tweak)?)?,
+            chain_code,
+        })
+    }
+}
+
+/// This method creates a mnemonic from random entropy.
+pub fn generate_mnemonic() -> Mnemonic {
+    let mut entropy = [0; MNEMONIC_ENTROPY_LEN];
+    crate::client::rand::rngs::OsRng {}.fill_bytes(&mut entropy);
+    Mnemonic::from_entropy(&entropy).expect("the entropy length is valid")
+}
+
+/// This method parses the words of a mnemonic, checking their checksum.
+pub fn parse_mnemonic(words: &str) -> Result<Mnemonic> {
+    Mnemonic::parse(words).map_err(|e| Error::Wallet(format!("Mnemonic is not valid: {}", e)))
+}
+
+/// This method computes HMAC-SHA512 over the concatenation of `data`, split into the
+/// left and right halves BIP32 uses as key material and chain code.
+fn hmac_sha512(key: &[u8], data: &[&[u8]]) -> ([u8; 32], [u8; 32]) {
+    let mut mac = Hmac::<Sha512>::new_from_slice(key).expect("HMAC accepts keys of any length");
+    for part in data {
+        mac.update(part);
+    }
+    let output = mac.finalize().into_bytes();
+
+    let mut left = [0; 32];
+    let mut right = [0; 32];
+    left.copy_from_slice(&output[..32]);
+    right.copy_from_slice(&output[32..]);
+    (left, right)
+}
+
+/// This method reads the key material of a derivation as a scalar. BIP32 skips the
+/// vanishingly rare indexes where it is out of range, which is surfaced as an error.
+fn scalar(bytes: [u8; 32]) -> Result<Scalar> {
+    Scalar::from_be_bytes(bytes)
+        .map_err(|_| Error::Wallet(String::from("Derived key is not valid")))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::codec;
+
+    /// BIP32 test vector 1: the path, chain code, secret key and public key of each node.
+    const VECTOR_1: [(&[u32], &str, &str, &str); 4] = [
+        (
+            &[],
+            "873dff81c02f525623fd1fe5167eac3a55a049de3d314bb42ee227ffed37d508",
+            "e8f32e723decf4051aefac8e2c93c9c5b214313817cdb01a1494b917c8436b35",
+            "0339a36013301597daef41fbe593a02cc513d0b55527ec2df1050e2e8ff49c85c2",
+        ),
+        (
+            &[HARDENED],
+            "47fdacbd0f1097043b78c63c20c34ef4ed9a111d980047ad16282c7ae6236141",
+            "edb2e14f9ee77d26dd93b4ecede8d16ed408ce149b6cd80b0715a2d911a0afea",
+            "035a784662a4a20a65bf6aab9ae98a6c068a81c52e4b032c0fb5400c706cfccc56",
+        ),
+        (
+            &[HARDENED, 1],
+            "2a7857631386ba23dacac34180dd1983734e444fdbf774041578e9b6adb37c19",
+            "3c6cb8d0f6a264c91ea8b5030fadaa8e538b020f0a387421a12de9319dc93368",
+            "03501e454bf00751f24b1b489aa925215d66af2234e3891c3b21a52bedb3cd711c",
+        ),
+        (
+            &[HARDENED, 1, HARDENED + 2],
+            "04466b9cc8e161e966409ca52986c584f07e9dc81f735db683c3ff6ec7b1503f",
+            "cbce0d719ecf7431d88e6a89fa1483e02e35092af60c042b1df2ff59fa424dca",
+            "0357bfe1e341d01c69fe5654309956cbea516822fba8a601743a012a7896ee8dc2",
+        ),
+    ];
+
+    #[test]
+    fn bip32_vector_1() {
+        let secp = Secp256k1::new();
+        let seed = codec::decode_hex("000102030405060708090a0b0c0d0e0f").unwrap();
+        let master = ExtendedSecretKey::from_seed(&seed).unwrap();
+
+        for (path, chain_code, secret_key, public_key) in VECTOR_1 {
+            let key = master.derive_path(&secp, path).unwrap();
+            assert_eq!(codec::encode_hex(&key.chain_code), chain_code);
+            assert_eq!(
+                codec::encode_hex(&key.secret_key.secret_bytes()),
+                secret_key
+            );
+            assert_eq!(key.public_key(&secp).public_key.to_string(), public_key);
+        }
+    }
+
+    #[test]
+    fn public_derivation_matches_secret_derivation() {
+        let secp = Secp256k1::new();
+        let account = ExtendedSecretKey::from_seed(&[1; 32])
+            .unwrap()
+            .derive_path(&secp, &[HARDENED + 44, HARDENED, HARDENED])
+            .unwrap();
+
+        for index in 0..3 {
+            assert_eq!(
+                account
+                    .public_key(&secp)
+                    .derive_child(&secp, index)
+                    .unwrap(),
+                account
+                    .derive_child(&secp, index)
+                    .unwrap()
+                    .public_key(&secp)
+            );
+        }
+        assert!(account
+            .public_key(&secp)
+            .derive_child(&secp, HARDENED)
+            .is_err());
+    }
+
+    #[test]
+    fn bip39_vector() {
+        let mnemonic = parse_mnemonic(
+            "abandon abandon abandon abandon abandon abandon abandon abandon abandon abandon \
+             abandon about",
+        )
+        .unwrap();
+        assert_eq!(mnemonic.to_entropy(), [0; 16]);
+        assert_eq!(
+            codec::encode_hex(&mnemonic.to_seed("TREZOR")),
+            "c55257c360c07c72029aebc1b53c05ed0362ada38ead3e3e9efa3708e53495531f09a6987599d18264c1e1c92f2cf141630c7a3c4ab7c81b2f001698e7463b04"
+        );
+    }
+
+    #[test]
+    fn mnemonic_checksum_is_checked() {
+        assert!(matches!(
+            parse_mnemonic(&["abandon"; 12].join(" ")),
+            Err(Error::Wallet(_))
+        ));
+        assert_eq!(generate_mnemonic().word_count(), 12);
+    }
+}
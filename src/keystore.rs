@@ -0,0 +1,283 @@
+use crate::{
+    client::{rand::RngCore, PublicKey, SecretKey},
+    codec,
+    error::{Error, Result},
+};
+use chacha20poly1305::{
+    aead::{Aead, KeyInit, Payload},
+    ChaCha20Poly1305, Key, Nonce,
+};
+use serde::{Deserialize, Serialize};
+
+/// The version of the keystore format written by this binary.
+const KEYSTORE_VERSION: u32 = 1;
+const SALT_LEN: usize = 16;
+const NONCE_LEN: usize = 12;
+const KEY_LEN: usize = 32;
+/// The data authenticated together with the entropy of a seed.
+const SEED_AAD: &[u8] = b"seed";
+
+/// Parameters of the scrypt key derivation.
+///
+/// `log_n` contains the base 2 logarithm of the CPU/memory cost.
+/// `r` contains the block size.
+/// `p` contains the parallelization.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct KdfParams {
+    pub log_n: u8,
+    pub r: u32,
+    pub p: u32,
+}
+
+impl Default for KdfParams {
+    fn default() -> Self {
+        Self {
+            log_n: 15,
+            r: 8,
+            p: 1,
+        }
+    }
+}
+
+/// A secret key encrypted with a key derived from a passphrase.
+///
+/// `version` contains the version of the keystore format.
+/// `public_key` contains the public key of the secret key, readable without the passphrase.
+/// `kdf` contains the parameters deriving the encryption key from the passphrase.
+/// `salt` contains the random salt of the key derivation, hex encoded.
+/// `nonce` contains the random nonce of the encryption, hex encoded.
+/// `ciphertext` contains the secret key encrypted with ChaCha20-Poly1305 and authenticated
+/// together with the public key, hex encoded.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Keystore {
+    pub version: u32,
+    pub public_key: PublicKey,
+    pub kdf: KdfParams,
+    salt: String,
+    nonce: String,
+    ciphertext: String,
+}
+
+impl Keystore {
+    /// This method encrypts a secret key with a passphrase using random salt and nonce.
+    pub fn encrypt(
+        secret_key: &SecretKey,
+        public_key: PublicKey,
+        passphrase: &str,
+        kdf: KdfParams,
+    ) -> Result<Self> {
+        let sealed = Sealed::seal(
+            &secret_key.secret_bytes(),
+            &public_key.serialize(),
+            passphrase,
+            kdf,
+        )?;
+        Ok(Self {
+            version: KEYSTORE_VERSION,
+            public_key,
+            kdf,
+            salt: sealed.salt,
+            nonce: sealed.nonce,
+            ciphertext: sealed.ciphertext,
+        })
+    }
+
+    /// This method decrypts the secret key with a passphrase.
+    ///
+    /// A wrong passphrase and a tampered keystore both fail authentication and cannot be
+    /// told apart.
+    pub fn decrypt(&self, passphrase: &str) -> Result<SecretKey> {
+        check_version(self.version)?;
+        let secret = open(
+            &self.salt,
+            &self.nonce,
+            &self.ciphertext,
+            &self.public_key.serialize(),
+            passphrase,
+            self.kdf,
+        )?;
+        Ok(SecretKey::from_slice(&secret)?)
+    }
+}
+
+/// The entropy of the mnemonic of a wallet seed, encrypted with a key derived from a
+/// passphrase.
+///
+/// `version` contains the version of the keystore format.
+/// `kdf` contains the parameters deriving the encryption key from the passphrase.
+/// `salt` contains the random salt of the key derivation, hex encoded.
+/// `nonce` contains the random nonce of the encryption, hex encoded.
+/// `ciphertext` contains the entropy encrypted with ChaCha20-Poly1305, hex encoded.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SeedKeystore {
+    pub version: u32,
+    pub kdf: KdfParams,
+    salt: String,
+    nonce: String,
+    ciphertext: String,
+}
+
+impl SeedKeystore {
+    /// This method encrypts the entropy of a mnemonic with a passphrase using random salt
+    /// and nonce.
+    pub fn encrypt(entropy: &[u8], passphrase: &str, kdf: KdfParams) -> Result<Self> {
+        let sealed = Sealed::seal(entropy, SEED_AAD, passphrase, kdf)?;
+        Ok(Self {
+            version: KEYSTORE_VERSION,
+            kdf,
+            salt: sealed.salt,
+            nonce: sealed.nonce,
+            ciphertext: sealed.ciphertext,
+        })
+    }
+
+    /// This method decrypts the entropy of the mnemonic with a passphrase.
+    pub fn decrypt(&self, passphrase: &str) -> Result<Vec<u8>> {
+        check_version(self.version)?;
+        open(
+            &self.salt,
+            &self.nonce,
+            &self.ciphertext,
+            SEED_AAD,
+            passphrase,
+            self.kdf,
+        )
+    }
+}
+
+/// The hex encoded output of an encryption.
+struct Sealed {
+    salt: String,
+    nonce: String,
+    ciphertext: String,
+}
+
+impl Sealed {
+    /// This method encrypts and authenticates `secret` together with `aad`, using a key
+    /// derived from a passphrase with random salt and nonce.
+    fn seal(secret: &[u8], aad: &[u8], passphrase: &str, kdf: KdfParams) -> Result<Self> {
+        let mut rng = crate::client::rand::rngs::OsRng {};
+        let mut salt = [0; SALT_LEN];
+        rng.fill_bytes(&mut salt);
+        let mut nonce = [0; NONCE_LEN];
+        rng.fill_bytes(&mut nonce);
+
+        let cipher = ChaCha20Poly1305::new(&derive_key(passphrase, &salt, kdf)?);
+        let ciphertext = cipher
+            .encrypt(Nonce::from_slice(&nonce), Payload { msg: secret, aad })
+            .map_err(|_| Error::Wallet(String::from("Failed to encrypt the secret key")))?;
+
+        Ok(Self {
+            salt: codec::encode_hex(&salt),
+            nonce: codec::encode_hex(&nonce),
+            ciphertext: codec::encode_hex(&ciphertext),
+        })
+    }
+}
+
+fn check_version(version: u32) -> Result<()> {
+    if version != KEYSTORE_VERSION {
+        return Err(Error::Wallet(format!(
+            "Unsupported keystore version {}",
+            version
+        )));
+    }
+    Ok(())
+}
+
+/// This method decrypts a secret sealed together with `aad`.
+fn open(
+    salt: &str,
+    nonce: &str,
+    ciphertext: &str,
+    aad: &[u8],
+    passphrase: &str,
+    kdf: KdfParams,
+) -> Result<Vec<u8>> {
+    let salt = decode_field(salt, "salt")?;
+    let nonce = decode_field(nonce, "nonce")?;
+    if nonce.len() != NONCE_LEN {
+        return Err(Error::Wallet(String::from("Keystore nonce is not valid")));
+    }
+    let ciphertext = decode_field(ciphertext, "ciphertext")?;
+
+    let cipher = ChaCha20Poly1305::new(&derive_key(passphrase, &salt, kdf)?);
+    cipher
+        .decrypt(
+            Nonce::from_slice(&nonce),
+            Payload {
+                msg: &ciphertext,
+                aad,
+            },
+        )
+        .map_err(|_| Error::Wallet(String::from("Wrong passphrase")))
+}
+
+/// This method derives the encryption key from a passphrase with scrypt.
+fn derive_key(passphrase: &str, salt: &[u8], kdf: KdfParams) -> Result<Key> {
+    let params = scrypt::Params::new(kdf.log_n, kdf.r, kdf.p, KEY_LEN)
+        .map_err(|_| Error::Wallet(String::from("Keystore key derivation is not valid")))?;
+    let mut key = Key::default();
+    scrypt::scrypt(passphrase.as_bytes(), salt, &params, &mut key)
+        .map_err(|_| Error::Wallet(String::from("Keystore key derivation is not valid")))?;
+    Ok(key)
+}
+
+fn decode_field(hex: &str, name: &str) -> Result<Vec<u8>> {
+    codec::decode_hex(hex).ok_or_else(|| Error::Wallet(format!("Keystore {} is not valid", name)))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::client::Client;
+
+    /// Cheap parameters, the derivation cost is irrelevant to the format.
+    const TEST_KDF: KdfParams = KdfParams {
+        log_n: 4,
+        r: 8,
+        p: 1,
+    };
+
+    #[test]
+    fn keystore_round_trip() {
+        let client = Client::new();
+        let keystore =
+            Keystore::encrypt(&client.secret_key, client.public_key, "pw", TEST_KDF).unwrap();
+        let json = serde_json::to_string(&keystore).unwrap();
+        let keystore: Keystore = serde_json::from_str(&json).unwrap();
+
+        assert_eq!(keystore.public_key, client.public_key);
+        assert_eq!(keystore.decrypt("pw").unwrap(), client.secret_key);
+        assert!(matches!(keystore.decrypt("wrong"), Err(Error::Wallet(_))));
+    }
+
+    #[test]
+    fn keystore_is_bound_to_its_public_key() {
+        let client = Client::new();
+        let mut keystore =
+            Keystore::encrypt(&client.secret_key, client.public_key, "pw", TEST_KDF).unwrap();
+        keystore.public_key = Client::new().public_key;
+
+        assert!(keystore.decrypt("pw").is_err());
+    }
+
+    #[test]
+    fn seed_keystore_round_trip() {
+        let entropy = [7u8; 16];
+        let keystore = SeedKeystore::encrypt(&entropy, "", TEST_KDF).unwrap();
+        let json = serde_json::to_string(&keystore).unwrap();
+        let keystore: SeedKeystore = serde_json::from_str(&json).unwrap();
+
+        assert_eq!(keystore.decrypt("").unwrap(), entropy);
+        assert!(keystore.decrypt("pw").is_err());
+    }
+
+    #[test]
+    fn unknown_version_is_refused() {
+        let mut keystore = SeedKeystore::encrypt(&[7u8; 16], "", TEST_KDF).unwrap();
+        keystore.version = KEYSTORE_VERSION + 1;
+
+        assert!(matches!(keystore.decrypt(""), Err(Error::Wallet(_))));
+    }
+}
@@ -1,5 +1,20 @@
-pub mod client;
-pub mod transaction;
+pub mod address;
 pub mod block;
 pub mod blockchain;
-pub mod storage;
\ No newline at end of file
+pub mod blockfile;
+pub mod client;
+pub mod clock;
+pub mod codec;
+pub mod config;
+pub mod error;
+pub mod hd;
+pub mod keystore;
+pub mod mempool;
+pub mod migration;
+pub mod multisig;
+pub mod offline;
+pub mod sigcache;
+pub mod snapshot;
+pub mod storage;
+pub mod transaction;
+pub mod wallet;
@@ -1,37 +1,166 @@
 use clap::{App, Arg, SubCommand};
 use secp256k1::PublicKey;
-use simple_blockchain_rs::{blockchain::Blockchain, client::Client, storage};
+use simple_blockchain_rs::{
+    address::Address,
+    blockchain::{Blockchain, DropReason},
+    client::{Client, SignatureScheme},
+    config::{BlockchainConfig, Network, DEFAULT_DATA_DIR},
+    multisig::Multisig,
+    offline::OfflineTransaction,
+    snapshot::Snapshot,
+    storage,
+    transaction::{Receiver, Sender},
+};
 use std::io::{self, Write};
+use std::path::Path;
 use std::str::FromStr;
 
-fn main() -> std::io::Result<()> {
-    let mut blockchain = Blockchain::new();
-    let latest_block = blockchain
-        .start_blockchain()
-        .expect("Blockchain failed to start");
-    println!("Blockchain started");
-    println!("Latest block: {:#?}", latest_block);
-    println!("Your public key is: {}", blockchain.client.identify());
+/// This method parses the arguments of the `export` command: `[--from H] [--to H] <file>`.
+fn parse_export_args<'a>(
+    mut args: impl Iterator<Item = &'a str>,
+) -> Option<(usize, Option<usize>, &'a str)> {
+    let mut from = 0;
+    let mut to = None;
+    let mut path = None;
+
+    while let Some(arg) = args.next() {
+        match arg {
+            "--from" => from = usize::from_str(args.next()?).ok()?,
+            "--to" => to = Some(usize::from_str(args.next()?).ok()?),
+            _ if path.is_none() => path = Some(arg),
+            _ => return None,
+        }
+    }
+
+    Some((from, to, path?))
+}
+
+/// The optional arguments of the `send` command.
+///
+/// `fee` contains the fee paid to the miner.
+/// `ttl` contains the number of blocks the transaction is allowed in.
+/// `from` contains the account sending the transaction, the active account when `None`.
+struct SendArgs<'a> {
+    fee: f64,
+    ttl: Option<usize>,
+    from: Option<&'a str>,
+}
+
+/// This method parses the optional arguments of the `send` command:
+/// `[fee] [--ttl N] [--from <account>]`.
+fn parse_send_args<'a>(mut args: impl Iterator<Item = &'a str>) -> Option<SendArgs<'a>> {
+    let mut fee = None;
+    let mut ttl = None;
+    let mut from = None;
+
+    while let Some(arg) = args.next() {
+        match arg {
+            "--ttl" => ttl = Some(usize::from_str(args.next()?).ok().filter(|ttl| *ttl > 0)?),
+            "--from" => from = Some(args.next()?),
+            _ if fee.is_none() => fee = Some(f64::from_str(arg).ok()?),
+            _ => return None,
+        }
+    }
+
+    Some(SendArgs {
+        fee: fee.unwrap_or(0.0),
+        ttl,
+        from,
+    })
+}
+
+/// This method prints a prompt and reads the line typed in answer, without its line ending.
+fn prompt_line(prompt: &str) -> io::Result<String> {
+    print!("{}", prompt);
+    io::stdout().flush()?;
+
+    let mut line = String::new();
+    io::stdin().read_line(&mut line)?;
+    Ok(line.trim_end_matches(['\r', '\n']).to_string())
+}
 
-    let _ = App::new("Simple Blockchain")
+fn main() -> Result<(), Box<dyn std::error::Error>> {
+    let matches = App::new("Simple Blockchain")
         .version("0.1.2")
         .author("Utsav Balar <utsavbalar1231@gmail.com>")
         .about("A simple blockchain implementation in Rust")
+        .arg(
+            Arg::with_name("datadir")
+                .long("datadir")
+                .value_name("DIR")
+                .help("The directory where the chain data is stored")
+                .takes_value(true)
+                .default_value(DEFAULT_DATA_DIR),
+        )
+        .arg(
+            Arg::with_name("network")
+                .long("network")
+                .value_name("NETWORK")
+                .help("The network to use")
+                .takes_value(true)
+                .possible_values(["mainnet", "testnet", "regtest"])
+                .default_value("mainnet"),
+        )
+        .arg(
+            Arg::with_name("compress-blocks")
+                .long("compress-blocks")
+                .help("Compress the stored blocks"),
+        )
+        .arg(
+            Arg::with_name("signature-scheme")
+                .long("signature-scheme")
+                .value_name("SCHEME")
+                .help("Sign the transactions of the wallet with ECDSA or BIP340 Schnorr")
+                .takes_value(true)
+                .possible_values(["ecdsa", "schnorr"])
+                .default_value("ecdsa"),
+        )
+        .arg(
+            Arg::with_name("prune")
+                .long("prune")
+                .value_name("N")
+                .help("Delete block bodies deeper than N blocks")
+                .takes_value(true),
+        )
         .subcommand(
             SubCommand::with_name("pubkey").about("Create a new client and print its public key"),
         )
         .subcommand(
             SubCommand::with_name("balance")
-                .about("Get the balance of a client")
+                .about("Get the balance of an address")
                 .arg(
-                    Arg::with_name("public_key")
-                        .help("The public key of the client")
+                    Arg::with_name("address")
+                        .help("The address of the account")
                         .required(true)
                         .index(1),
                 ),
         )
         .subcommand(SubCommand::with_name("mine").about("Mine a block"))
-        .subcommand(SubCommand::with_name("mempool").about("Print the transactions in the mempool"))
+        .subcommand(
+            SubCommand::with_name("supply")
+                .about("Print the number of coins issued up to a height")
+                .arg(
+                    Arg::with_name("height")
+                        .help("The height, the latest block when omitted")
+                        .index(1),
+                ),
+        )
+        .subcommand(
+            SubCommand::with_name("mempool")
+                .about("Print the transactions in the mempool")
+                .arg(
+                    Arg::with_name("txid")
+                        .help("Only print the transaction with this txid")
+                        .index(1),
+                )
+                .arg(
+                    Arg::with_name("sender")
+                        .long("sender")
+                        .value_name("SENDER")
+                        .help("Only print the transactions of this public key or multisig policy")
+                        .takes_value(true),
+                ),
+        )
         .subcommand(
             SubCommand::with_name("send")
                 .about("Create a new transaction and print it")
@@ -40,7 +169,7 @@ fn main() -> std::io::Result<()> {
                         .short('r')
                         .long("receiver")
                         .value_name("RECEIVER")
-                        .help("The receiver of the transaction")
+                        .help("The address of the receiver of the transaction")
                         .takes_value(true)
                         .required(true),
                 )
@@ -52,10 +181,380 @@ fn main() -> std::io::Result<()> {
                         .help("The amount of the transaction")
                         .takes_value(true)
                         .required(true),
+                )
+                .arg(
+                    Arg::with_name("fee")
+                        .short('f')
+                        .long("fee")
+                        .value_name("FEE")
+                        .help("The fee paid to the miner")
+                        .takes_value(true)
+                        .default_value("0"),
+                )
+                .arg(
+                    Arg::with_name("ttl")
+                        .long("ttl")
+                        .value_name("N")
+                        .help("Only allow the transaction in the next N blocks")
+                        .takes_value(true),
+                )
+                .arg(
+                    Arg::with_name("from")
+                        .long("from")
+                        .value_name("ACCOUNT")
+                        .help(
+                            "The wallet account sending the transaction, the active one by default",
+                        )
+                        .takes_value(true),
+                ),
+        )
+        .subcommand(
+            SubCommand::with_name("multisig")
+                .about("Print the address of a policy letting any N of the given keys spend")
+                .arg(
+                    Arg::with_name("threshold")
+                        .help("The number of signatures needed to spend")
+                        .required(true)
+                        .index(1),
+                )
+                .arg(
+                    Arg::with_name("public_keys")
+                        .help("The public keys allowed to sign")
+                        .required(true)
+                        .multiple(true)
+                        .index(2),
+                ),
+        )
+        .subcommand(
+            SubCommand::with_name("createtx")
+                .about("Create an unsigned transaction and write it to a file to sign offline")
+                .arg(
+                    Arg::with_name("sender")
+                        .help("The public key or the multi(...) policy of the sender")
+                        .required(true)
+                        .index(1),
+                )
+                .arg(
+                    Arg::with_name("receiver")
+                        .help("The address of the receiver of the transaction")
+                        .required(true)
+                        .index(2),
+                )
+                .arg(
+                    Arg::with_name("amount")
+                        .help("The amount of the transaction")
+                        .required(true)
+                        .index(3),
+                )
+                .arg(
+                    Arg::with_name("file")
+                        .help("The transaction file to write")
+                        .required(true)
+                        .index(4),
+                )
+                .arg(
+                    Arg::with_name("fee")
+                        .help("The fee paid to the miner")
+                        .index(5),
+                )
+                .arg(
+                    Arg::with_name("ttl")
+                        .long("ttl")
+                        .value_name("N")
+                        .help("Only allow the transaction in the next N blocks")
+                        .takes_value(true),
+                ),
+        )
+        .subcommand(
+            SubCommand::with_name("signtx")
+                .about("Add the signatures of the wallet keys to a transaction file")
+                .arg(
+                    Arg::with_name("file")
+                        .help("The transaction file to sign")
+                        .required(true)
+                        .index(1),
+                ),
+        )
+        .subcommand(
+            SubCommand::with_name("sendtx")
+                .about("Combine the signatures of transaction files and broadcast the transaction")
+                .arg(
+                    Arg::with_name("files")
+                        .help("The signed copies of the transaction file")
+                        .required(true)
+                        .multiple(true)
+                        .index(1),
+                ),
+        )
+        .subcommand(
+            SubCommand::with_name("account")
+                .about("Manage the named accounts of the wallet")
+                .subcommand(
+                    SubCommand::with_name("new")
+                        .about("Create an account derived from the wallet seed")
+                        .arg(
+                            Arg::with_name("name")
+                                .help("The name of the account")
+                                .required(true)
+                                .index(1),
+                        ),
+                )
+                .subcommand(SubCommand::with_name("list").about("List the accounts"))
+                .subcommand(
+                    SubCommand::with_name("use")
+                        .about("Send from an account by default")
+                        .arg(
+                            Arg::with_name("name")
+                                .help("The name of the account")
+                                .required(true)
+                                .index(1),
+                        ),
+                )
+                .subcommand(
+                    SubCommand::with_name("coinbase")
+                        .about("Receive mining rewards on an account")
+                        .arg(
+                            Arg::with_name("name")
+                                .help("The name of the account")
+                                .required(true)
+                                .index(1),
+                        ),
+                )
+                .subcommand(
+                    SubCommand::with_name("watch")
+                        .about("Watch a public key or an address without its secret key")
+                        .arg(
+                            Arg::with_name("name")
+                                .help("The name of the watch-only account")
+                                .required(true)
+                                .index(1),
+                        )
+                        .arg(
+                            Arg::with_name("watched")
+                                .help("The public key or address to watch")
+                                .required(true)
+                                .index(2),
+                        ),
+                ),
+        )
+        .subcommand(
+            SubCommand::with_name("payments")
+                .about("List the payments received by an account and its available balance")
+                .arg(
+                    Arg::with_name("account")
+                        .help("The name of the account, the active one by default")
+                        .index(1),
+                ),
+        )
+        .subcommand(
+            SubCommand::with_name("wallet")
+                .about("Show the balances of an account and the transactions it sent or received")
+                .arg(
+                    Arg::with_name("account")
+                        .help("The name of the account, the active one by default")
+                        .index(1),
+                ),
+        )
+        .subcommand(
+            SubCommand::with_name("bumpfee")
+                .about("Replace a pending transaction with one paying a higher fee")
+                .arg(
+                    Arg::with_name("txid")
+                        .help("The txid of the pending transaction")
+                        .required(true)
+                        .index(1),
+                )
+                .arg(
+                    Arg::with_name("fee")
+                        .help("The new fee, the minimum fee bump is used by default")
+                        .index(2),
+                ),
+        )
+        .subcommand(
+            SubCommand::with_name("cancel")
+                .about("Cancel a pending transaction by replacing it with a self-send")
+                .arg(
+                    Arg::with_name("txid")
+                        .help("The txid of the pending transaction")
+                        .required(true)
+                        .index(1),
+                ),
+        )
+        .subcommand(
+            SubCommand::with_name("receive")
+                .about("Hand out a new receive address of an account")
+                .arg(
+                    Arg::with_name("account")
+                        .help("The name of the account, the active one by default")
+                        .index(1),
+                ),
+        )
+        .subcommand(
+            SubCommand::with_name("mnemonic")
+                .about("Print the mnemonic backing up the wallet, the wallet must be unlocked"),
+        )
+        .subcommand(
+            SubCommand::with_name("restore")
+                .about("Replace the wallet with the one of a mnemonic and rescan the chain"),
+        )
+        .subcommand(
+            SubCommand::with_name("unlock")
+                .about("Decrypt the wallet key with its passphrase to send and mine"),
+        )
+        .subcommand(SubCommand::with_name("lock").about("Drop the decrypted wallet key"))
+        .subcommand(
+            SubCommand::with_name("passphrase")
+                .about("Encrypt the wallet key with a new passphrase, the wallet must be unlocked"),
+        )
+        .subcommand(SubCommand::with_name("disconnect").about("Undo the latest block"))
+        .subcommand(
+            SubCommand::with_name("verifychain")
+                .about("Verify the stored chain and balances")
+                .arg(
+                    Arg::with_name("depth")
+                        .long("depth")
+                        .value_name("N")
+                        .help("Only check PoW and signatures of the last N blocks")
+                        .takes_value(true),
+                ),
+        )
+        .subcommand(
+            SubCommand::with_name("reindex")
+                .about("Rebuild the block index and balances from the stored blocks"),
+        )
+        .subcommand(
+            SubCommand::with_name("export")
+                .about("Export blocks to a block file")
+                .arg(
+                    Arg::with_name("from")
+                        .long("from")
+                        .value_name("H")
+                        .help("The height of the first exported block")
+                        .takes_value(true),
+                )
+                .arg(
+                    Arg::with_name("to")
+                        .long("to")
+                        .value_name("H")
+                        .help("The height of the last exported block")
+                        .takes_value(true),
+                )
+                .arg(
+                    Arg::with_name("file")
+                        .help("The block file to write")
+                        .required(true)
+                        .index(1),
+                ),
+        )
+        .subcommand(
+            SubCommand::with_name("import")
+                .about("Import and validate the blocks of a block file before starting")
+                .arg(
+                    Arg::with_name("file")
+                        .help("The block file to read")
+                        .required(true)
+                        .index(1),
+                ),
+        )
+        .subcommand(
+            SubCommand::with_name("snapshot")
+                .about("Take a snapshot of the account state at the latest block")
+                .arg(
+                    Arg::with_name("file")
+                        .help("A file to export the snapshot to")
+                        .index(1),
+                ),
+        )
+        .subcommand(
+            SubCommand::with_name("loadsnapshot")
+                .about("Start an empty chain from a snapshot file")
+                .arg(
+                    Arg::with_name("file")
+                        .help("The snapshot file to read")
+                        .required(true)
+                        .index(1),
+                )
+                .arg(
+                    Arg::with_name("state-hash")
+                        .help("The state hash the snapshot must have, from a trusted source")
+                        .required(true)
+                        .index(2),
                 ),
+        )
+        .get_matches();
+
+    let network = Network::from_str(matches.value_of("network").unwrap()).unwrap();
+    let mut config = BlockchainConfig::new(matches.value_of("datadir").unwrap(), network);
+    config.prune_depth = matches.value_of("prune").map(usize::from_str).transpose()?;
+    config.compress_blocks = matches.is_present("compress-blocks");
+    config.signature_scheme =
+        SignatureScheme::from_str(matches.value_of("signature-scheme").unwrap())?;
+
+    let mut blockchain = Blockchain::new(config)?;
+    if let Some(load) = matches.subcommand_matches("loadsnapshot") {
+        let snapshot = Snapshot::read(Path::new(load.value_of("file").unwrap()))?;
+        blockchain.load_snapshot(&snapshot, load.value_of("state-hash").unwrap())?;
+        println!(
+            "Loaded snapshot at height {} with state hash {}",
+            snapshot.height(),
+            snapshot.state_hash
         );
+    }
+    if let Some(import) = matches.subcommand_matches("import") {
+        let imported = blockchain.import_blocks(Path::new(import.value_of("file").unwrap()))?;
+        println!("Imported {} blocks", imported);
+    }
+    if let Some(verify) = matches.subcommand_matches("verifychain") {
+        let depth = verify.value_of("depth").map(usize::from_str).transpose()?;
+        let checked = blockchain.verify_chain(depth)?;
+        println!("Chain is valid, {} blocks checked", checked);
+        return Ok(());
+    }
+    if matches.subcommand_matches("reindex").is_some() {
+        let blocks = blockchain.reindex()?;
+        println!("Reindexed {} blocks", blocks);
+        return Ok(());
+    }
+    let latest_block = blockchain.start_blockchain()?;
+    println!("Blockchain started on {}", network);
+    println!("Latest block: {:#?}", latest_block);
+    println!(
+        "Your address is: {} (account {})",
+        blockchain.address(&blockchain.wallet.public_key()?),
+        blockchain.wallet.active_account()
+    );
+    if blockchain.wallet.is_locked() {
+        println!("The wallet is locked, use unlock to send and mine");
+    }
+
+    if let Some(export) = matches.subcommand_matches("export") {
+        let from = export.value_of("from").map(usize::from_str).transpose()?;
+        let to = export.value_of("to").map(usize::from_str).transpose()?;
+        let path = export.value_of("file").unwrap();
+        let exported = blockchain.export_blocks(from.unwrap_or(0), to, Path::new(path))?;
+        println!("Exported {} blocks to {}", exported, path);
+        return Ok(());
+    }
 
     loop {
+        for dropped in blockchain.take_dropped_payments() {
+            let payment = &dropped.transaction;
+            let reason = match dropped.reason {
+                DropReason::Expired => "expired without being confirmed",
+                DropReason::ParentExpired => {
+                    "was dropped because an earlier payment of its sender expired"
+                }
+                DropReason::Replaced => "was replaced by another transaction",
+                DropReason::Evicted => "was evicted from the full mempool",
+            };
+            println!(
+                "Payment {} of {} to {} {}",
+                payment.txid(),
+                payment.amount,
+                payment.receiver,
+                reason
+            );
+        }
         print!("> ");
         io::stdout().flush()?;
 
@@ -64,39 +563,665 @@ fn main() -> std::io::Result<()> {
         let input = input.trim();
         let mut args = input.split_whitespace();
 
-        let command = args.next().unwrap();
+        let command = match args.next() {
+            Some(command) => command,
+            None => continue,
+        };
         match command {
             "newpubkey" => {
                 let client = Client::new();
                 println!("new public key: {}", client.identify());
+                println!("address: {}", blockchain.address(&client.public_key));
             }
-            "balances" => {
-                println!("{{");
-                for (&pubkey, &amount) in &storage::get_balances(&blockchain.balances).unwrap() {
-                    println!("{}: {}", pubkey, amount);
+            "balances" => match storage::get_balances(&blockchain.balances)
+                .and_then(|balances| Ok((balances, blockchain.immature_balances()?)))
+            {
+                Ok((balances, immature)) => {
+                    println!("{{");
+                    for (address, amount) in &balances {
+                        let immature = immature.get(address).copied().unwrap_or(0.0);
+                        println!(
+                            "{}: spendable: {}, immature: {}",
+                            address,
+                            amount - immature,
+                            immature
+                        );
+                    }
+                    println!("}}");
+                }
+                Err(e) => println!("Failed to read balances: {}", e),
+            },
+            "balance" => {
+                let address = match args.next() {
+                    Some(address) => address,
+                    None => {
+                        println!("Usage: balance <address>");
+                        continue;
+                    }
+                };
+                let address = match Address::parse(address, network) {
+                    Ok(address) => address,
+                    Err(e) => {
+                        println!("Invalid address: {}", e);
+                        continue;
+                    }
+                };
+                match storage::get_balance(&blockchain.balances, address)
+                    .and_then(|balance| Ok((balance, blockchain.immature_balances()?)))
+                {
+                    Ok((balance, immature)) => {
+                        let immature = immature.get(&address).copied().unwrap_or(0.0);
+                        println!("{}", address);
+                        println!("spendable: {}", balance.unwrap_or(0.0) - immature);
+                        println!("immature: {}", immature);
+                    }
+                    Err(e) => println!("Failed to read the balance: {}", e),
                 }
-                println!("}}");
-            }
-            "mine" => {
-                let block = blockchain.mine();
-                println!("Block mined: {:#?}", block);
             }
-            "mempool" => {
-                println!("Mempool: {:#?}", blockchain.mempool);
+            "supply" => {
+                let height = match args.next().map(usize::from_str).transpose() {
+                    Ok(Some(height)) => Ok(height),
+                    Ok(None) => storage::get_latest_block_number(&blockchain.block_metadata),
+                    Err(e) => {
+                        println!("Invalid height: {}", e);
+                        continue;
+                    }
+                };
+                match height {
+                    Ok(height) => println!(
+                        "Supply at height {}: {}",
+                        height,
+                        blockchain.config.supply(height)
+                    ),
+                    Err(e) => println!("Failed to read the latest height: {}", e),
+                }
             }
+            "mine" => match blockchain.mine() {
+                Ok(block) => println!("Block mined: {:#?}", block),
+                Err(e) => println!("Failed to mine block: {}", e),
+            },
+            "mempool" => match (args.next(), args.next()) {
+                (None, _) => {
+                    println!(
+                        "Mempool: {} transactions, {} bytes",
+                        blockchain.mempool.len(),
+                        blockchain.mempool.size_bytes()
+                    );
+                    for transaction in blockchain.mempool.transactions() {
+                        println!("{}: {:#?}", transaction.txid(), transaction);
+                    }
+                }
+                (Some("--sender"), Some(sender)) => match Sender::from_str(sender) {
+                    Ok(sender) => println!("{:#?}", blockchain.mempool.by_sender(&sender)),
+                    Err(e) => println!("Invalid sender: {}", e),
+                },
+                (Some(txid), None) => match blockchain.mempool.get(txid) {
+                    Some(transaction) => println!("{:#?}", transaction),
+                    None => println!("Transaction not found in the mempool"),
+                },
+                _ => println!("Usage: mempool [<txid> | --sender <public key | multi(...)>]"),
+            },
             "send" => {
-                let receiver =
-                    PublicKey::from_str(args.next().unwrap()).expect("Invalid public key");
-                let amount = f64::from_str(args.next().unwrap()).expect("Invalid amount");
-                match blockchain.send_transaction(receiver, amount) {
+                let receiver = match args.next().map(|s| Address::parse(s, network)) {
+                    Some(Ok(receiver)) => receiver,
+                    Some(Err(e)) => {
+                        println!("Invalid address: {}", e);
+                        continue;
+                    }
+                    None => {
+                        println!(
+                            "Usage: send <address> <amount> [fee] [--ttl N] [--from <account>]"
+                        );
+                        continue;
+                    }
+                };
+                let amount = match args.next().map(f64::from_str) {
+                    Some(Ok(amount)) => amount,
+                    _ => {
+                        println!("Invalid amount");
+                        continue;
+                    }
+                };
+                let options = match parse_send_args(args) {
+                    Some(options) => options,
+                    None => {
+                        println!(
+                            "Usage: send <address> <amount> [fee] [--ttl N] [--from <account>]"
+                        );
+                        continue;
+                    }
+                };
+                let expiry_height = match options.ttl {
+                    Some(ttl) => match storage::get_latest_block_number(&blockchain.block_metadata)
+                    {
+                        Ok(height) => Some(height + ttl),
+                        Err(e) => {
+                            println!("Failed to read the latest block: {}", e);
+                            continue;
+                        }
+                    },
+                    None => None,
+                };
+                let from = options
+                    .from
+                    .unwrap_or(blockchain.wallet.active_account())
+                    .to_string();
+                match blockchain.send_transaction_from(
+                    &from,
+                    receiver,
+                    amount,
+                    options.fee,
+                    expiry_height,
+                ) {
                     Ok(transaction) => {
-                        println!("Transaction created: {:#?}", transaction);
+                        println!(
+                            "Transaction {} created: {:#?}",
+                            transaction.txid(),
+                            transaction
+                        );
                     }
                     Err(e) => {
                         println!("Transaction failed: {}", e);
                     }
                 }
             }
+            "multisig" => {
+                let threshold = match args.next().map(usize::from_str) {
+                    Some(Ok(threshold)) => threshold,
+                    _ => {
+                        println!("Usage: multisig <threshold> <public key>...");
+                        continue;
+                    }
+                };
+                let public_keys = match args.map(PublicKey::from_str).collect() {
+                    Ok(public_keys) => public_keys,
+                    Err(_) => {
+                        println!("Invalid public key");
+                        continue;
+                    }
+                };
+                match Multisig::new(threshold, public_keys) {
+                    Ok(multisig) => {
+                        println!("Multisig policy: {}", multisig);
+                        println!("address: {}", multisig.address(network));
+                    }
+                    Err(e) => println!("Invalid multisig policy: {}", e),
+                }
+            }
+            "createtx" => {
+                let usage = "Usage: createtx <public key | multi(...)> <address> <amount> <file> [fee] [--ttl N]";
+                let (sender, receiver, amount, path) =
+                    match (args.next(), args.next(), args.next(), args.next()) {
+                        (Some(sender), Some(receiver), Some(amount), Some(path)) => {
+                            (sender, receiver, amount, path)
+                        }
+                        _ => {
+                            println!("{}", usage);
+                            continue;
+                        }
+                    };
+                let sender = match Sender::from_str(sender) {
+                    Ok(sender) => sender,
+                    Err(e) => {
+                        println!("Invalid sender: {}", e);
+                        continue;
+                    }
+                };
+                let receiver = match Address::parse(receiver, network) {
+                    Ok(receiver) => receiver,
+                    Err(e) => {
+                        println!("Invalid address: {}", e);
+                        continue;
+                    }
+                };
+                let amount = match f64::from_str(amount) {
+                    Ok(amount) => amount,
+                    Err(_) => {
+                        println!("Invalid amount");
+                        continue;
+                    }
+                };
+                let options = match parse_send_args(args) {
+                    Some(options) if options.from.is_none() => options,
+                    _ => {
+                        println!("{}", usage);
+                        continue;
+                    }
+                };
+                let expiry_height = match options.ttl {
+                    Some(ttl) => match storage::get_latest_block_number(&blockchain.block_metadata)
+                    {
+                        Ok(height) => Some(height + ttl),
+                        Err(e) => {
+                            println!("Failed to read the latest block: {}", e);
+                            continue;
+                        }
+                    },
+                    None => None,
+                };
+                match blockchain
+                    .unsigned_transaction(sender, receiver, amount, options.fee, expiry_height)
+                    .and_then(|transaction| {
+                        OfflineTransaction::new(network, transaction.clone())
+                            .write(Path::new(path))?;
+                        Ok(transaction)
+                    }) {
+                    Ok(transaction) => println!(
+                        "Unsigned transaction {} written to {}",
+                        transaction.txid(),
+                        path
+                    ),
+                    Err(e) => println!("Transaction failed: {}", e),
+                }
+            }
+            "signtx" => {
+                let path = match args.next() {
+                    Some(path) => Path::new(path),
+                    None => {
+                        println!("Usage: signtx <file>");
+                        continue;
+                    }
+                };
+                let mut offline = match OfflineTransaction::read(path)
+                    .and_then(|offline| offline.verify(network).map(|()| offline))
+                {
+                    Ok(offline) => offline,
+                    Err(e) => {
+                        println!("Failed to read the transaction: {}", e);
+                        continue;
+                    }
+                };
+                let transaction = &offline.transaction;
+                println!(
+                    "Signing transaction {}: {} to {} with fee {}",
+                    transaction.txid(),
+                    transaction.amount,
+                    transaction.receiver,
+                    transaction.fee
+                );
+                match blockchain
+                    .wallet
+                    .sign_transaction(&mut offline.transaction)
+                    .and_then(|signed| {
+                        offline.write(path)?;
+                        Ok(signed)
+                    }) {
+                    Ok(signed) => println!(
+                        "Added {} signatures, {} still missing",
+                        signed,
+                        offline.transaction.missing_signatures()
+                    ),
+                    Err(e) => println!("Signing failed: {}", e),
+                }
+            }
+            "sendtx" => {
+                let mut copies = args.map(|path| {
+                    OfflineTransaction::read(Path::new(path))
+                        .and_then(|offline| offline.verify(network).map(|()| offline))
+                });
+                let mut offline = match copies.next() {
+                    Some(Ok(offline)) => offline,
+                    Some(Err(e)) => {
+                        println!("Failed to read the transaction: {}", e);
+                        continue;
+                    }
+                    None => {
+                        println!("Usage: sendtx <file>...");
+                        continue;
+                    }
+                };
+                let combined = copies.try_for_each(|copy| {
+                    offline.transaction.combine_signatures(&copy?.transaction)
+                });
+                if let Err(e) = combined {
+                    println!("Failed to combine the signatures: {}", e);
+                    continue;
+                }
+                let txid = offline.transaction.txid();
+                match blockchain.broadcast_transaction(offline.transaction) {
+                    Ok(()) => println!("Transaction {} broadcast", txid),
+                    Err(e) => println!("Transaction failed: {}", e),
+                }
+            }
+            "bumpfee" => {
+                let txid = match args.next() {
+                    Some(txid) => txid,
+                    None => {
+                        println!("Usage: bumpfee <txid> [fee]");
+                        continue;
+                    }
+                };
+                let fee = match args.next().map(f64::from_str).transpose() {
+                    Ok(fee) => fee,
+                    Err(_) => {
+                        println!("Invalid fee");
+                        continue;
+                    }
+                };
+                match blockchain.bump_fee(txid, fee) {
+                    Ok(transaction) => println!(
+                        "Transaction {} replaced by {}: {:#?}",
+                        txid,
+                        transaction.txid(),
+                        transaction
+                    ),
+                    Err(e) => println!("Fee bump failed: {}", e),
+                }
+            }
+            "cancel" => match args.next() {
+                Some(txid) => match blockchain.cancel_transaction(txid) {
+                    Ok(transaction) => {
+                        println!("Transaction {} cancelled by {}", txid, transaction.txid())
+                    }
+                    Err(e) => println!("Cancel failed: {}", e),
+                },
+                None => println!("Usage: cancel <txid>"),
+            },
+            "account" => match (args.next(), args.next()) {
+                (Some("new"), Some(name)) => match blockchain.wallet.new_account(name) {
+                    Ok(public_key) => println!(
+                        "Account {} created: {}",
+                        name,
+                        blockchain.address(&public_key)
+                    ),
+                    Err(e) => println!("Failed to create the account: {}", e),
+                },
+                (Some("list"), None) => {
+                    let wallet = &blockchain.wallet;
+                    for (name, receiver) in wallet.accounts() {
+                        let active = if name == wallet.active_account() {
+                            "*"
+                        } else {
+                            " "
+                        };
+                        let kind = if name == wallet.coinbase_account() {
+                            " (coinbase)"
+                        } else if wallet.is_watch_only(name).unwrap_or(false) {
+                            " (watch-only)"
+                        } else {
+                            ""
+                        };
+                        match receiver {
+                            Ok(receiver) => println!(
+                                "{} {}: {}{}",
+                                active,
+                                name,
+                                receiver.address(network),
+                                kind
+                            ),
+                            Err(e) => println!("{} {}: {}{}", active, name, e, kind),
+                        }
+                    }
+                }
+                (Some("use"), Some(name)) => match blockchain.wallet.use_account(name) {
+                    Ok(()) => println!("Sending from account {}", name),
+                    Err(e) => println!("Failed to use the account: {}", e),
+                },
+                (Some("coinbase"), Some(name)) => {
+                    match blockchain.wallet.set_coinbase_account(name) {
+                        Ok(()) => println!("Mining rewards go to account {}", name),
+                        Err(e) => println!("Failed to set the coinbase account: {}", e),
+                    }
+                }
+                (Some("watch"), Some(name)) => {
+                    let watched = match args.next() {
+                        Some(watched) => match PublicKey::from_str(watched) {
+                            Ok(public_key) => Ok(Receiver::PublicKey(public_key)),
+                            Err(_) => Address::parse(watched, network).map(Receiver::Address),
+                        },
+                        None => {
+                            println!("Usage: account watch <name> <public key | address>");
+                            continue;
+                        }
+                    };
+                    match watched
+                        .and_then(|watched| blockchain.wallet.watch(name, watched, network))
+                    {
+                        Ok(address) => println!("Account {} watches {}", name, address),
+                        Err(e) => println!("Failed to watch: {}", e),
+                    }
+                }
+                _ => println!(
+                    "Usage: account new <name> | list | use <name> | coinbase <name> | watch <name> <public key | address>"
+                ),
+            },
+            "payments" => {
+                let account = args
+                    .next()
+                    .unwrap_or(blockchain.wallet.active_account())
+                    .to_string();
+                match blockchain
+                    .received_payments(&account)
+                    .and_then(|payments| Ok((payments, blockchain.account_balance(&account)?)))
+                {
+                    Ok((payments, balance)) => {
+                        println!("Account {}: {} available", account, balance);
+                        for payment in payments {
+                            let transaction = &payment.transaction;
+                            let from = match &transaction.sender {
+                                Some(_) if payment.is_coinbase => String::from("mining reward"),
+                                Some(sender) => format!("from {}", sender.address(network)),
+                                None => String::from("without sender"),
+                            };
+                            println!(
+                                "Block {}: {} to {} {} ({})",
+                                payment.height,
+                                transaction.amount,
+                                transaction.receiver.address(network),
+                                from,
+                                transaction.txid()
+                            );
+                        }
+                    }
+                    Err(e) => println!("Failed to read the payments: {}", e),
+                }
+            }
+            "wallet" => {
+                let account = args
+                    .next()
+                    .unwrap_or(blockchain.wallet.active_account())
+                    .to_string();
+                match blockchain.account_balances(&account).and_then(|balances| {
+                    Ok((balances, blockchain.account_transactions(&account)?))
+                }) {
+                    Ok((balances, transactions)) => {
+                        println!("Account {}", account);
+                        println!("confirmed: {}", balances.confirmed);
+                        println!("pending: {:+}", balances.pending);
+                        println!("immature: {}", balances.immature);
+                        for wallet_transaction in transactions {
+                            let transaction = &wallet_transaction.transaction;
+                            let status = match wallet_transaction.height {
+                                Some(height) => format!(
+                                    "Block {} ({} confirmations)",
+                                    height, wallet_transaction.confirmations
+                                ),
+                                None => String::from("Pending"),
+                            };
+                            let counterpart = match &transaction.sender {
+                                _ if wallet_transaction.is_coinbase => {
+                                    String::from("mining reward")
+                                }
+                                Some(sender) if wallet_transaction.amount < 0.0 => {
+                                    format!(
+                                        "sent from {} to {}",
+                                        sender.address(network),
+                                        transaction.receiver.address(network)
+                                    )
+                                }
+                                Some(sender) => format!(
+                                    "received on {} from {}",
+                                    transaction.receiver.address(network),
+                                    sender.address(network)
+                                ),
+                                None => format!(
+                                    "received on {} without sender",
+                                    transaction.receiver.address(network)
+                                ),
+                            };
+                            println!(
+                                "{}: {:+} {} ({})",
+                                status,
+                                wallet_transaction.amount,
+                                counterpart,
+                                transaction.txid()
+                            );
+                        }
+                    }
+                    Err(e) => println!("Failed to read the wallet: {}", e),
+                }
+            }
+            "receive" => {
+                let account = args
+                    .next()
+                    .unwrap_or(blockchain.wallet.active_account())
+                    .to_string();
+                match blockchain.wallet.new_receive_key(&account) {
+                    Ok(public_key) => {
+                        println!(
+                            "New receive address of account {}: {}",
+                            account,
+                            blockchain.address(&public_key)
+                        );
+                        println!("public key: {}", public_key);
+                    }
+                    Err(e) => println!("Failed to create a receive key: {}", e),
+                }
+            }
+            "mnemonic" => match blockchain.wallet.mnemonic() {
+                Ok(mnemonic) => println!("Wallet mnemonic: {}", mnemonic),
+                Err(e) => println!("Failed to read the mnemonic: {}", e),
+            },
+            "restore" => {
+                let mnemonic = match prompt_line("Mnemonic: ") {
+                    Ok(mnemonic) => mnemonic,
+                    Err(e) => {
+                        println!("Failed to read the mnemonic: {}", e);
+                        continue;
+                    }
+                };
+                let passphrase = match (
+                    prompt_line("New passphrase: "),
+                    prompt_line("Repeat the new passphrase: "),
+                ) {
+                    (Ok(passphrase), Ok(repeated)) if passphrase == repeated => passphrase,
+                    (Ok(_), Ok(_)) => {
+                        println!("The passphrases do not match");
+                        continue;
+                    }
+                    (Err(e), _) | (_, Err(e)) => {
+                        println!("Failed to read the passphrase: {}", e);
+                        continue;
+                    }
+                };
+                match blockchain.restore_wallet(&mnemonic, &passphrase) {
+                    Ok(accounts) => {
+                        println!("Wallet restored with {} accounts", accounts);
+                        let names: Vec<String> = blockchain
+                            .wallet
+                            .accounts()
+                            .map(|(name, _)| name.to_string())
+                            .collect();
+                        for name in names {
+                            match blockchain.account_balance(&name) {
+                                Ok(balance) => println!("{}: {}", name, balance),
+                                Err(e) => println!("{}: failed to read the balance: {}", name, e),
+                            }
+                        }
+                    }
+                    Err(e) => println!("Restore failed: {}", e),
+                }
+            }
+            "unlock" => match prompt_line("Passphrase: ") {
+                Ok(passphrase) => match blockchain.wallet.unlock(&passphrase) {
+                    Ok(()) => println!("Wallet unlocked"),
+                    Err(e) => println!("Unlock failed: {}", e),
+                },
+                Err(e) => println!("Failed to read the passphrase: {}", e),
+            },
+            "lock" => {
+                blockchain.wallet.lock();
+                println!("Wallet locked");
+            }
+            "passphrase" => {
+                let passphrase = match (
+                    prompt_line("New passphrase: "),
+                    prompt_line("Repeat the new passphrase: "),
+                ) {
+                    (Ok(passphrase), Ok(repeated)) if passphrase == repeated => passphrase,
+                    (Ok(_), Ok(_)) => {
+                        println!("The passphrases do not match");
+                        continue;
+                    }
+                    (Err(e), _) | (_, Err(e)) => {
+                        println!("Failed to read the passphrase: {}", e);
+                        continue;
+                    }
+                };
+                match blockchain.wallet.change_passphrase(&passphrase) {
+                    Ok(()) => println!("Passphrase changed"),
+                    Err(e) => println!("Failed to change the passphrase: {}", e),
+                }
+            }
+            "disconnect" => match blockchain.disconnect_tip() {
+                Ok(block) => println!("Block disconnected: {}", block.block_hash),
+                Err(e) => println!("Failed to disconnect block: {}", e),
+            },
+            "verifychain" => {
+                let depth = match (args.next(), args.next()) {
+                    (None, _) => None,
+                    (Some("--depth"), Some(depth)) => match usize::from_str(depth) {
+                        Ok(depth) => Some(depth),
+                        Err(_) => {
+                            println!("Invalid depth");
+                            continue;
+                        }
+                    },
+                    _ => {
+                        println!("Usage: verifychain [--depth N]");
+                        continue;
+                    }
+                };
+                match blockchain.verify_chain(depth) {
+                    Ok(checked) => println!("Chain is valid, {} blocks checked", checked),
+                    Err(e) => println!("Chain verification failed: {}", e),
+                }
+            }
+            "reindex" => match blockchain.reindex() {
+                Ok(blocks) => println!("Reindexed {} blocks", blocks),
+                Err(e) => println!("Reindex failed: {}", e),
+            },
+            "export" => match parse_export_args(args) {
+                Some((from, to, path)) => match blockchain.export_blocks(from, to, Path::new(path))
+                {
+                    Ok(exported) => println!("Exported {} blocks to {}", exported, path),
+                    Err(e) => println!("Export failed: {}", e),
+                },
+                None => println!("Usage: export [--from H] [--to H] <file>"),
+            },
+            "import" => match args.next() {
+                Some(path) => match blockchain.import_blocks(Path::new(path)) {
+                    Ok(imported) => println!("Imported {} blocks", imported),
+                    Err(e) => println!("Import failed: {}", e),
+                },
+                None => println!("Usage: import <file>"),
+            },
+            "snapshot" => match blockchain.create_snapshot() {
+                Ok(snapshot) => {
+                    println!(
+                        "Snapshot taken at height {} with state hash {}",
+                        snapshot.height(),
+                        snapshot.state_hash
+                    );
+                    if let Some(path) = args.next() {
+                        match snapshot.write(Path::new(path)) {
+                            Ok(()) => println!("Snapshot exported to {}", path),
+                            Err(e) => println!("Snapshot export failed: {}", e),
+                        }
+                    }
+                }
+                Err(e) => println!("Snapshot failed: {}", e),
+            },
             "clear" => {
                 print!("\x1B[2J\x1B[1;1H");
             }
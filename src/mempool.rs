@@ -0,0 +1,642 @@
+use crate::{
+    address::Address,
+    block::Block,
+    config::{MempoolLimits, Network},
+    error::{Error, Result},
+    transaction::{Sender, Transaction},
+};
+use chrono::{DateTime, Utc};
+use std::collections::{HashMap, HashSet};
+
+/// A pending transaction together with its encoded size.
+struct MempoolEntry {
+    transaction: Transaction,
+    size: usize,
+}
+
+impl MempoolEntry {
+    /// This method returns the fee paid per byte of the transaction.
+    fn fee_rate(&self) -> f64 {
+        self.transaction.fee / self.size as f64
+    }
+}
+
+/// The pending transactions a new transaction took the place of.
+///
+/// `replaced` contains the transaction using the same nonce that it replaced.
+/// `evicted` contains the transactions evicted to make room for it.
+#[derive(Debug, Default)]
+pub struct Displaced {
+    pub replaced: Option<Transaction>,
+    pub evicted: Vec<Transaction>,
+}
+
+/// The pending transactions removed because they expired.
+///
+/// `expired` contains the expired transactions.
+/// `dependents` contains their descendants, which did not expire but can no longer be
+/// confirmed without them.
+#[derive(Debug, Default)]
+pub struct Expired {
+    pub expired: Vec<Transaction>,
+    pub dependents: Vec<Transaction>,
+}
+
+/// The transactions waiting to be included in a block.
+///
+/// The pending transactions of a sender never spend more than its confirmed balance and
+/// use consecutive nonces following its confirmed nonce, so the transactions of the
+/// mempool cannot conflict with each other. A pending transaction can be replaced by
+/// another one with the same nonce paying a higher fee. When a limit is reached the
+/// transactions paying the lowest fee per byte are evicted first.
+///
+/// `limits` contains the limits of the mempool.
+/// `entries` contains the pending transactions keyed by txid.
+/// `order` contains the txids in the order the transactions were added.
+/// `by_sender` contains the txids of the pending transactions of every sender, sorted by nonce.
+/// `total_bytes` contains the total encoded size of the pending transactions.
+pub struct Mempool {
+    limits: MempoolLimits,
+    entries: HashMap<String, MempoolEntry>,
+    order: Vec<String>,
+    by_sender: HashMap<Sender, Vec<String>>,
+    total_bytes: usize,
+}
+
+impl Mempool {
+    /// This method creates an empty mempool.
+    pub fn new(limits: MempoolLimits) -> Self {
+        Self {
+            limits,
+            entries: HashMap::new(),
+            order: Vec::new(),
+            by_sender: HashMap::new(),
+            total_bytes: 0,
+        }
+    }
+
+    pub fn limits(&self) -> &MempoolLimits {
+        &self.limits
+    }
+
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+
+    /// This method returns the total encoded size of the pending transactions.
+    pub fn size_bytes(&self) -> usize {
+        self.total_bytes
+    }
+
+    pub fn contains(&self, txid: &str) -> bool {
+        self.entries.contains_key(txid)
+    }
+
+    pub fn get(&self, txid: &str) -> Option<&Transaction> {
+        self.entries.get(txid).map(|entry| &entry.transaction)
+    }
+
+    /// This method returns the pending transactions in the order they were added, except
+    /// that the transactions of a sender always come in nonce order.
+    pub fn transactions(&self) -> Vec<Transaction> {
+        let mut emitted: HashMap<Sender, usize> = HashMap::new();
+        self.order
+            .iter()
+            .map(|txid| {
+                let transaction = &self.entries[txid].transaction;
+                let txid = match &transaction.sender {
+                    Some(sender) => {
+                        let position = emitted.entry(sender.clone()).or_default();
+                        *position += 1;
+                        &self.by_sender[sender][*position - 1]
+                    }
+                    None => txid,
+                };
+                self.entries[txid].transaction.clone()
+            })
+            .collect()
+    }
+
+    /// This method returns the pending transactions of a sender in nonce order.
+    pub fn by_sender(&self, sender: &Sender) -> Vec<&Transaction> {
+        self.by_sender
+            .get(sender)
+            .into_iter()
+            .flatten()
+            .map(|txid| &self.entries[txid].transaction)
+            .collect()
+    }
+
+    /// This method returns the total amount the pending transactions of a sender spend.
+    pub fn pending_spend(&self, sender: &Sender) -> f64 {
+        self.by_sender(sender)
+            .iter()
+            .map(|transaction| transaction.total_spent())
+            .sum()
+    }
+
+    /// This method returns the total amount the pending transactions sent from an address
+    /// on `network` spend.
+    pub fn address_pending_spend(&self, address: Address, network: Network) -> f64 {
+        self.by_sender
+            .keys()
+            .filter(|sender| sender.address(network) == address)
+            .map(|sender| self.pending_spend(sender))
+            .sum()
+    }
+
+    /// This method returns the nonce the next transaction of a sender must use, given the
+    /// nonce following its confirmed transactions.
+    pub fn next_nonce(&self, sender: &Sender, confirmed_nonce: u64) -> u64 {
+        self.by_sender(sender)
+            .iter()
+            .filter_map(|transaction| transaction.nonce)
+            .map(|nonce| nonce + 1)
+            .max()
+            .map_or(confirmed_nonce, |nonce| nonce.max(confirmed_nonce))
+    }
+
+    /// This method returns the txid of the pending transaction of a sender using a nonce.
+    fn find_nonce(&self, sender: &Sender, nonce: u64) -> Option<String> {
+        self.by_sender
+            .get(sender)?
+            .iter()
+            .find(|txid| self.entries[*txid].transaction.nonce == Some(nonce))
+            .cloned()
+    }
+
+    /// This method adds a transaction from a sender owning `balance` confirmed funds and
+    /// whose next confirmed transaction must use `confirmed_nonce`.
+    ///
+    /// A transaction reusing the nonce of a pending transaction replaces it when its fee is
+    /// higher by at least the minimum fee bump. Otherwise the transaction must use the next
+    /// nonce of the sender. The transaction is also rejected when it is already pending or
+    /// when the pending transactions of the sender would spend more than its balance. When
+    /// the mempool is full, transactions paying a lower fee per byte are evicted to make
+    /// room for it. Returns the replaced and evicted transactions.
+    pub fn insert(
+        &mut self,
+        transaction: Transaction,
+        balance: f64,
+        confirmed_nonce: u64,
+    ) -> Result<Displaced> {
+        let txid = transaction.txid();
+        if self.contains(&txid) {
+            return Err(Error::validation("Transaction is already in the mempool"));
+        }
+        let sender = transaction
+            .sender
+            .clone()
+            .ok_or_else(|| Error::validation("No sender found"))?;
+
+        let replaced = match transaction.nonce {
+            Some(nonce) if nonce < confirmed_nonce => {
+                return Err(Error::validation("Nonce is already used"))
+            }
+            Some(nonce) => match self.find_nonce(&sender, nonce) {
+                Some(replaced) => {
+                    let replaced_fee = self.entries[&replaced].transaction.fee;
+                    if transaction.fee < replaced_fee + self.limits.min_fee_bump {
+                        return Err(Error::validation(format!(
+                            "Replacement fee must be at least {}",
+                            replaced_fee + self.limits.min_fee_bump
+                        )));
+                    }
+                    Some(replaced)
+                }
+                None if nonce == self.next_nonce(&sender, confirmed_nonce) => None,
+                None => return Err(Error::validation("Nonce is not the next one of the sender")),
+            },
+            None => None,
+        };
+
+        let replaced_spend = replaced
+            .as_ref()
+            .map_or(0.0, |txid| self.entries[txid].transaction.total_spent());
+        if self.pending_spend(&sender) - replaced_spend + transaction.total_spent() > balance {
+            return Err(Error::validation(
+                "Pending transactions of the sender exceed its balance",
+            ));
+        }
+
+        let entry = MempoolEntry {
+            size: transaction.size(),
+            transaction,
+        };
+        let evictions = self.select_evictions(&entry, replaced.as_deref())?;
+
+        let displaced = Displaced {
+            replaced: replaced.and_then(|txid| self.remove(&txid)),
+            evicted: evictions
+                .iter()
+                .filter_map(|txid| self.remove(txid))
+                .collect(),
+        };
+
+        self.total_bytes += entry.size;
+        self.order.push(txid.clone());
+        let entries = &self.entries;
+        let txids = self.by_sender.entry(sender).or_default();
+        let position = txids.partition_point(|pending| {
+            entries[pending].transaction.nonce <= entry.transaction.nonce
+        });
+        txids.insert(position, txid.clone());
+        self.entries.insert(txid, entry);
+
+        Ok(displaced)
+    }
+
+    /// This method selects the transactions to evict so that `entry` fits in the limits,
+    /// lowest fee per byte first and oldest first among equal fees. A transaction is
+    /// evicted together with its descendants, and only when all of them pay a lower fee per
+    /// byte than `entry`. The transactions of the same sender are never evicted for it,
+    /// since it may depend on them.
+    fn select_evictions(
+        &self,
+        entry: &MempoolEntry,
+        replaced: Option<&str>,
+    ) -> Result<Vec<String>> {
+        let sender = &entry.transaction.sender;
+        let mut candidates: Vec<_> = self
+            .entries
+            .iter()
+            .filter(|(_, candidate)| &candidate.transaction.sender != sender)
+            .collect();
+        candidates.sort_by(|(_, a), (_, b)| {
+            a.fee_rate()
+                .total_cmp(&b.fee_rate())
+                .then(a.transaction.time().cmp(&b.transaction.time()))
+        });
+
+        let replaced_size = replaced.map_or(0, |txid| self.entries[txid].size);
+        let mut count = self.len() + 1 - usize::from(replaced.is_some());
+        let mut bytes = self.total_bytes + entry.size - replaced_size;
+        let mut candidates = candidates.into_iter();
+        let mut evicted: Vec<String> = Vec::new();
+        while count > self.limits.max_transactions || bytes > self.limits.max_bytes {
+            let txid = match candidates.next() {
+                Some((txid, candidate)) if candidate.fee_rate() < entry.fee_rate() => txid,
+                _ => {
+                    return Err(Error::validation(
+                        "Mempool is full and the transaction fee is too low",
+                    ))
+                }
+            };
+            let package: Vec<_> = self
+                .package(txid)
+                .into_iter()
+                .filter(|txid| !evicted.contains(txid))
+                .collect();
+            if package
+                .iter()
+                .any(|txid| self.entries[txid].fee_rate() >= entry.fee_rate())
+            {
+                continue;
+            }
+            for txid in package {
+                count -= 1;
+                bytes -= self.entries[&txid].size;
+                evicted.push(txid);
+            }
+        }
+
+        Ok(evicted)
+    }
+
+    /// This method removes a pending transaction and returns it.
+    pub fn remove(&mut self, txid: &str) -> Option<Transaction> {
+        let entry = self.entries.remove(txid)?;
+        self.total_bytes -= entry.size;
+        self.order.retain(|pending| pending != txid);
+        if let Some(sender) = &entry.transaction.sender {
+            if let Some(txids) = self.by_sender.get_mut(sender) {
+                txids.retain(|pending| pending != txid);
+                if txids.is_empty() {
+                    self.by_sender.remove(sender);
+                }
+            }
+        }
+
+        Some(entry.transaction)
+    }
+
+    /// This method returns the txid of a pending transaction followed by the txids of its
+    /// descendants: the pending transactions of its sender using a higher nonce, which can
+    /// no longer be confirmed without it.
+    fn package(&self, txid: &str) -> Vec<String> {
+        let transaction = match self.entries.get(txid) {
+            Some(entry) => &entry.transaction,
+            None => return Vec::new(),
+        };
+        let mut package = vec![txid.to_string()];
+        if let (Some(sender), Some(nonce)) = (&transaction.sender, transaction.nonce) {
+            package.extend(
+                self.by_sender[sender]
+                    .iter()
+                    .filter(|pending| self.entries[*pending].transaction.nonce > Some(nonce))
+                    .cloned(),
+            );
+        }
+        package
+    }
+
+    /// This method removes a pending transaction together with its descendants.
+    /// Returns the removed transactions.
+    pub fn remove_with_descendants(&mut self, txid: &str) -> Vec<Transaction> {
+        self.package(txid)
+            .iter()
+            .filter_map(|txid| self.remove(txid))
+            .collect()
+    }
+
+    /// This method removes the transactions confirmed by a block.
+    /// Returns the number of transactions that were removed.
+    pub fn remove_block_transactions(&mut self, block: &Block) -> usize {
+        block
+            .verified_transactions
+            .iter()
+            .filter_map(|transaction| self.remove(&transaction.txid()))
+            .count()
+    }
+
+    /// This method removes the pending transactions of a sender using a nonce that was
+    /// confirmed by another transaction. Returns the removed transactions.
+    pub fn remove_used_nonces(
+        &mut self,
+        sender: &Sender,
+        confirmed_nonce: u64,
+    ) -> Vec<Transaction> {
+        let used: Vec<_> = self
+            .by_sender(sender)
+            .iter()
+            .filter(|pending| pending.nonce.is_some_and(|nonce| nonce < confirmed_nonce))
+            .map(|pending| pending.txid())
+            .collect();
+
+        used.iter().filter_map(|txid| self.remove(txid)).collect()
+    }
+
+    /// This method removes the newest pending transactions of a sender until they spend no
+    /// more than `balance`. Returns the removed transactions.
+    pub fn trim_sender(&mut self, sender: &Sender, balance: f64) -> Vec<Transaction> {
+        let mut removed = Vec::new();
+        while self.pending_spend(sender) > balance {
+            let txid = match self.by_sender.get(sender).and_then(|txids| txids.last()) {
+                Some(txid) => txid.clone(),
+                None => break,
+            };
+            removed.extend(self.remove(&txid));
+        }
+        removed
+    }
+
+    /// This method removes the transactions that can no longer be included in a block at
+    /// `height`, together with their descendants.
+    pub fn remove_expired(&mut self, height: usize) -> Expired {
+        let expired: HashSet<_> = self
+            .entries
+            .iter()
+            .filter(|(_, entry)| entry.transaction.is_expired(height))
+            .map(|(txid, _)| txid.clone())
+            .collect();
+
+        self.remove_expired_packages(&expired)
+    }
+
+    /// This method evicts the transactions older than the maximum age, together with their
+    /// descendants.
+    pub fn evict_expired(&mut self, now: DateTime<Utc>) -> Expired {
+        let max_age =
+            chrono::Duration::from_std(self.limits.max_age).unwrap_or(chrono::Duration::MAX);
+        let expired: HashSet<_> = self
+            .entries
+            .iter()
+            .filter(|(_, entry)| now - entry.transaction.time() > max_age)
+            .map(|(txid, _)| txid.clone())
+            .collect();
+
+        self.remove_expired_packages(&expired)
+    }
+
+    /// This method removes the `expired` transactions together with their descendants,
+    /// telling the ones that expired from the ones removed with them.
+    fn remove_expired_packages(&mut self, expired: &HashSet<String>) -> Expired {
+        let mut removed = Expired::default();
+        for txid in expired.iter() {
+            for transaction in self.remove_with_descendants(txid) {
+                if expired.contains(&transaction.txid()) {
+                    removed.expired.push(transaction);
+                } else {
+                    removed.dependents.push(transaction);
+                }
+            }
+        }
+        removed
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::client::Client;
+    use chrono::Duration;
+
+    /// This method returns a payment of 1 from `sender` using `nonce` and paying `fee`.
+    fn payment(sender: &Client, nonce: u64, fee: f64) -> Transaction {
+        let receiver = Address::from_public_key(&Client::new().public_key, Network::Regtest);
+        let mut transaction =
+            Transaction::new(Some(sender.public_key.into()), receiver.into(), 1.0, None);
+        transaction.fee = fee;
+        transaction.nonce = Some(nonce);
+        transaction.sign_transaction(sender);
+        transaction
+    }
+
+    fn mempool(max_transactions: usize) -> Mempool {
+        Mempool::new(MempoolLimits {
+            max_transactions,
+            ..MempoolLimits::default()
+        })
+    }
+
+    #[test]
+    fn lowest_fee_rate_is_evicted_when_full() {
+        let mut mempool = mempool(2);
+        let low = payment(&Client::new(), 0, 0.1);
+        let high = payment(&Client::new(), 0, 0.3);
+        mempool.insert(low.clone(), 10.0, 0).unwrap();
+        mempool.insert(high.clone(), 10.0, 0).unwrap();
+
+        let evicted = mempool
+            .insert(payment(&Client::new(), 0, 0.2), 10.0, 0)
+            .unwrap()
+            .evicted;
+        assert_eq!(evicted.len(), 1);
+        assert_eq!(evicted[0].txid(), low.txid());
+        assert_eq!(mempool.len(), 2);
+        assert!(mempool.contains(&high.txid()));
+    }
+
+    #[test]
+    fn descendants_are_evicted_with_their_parent() {
+        let mut mempool = mempool(3);
+        let sender = Client::new();
+        let parent = payment(&sender, 0, 0.1);
+        mempool.insert(parent.clone(), 10.0, 0).unwrap();
+        mempool.insert(payment(&sender, 1, 0.12), 10.0, 0).unwrap();
+        mempool
+            .insert(payment(&Client::new(), 0, 0.15), 10.0, 0)
+            .unwrap();
+
+        let evicted = mempool
+            .insert(payment(&Client::new(), 0, 0.2), 10.0, 0)
+            .unwrap()
+            .evicted;
+        assert_eq!(evicted.len(), 2);
+        assert_eq!(evicted[0].txid(), parent.txid());
+        assert_eq!(mempool.len(), 2);
+        assert!(mempool.by_sender(&sender.public_key.into()).is_empty());
+    }
+
+    #[test]
+    fn descendants_paying_more_are_not_evicted() {
+        let mut mempool = mempool(2);
+        let sender = Client::new();
+        mempool.insert(payment(&sender, 0, 0.1), 10.0, 0).unwrap();
+        mempool.insert(payment(&sender, 1, 0.5), 10.0, 0).unwrap();
+
+        assert!(mempool
+            .insert(payment(&Client::new(), 0, 0.2), 10.0, 0)
+            .is_err());
+        assert_eq!(mempool.len(), 2);
+    }
+
+    #[test]
+    fn full_mempool_refuses_a_lower_fee() {
+        let mut mempool = mempool(1);
+        mempool
+            .insert(payment(&Client::new(), 0, 0.2), 10.0, 0)
+            .unwrap();
+
+        assert!(mempool
+            .insert(payment(&Client::new(), 0, 0.1), 10.0, 0)
+            .is_err());
+        assert_eq!(mempool.len(), 1);
+    }
+
+    #[test]
+    fn byte_limit_is_enforced() {
+        let first = payment(&Client::new(), 0, 0.1);
+        let mut mempool = Mempool::new(MempoolLimits {
+            max_bytes: first.size() + 16,
+            ..MempoolLimits::default()
+        });
+        mempool.insert(first.clone(), 10.0, 0).unwrap();
+
+        let evicted = mempool
+            .insert(payment(&Client::new(), 0, 0.2), 10.0, 0)
+            .unwrap()
+            .evicted;
+        assert_eq!(evicted[0].txid(), first.txid());
+        assert!(mempool.size_bytes() <= mempool.limits().max_bytes);
+    }
+
+    #[test]
+    fn pending_spend_cannot_exceed_the_balance() {
+        let mut mempool = mempool(10);
+        let sender = Client::new();
+        mempool.insert(payment(&sender, 0, 0.1), 2.5, 0).unwrap();
+        mempool.insert(payment(&sender, 1, 0.1), 2.5, 0).unwrap();
+
+        assert!(mempool.insert(payment(&sender, 2, 0.1), 2.5, 0).is_err());
+        assert!((mempool.pending_spend(&sender.public_key.into()) - 2.2).abs() < 1e-9);
+    }
+
+    #[test]
+    fn old_transactions_are_evicted() {
+        let mut mempool = mempool(10);
+        let transaction = payment(&Client::new(), 0, 0.1);
+        mempool.insert(transaction.clone(), 10.0, 0).unwrap();
+        let max_age = Duration::from_std(mempool.limits().max_age).unwrap();
+
+        assert!(mempool
+            .evict_expired(transaction.time() + max_age)
+            .expired
+            .is_empty());
+        let evicted = mempool.evict_expired(transaction.time() + max_age + Duration::seconds(1));
+        assert_eq!(evicted.expired.len(), 1);
+        assert!(evicted.dependents.is_empty());
+        assert!(mempool.is_empty());
+    }
+
+    #[test]
+    fn replacement_must_raise_the_fee() {
+        let mut mempool = mempool(10);
+        let sender = Client::new();
+        let original = payment(&sender, 0, 0.1);
+        mempool.insert(original.clone(), 10.0, 0).unwrap();
+
+        let bump = mempool.limits().min_fee_bump;
+        assert!(mempool
+            .insert(payment(&sender, 0, 0.1 + bump / 2.0), 10.0, 0)
+            .is_err());
+        let replacement = payment(&sender, 0, 0.1 + bump);
+        let displaced = mempool.insert(replacement.clone(), 10.0, 0).unwrap();
+        assert_eq!(displaced.replaced.unwrap().txid(), original.txid());
+        assert!(displaced.evicted.is_empty());
+        assert_eq!(mempool.len(), 1);
+        assert!(mempool.contains(&replacement.txid()));
+    }
+
+    #[test]
+    fn nonces_must_follow_each_other() {
+        let mut mempool = mempool(10);
+        let sender = Client::new();
+        assert!(mempool.insert(payment(&sender, 2, 0.1), 10.0, 3).is_err());
+        assert!(mempool.insert(payment(&sender, 4, 0.1), 10.0, 3).is_err());
+        mempool.insert(payment(&sender, 3, 0.1), 10.0, 3).unwrap();
+        mempool.insert(payment(&sender, 4, 0.1), 10.0, 3).unwrap();
+
+        assert_eq!(mempool.next_nonce(&sender.public_key.into(), 3), 5);
+    }
+
+    #[test]
+    fn descendants_are_removed_with_their_parent() {
+        let mut mempool = mempool(10);
+        let sender = Client::new();
+        let parent = payment(&sender, 0, 0.1);
+        mempool.insert(parent.clone(), 10.0, 0).unwrap();
+        mempool.insert(payment(&sender, 1, 0.1), 10.0, 0).unwrap();
+        mempool
+            .insert(payment(&Client::new(), 0, 0.1), 10.0, 0)
+            .unwrap();
+
+        assert_eq!(mempool.remove_with_descendants(&parent.txid()).len(), 2);
+        assert_eq!(mempool.len(), 1);
+        assert!(mempool.by_sender(&sender.public_key.into()).is_empty());
+    }
+
+    #[test]
+    fn expired_transactions_are_removed_with_their_descendants() {
+        let mut mempool = mempool(10);
+        let sender = Client::new();
+        let mut expiring = payment(&sender, 0, 0.1);
+        expiring.expiry_height = Some(5);
+        expiring.sign_transaction(&sender);
+        mempool.insert(expiring.clone(), 10.0, 0).unwrap();
+        let dependent = payment(&sender, 1, 0.1);
+        mempool.insert(dependent.clone(), 10.0, 0).unwrap();
+
+        assert!(mempool.remove_expired(5).expired.is_empty());
+        let removed = mempool.remove_expired(6);
+        assert_eq!(removed.expired.len(), 1);
+        assert_eq!(removed.expired[0].txid(), expiring.txid());
+        assert_eq!(removed.dependents.len(), 1);
+        assert_eq!(removed.dependents[0].txid(), dependent.txid());
+        assert!(mempool.is_empty());
+    }
+}
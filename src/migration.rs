@@ -0,0 +1,152 @@
+use crate::{
+    blockchain::Blockchain,
+    config::{BlockchainConfig, Network},
+    error::{Error, Result},
+    storage,
+};
+use std::fs::{create_dir_all, rename};
+
+/// The version of the on-disk schema written by this binary.
+pub const SCHEMA_VERSION: u32 = 6;
+
+/// A migration upgrading the stores of a blockchain by one schema version.
+type Migration = fn(&mut Blockchain) -> Result<()>;
+
+/// The migrations in order: the migration at index `i` upgrades version `i` to `i + 1`.
+const MIGRATIONS: [Migration; SCHEMA_VERSION as usize] = [
+    migrate_v0_to_v1,
+    migrate_v1_to_v2,
+    migrate_v2_to_v3,
+    migrate_v3_to_v4,
+    migrate_v4_to_v5,
+    migrate_v5_to_v6,
+];
+
+/// The stores of the original layout, kept directly inside the data directory.
+const LEGACY_STORES: [&str; 4] = ["_blocks", "_blocks_metadata", "_balances", "_wallets"];
+
+/// This method moves the stores of the original layout into the mainnet chain directory.
+///
+/// Before networks existed every store lived directly inside the data directory, so such
+/// a directory can only hold a mainnet chain.
+pub fn migrate_legacy_layout(config: &BlockchainConfig) -> Result<()> {
+    let chain_dir = config.chain_dir();
+    if config.network != Network::Mainnet
+        || chain_dir.exists()
+        || !config.data_dir.join(LEGACY_STORES[0]).exists()
+    {
+        return Ok(());
+    }
+
+    create_dir_all(&chain_dir)?;
+    for store in LEGACY_STORES {
+        let legacy_path = config.data_dir.join(store);
+        if legacy_path.exists() {
+            rename(legacy_path, chain_dir.join(store))?;
+        }
+    }
+
+    Ok(())
+}
+
+/// This method upgrades the stores of a blockchain to the current schema version.
+///
+/// A new chain is marked with the current version. A database without a version marker
+/// was written before versioning existed and is treated as version 0.
+pub fn migrate(blockchain: &mut Blockchain) -> Result<()> {
+    let version = match storage::get_schema_version(&blockchain.block_metadata)? {
+        Some(version) => version,
+        None if storage::get_latest_block_hash(&blockchain.block_metadata)?.is_none() => {
+            return storage::set_schema_version(&blockchain.block_metadata, SCHEMA_VERSION)
+        }
+        None => 0,
+    };
+
+    if version > SCHEMA_VERSION {
+        return Err(Error::UnsupportedSchema {
+            found: version,
+            supported: SCHEMA_VERSION,
+        });
+    }
+
+    // Version 4 introduced the coinbase maturity rule: the stored blocks were accepted
+    // without it, and every height they may be stored at is exempt from it
+    if version < 4 {
+        let stored_blocks = storage::count_blocks(&blockchain.blocks)?;
+        storage::set_maturity_height(&blockchain.block_metadata, stored_blocks + 1)?;
+    }
+
+    for (from, migration) in MIGRATIONS.iter().enumerate().skip(version as usize) {
+        migration(blockchain)?;
+        storage::set_schema_version(&blockchain.block_metadata, from as u32 + 1)?;
+    }
+
+    Ok(())
+}
+
+/// Version 0 stored block heights off by one and had no height index, headers or undo
+/// data, all of which are rebuilt from the stored blocks.
+fn migrate_v0_to_v1(blockchain: &mut Blockchain) -> Result<()> {
+    blockchain.reindex()?;
+    Ok(())
+}
+
+/// Version 1 stored blocks as JSON, which are converted to binary records.
+fn migrate_v1_to_v2(blockchain: &mut Blockchain) -> Result<()> {
+    storage::convert_blocks(&blockchain.blocks, blockchain.config.compress_blocks)?;
+    Ok(())
+}
+
+/// Version 2 had no transaction index, which is rebuilt from the stored blocks.
+fn migrate_v2_to_v3(blockchain: &mut Blockchain) -> Result<()> {
+    blockchain.reindex()?;
+    Ok(())
+}
+
+/// Version 3 did not record coinbase rewards, which are read from the stored blocks.
+///
+/// The blocks are not replayed: spends of immature rewards accepted before the maturity
+/// rule existed are kept, and the blocks below the maturity height recorded by `migrate`
+/// stay exempt from the rule when they are replayed later.
+fn migrate_v3_to_v4(blockchain: &mut Blockchain) -> Result<()> {
+    let latest = storage::get_latest_block_number(&blockchain.block_metadata)?;
+    for height in 1..=latest {
+        let block = match blockchain.get_block_at_height(height)? {
+            Some(block) => block,
+            None => continue,
+        };
+        if let Some(coinbase) = block.verified_transactions.first() {
+            storage::set_coinbase(
+                &blockchain.block_metadata,
+                height,
+                coinbase.receiver.address(blockchain.config.network),
+                coinbase.amount,
+            )?;
+        }
+    }
+    Ok(())
+}
+
+/// Version 4 kept the balances, undo data and coinbase records of accounts by public key,
+/// which are now kept by address.
+fn migrate_v4_to_v5(blockchain: &mut Blockchain) -> Result<()> {
+    let network = blockchain.config.network;
+    storage::convert_balances(&blockchain.balances, network)?;
+    storage::convert_block_records(&blockchain.block_metadata, network)
+}
+
+/// Version 5 had no address index, which is built from the stored blocks. Blocks whose
+/// body was pruned cannot be indexed.
+fn migrate_v5_to_v6(blockchain: &mut Blockchain) -> Result<()> {
+    let latest = storage::get_latest_block_number(&blockchain.block_metadata)?;
+    for height in 0..=latest {
+        if let Some(block) = blockchain.get_block_at_height(height)? {
+            storage::add_transactions(
+                &blockchain.block_metadata,
+                &block,
+                blockchain.config.network,
+            )?;
+        }
+    }
+    Ok(())
+}
@@ -0,0 +1,185 @@
+use crate::{
+    address::Address,
+    client::PublicKey,
+    codec::{self, Reader},
+    config::Network,
+    error::{Error, Result},
+};
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
+use std::{fmt, str::FromStr};
+
+/// The largest number of keys of a multisig policy.
+pub const MAX_MULTISIG_KEYS: usize = 15;
+
+/// A policy letting any `threshold` keys among a set of keys spend together from its address.
+///
+/// `threshold` contains the number of signatures needed to spend.
+/// `public_keys` contains the keys allowed to sign, in the order they were given.
+#[derive(Clone, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct Multisig {
+    threshold: usize,
+    public_keys: Vec<PublicKey>,
+}
+
+impl Multisig {
+    /// This method creates a policy, checking that the threshold can be reached and that
+    /// no key is repeated.
+    pub fn new(threshold: usize, public_keys: Vec<PublicKey>) -> Result<Self> {
+        if public_keys.is_empty() || public_keys.len() > MAX_MULTISIG_KEYS {
+            return Err(Error::validation(format!(
+                "Multisig policy must have between 1 and {} keys",
+                MAX_MULTISIG_KEYS
+            )));
+        }
+        if threshold == 0 || threshold > public_keys.len() {
+            return Err(Error::validation(
+                "Multisig threshold must be between 1 and the number of keys",
+            ));
+        }
+        if public_keys
+            .iter()
+            .enumerate()
+            .any(|(i, public_key)| public_keys[..i].contains(public_key))
+        {
+            return Err(Error::validation("Multisig policy repeats a key"));
+        }
+
+        Ok(Self {
+            threshold,
+            public_keys,
+        })
+    }
+
+    pub fn threshold(&self) -> usize {
+        self.threshold
+    }
+
+    pub fn public_keys(&self) -> &[PublicKey] {
+        &self.public_keys
+    }
+
+    /// This method returns the address of the policy on a network.
+    pub fn address(&self, network: Network) -> Address {
+        Address::from_multisig(self, network)
+    }
+
+    /// This method appends the binary encoding of the policy to `buffer`.
+    pub fn encode(&self, buffer: &mut Vec<u8>) {
+        codec::put_u8(buffer, self.threshold as u8);
+        codec::put_u8(buffer, self.public_keys.len() as u8);
+        for public_key in &self.public_keys {
+            buffer.extend(public_key.serialize());
+        }
+    }
+
+    /// This method decodes a policy written by `encode`.
+    pub fn decode(reader: &mut Reader) -> Result<Self> {
+        let threshold = reader.u8()? as usize;
+        let count = reader.u8()?;
+        let public_keys = (0..count)
+            .map(|_| Ok(PublicKey::from_slice(reader.take(33)?)?))
+            .collect::<Result<_>>()?;
+        Self::new(threshold, public_keys)
+    }
+}
+
+impl fmt::Display for Multisig {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "multi({}", self.threshold)?;
+        for public_key in &self.public_keys {
+            write!(f, ",{}", public_key)?;
+        }
+        f.write_str(")")
+    }
+}
+
+impl fmt::Debug for Multisig {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "Multisig({})", self)
+    }
+}
+
+impl FromStr for Multisig {
+    type Err = Error;
+
+    /// This method parses a policy written as `multi(<threshold>,<public key>,...)`.
+    fn from_str(s: &str) -> Result<Self> {
+        let mut parts = s
+            .strip_prefix("multi(")
+            .and_then(|s| s.strip_suffix(')'))
+            .ok_or_else(|| {
+                Error::Serialization(String::from(
+                    "Multisig policy must be written as multi(<threshold>,<public key>,...)",
+                ))
+            })?
+            .split(',');
+        let threshold = parts
+            .next()
+            .and_then(|threshold| usize::from_str(threshold.trim()).ok())
+            .ok_or_else(|| {
+                Error::Serialization(String::from("Multisig threshold is not a number"))
+            })?;
+        let public_keys = parts
+            .map(|public_key| Ok(PublicKey::from_str(public_key.trim())?))
+            .collect::<Result<_>>()?;
+        Self::new(threshold, public_keys)
+    }
+}
+
+impl Serialize for Multisig {
+    fn serialize<S: Serializer>(&self, serializer: S) -> std::result::Result<S::Ok, S::Error> {
+        serializer.collect_str(self)
+    }
+}
+
+impl<'de> Deserialize<'de> for Multisig {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> std::result::Result<Self, D::Error> {
+        let multisig = String::deserialize(deserializer)?;
+        Multisig::from_str(&multisig).map_err(serde::de::Error::custom)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::client::Client;
+
+    fn keys(count: usize) -> Vec<PublicKey> {
+        (0..count).map(|_| Client::new().public_key).collect()
+    }
+
+    #[test]
+    fn policy_round_trip() {
+        let multisig = Multisig::new(2, keys(3)).unwrap();
+        assert_eq!(Multisig::from_str(&multisig.to_string()).unwrap(), multisig);
+
+        let mut buffer = Vec::new();
+        multisig.encode(&mut buffer);
+        let mut reader = Reader::new(&buffer);
+        assert_eq!(Multisig::decode(&mut reader).unwrap(), multisig);
+        assert!(reader.is_empty());
+    }
+
+    #[test]
+    fn invalid_policies_are_refused() {
+        assert!(Multisig::new(1, vec![]).is_err());
+        assert!(Multisig::new(0, keys(2)).is_err());
+        assert!(Multisig::new(3, keys(2)).is_err());
+        assert!(Multisig::new(1, keys(MAX_MULTISIG_KEYS + 1)).is_err());
+        let key = Client::new().public_key;
+        assert!(Multisig::new(1, vec![key, key]).is_err());
+        assert!(Multisig::from_str("multi(two)").is_err());
+    }
+
+    #[test]
+    fn address_depends_on_the_policy() {
+        let public_keys = keys(2);
+        let one_of_two = Multisig::new(1, public_keys.clone()).unwrap();
+        let two_of_two = Multisig::new(2, public_keys).unwrap();
+        let address = one_of_two.address(Network::Mainnet);
+
+        assert!(address.is_multisig());
+        assert_ne!(address, two_of_two.address(Network::Mainnet));
+        assert_ne!(address, one_of_two.address(Network::Testnet));
+    }
+}
@@ -0,0 +1,53 @@
+use crate::{
+    config::Network,
+    error::{Error, Result},
+    transaction::Transaction,
+};
+use serde::{Deserialize, Serialize};
+use std::path::Path;
+
+/// A transaction handed as a file between an online node and offline signers.
+///
+/// The node creates the transaction unsigned from the state of its sender, the signers add
+/// their signatures with only their keys, and the node broadcasts it once it has enough
+/// signatures. The keys of a multisig sender can sign the file in turn or sign copies that
+/// are combined before broadcasting.
+///
+/// `network` contains the name of the network the transaction belongs to.
+/// `transaction` contains the transaction with the signatures gathered so far.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct OfflineTransaction {
+    pub network: String,
+    pub transaction: Transaction,
+}
+
+impl OfflineTransaction {
+    pub fn new(network: Network, transaction: Transaction) -> Self {
+        Self {
+            network: network.name().to_string(),
+            transaction,
+        }
+    }
+
+    /// This method checks that the transaction belongs to the network.
+    pub fn verify(&self, network: Network) -> Result<()> {
+        if self.network != network.name() {
+            return Err(Error::Serialization(format!(
+                "Transaction belongs to network {}, expected {}",
+                self.network, network
+            )));
+        }
+        Ok(())
+    }
+
+    /// This method writes the transaction to a file.
+    pub fn write(&self, path: &Path) -> Result<()> {
+        std::fs::write(path, serde_json::to_vec_pretty(self)?)?;
+        Ok(())
+    }
+
+    /// This method reads a transaction from a file.
+    pub fn read(path: &Path) -> Result<Self> {
+        Ok(serde_json::from_slice(&std::fs::read(path)?)?)
+    }
+}
@@ -0,0 +1,194 @@
+use crate::{
+    error::{Error, Result},
+    transaction::Transaction,
+};
+use std::{
+    collections::{HashSet, VecDeque},
+    sync::Mutex,
+    thread,
+};
+
+/// The fewest signatures worth handing to a thread of their own.
+const MIN_TRANSACTIONS_PER_THREAD: usize = 64;
+
+/// A bounded set of the signed transactions already found valid, so a transaction checked
+/// when it entered the mempool is not checked again when its block arrives.
+///
+/// Transactions are identified by their signed hash, so a transaction whose signatures
+/// changed is checked again. The oldest entries are dropped first once `capacity` is
+/// reached.
+pub struct SignatureCache {
+    capacity: usize,
+    entries: Mutex<CacheEntries>,
+}
+
+/// The entries of a signature cache.
+///
+/// `valid` contains the signed hashes of the valid transactions.
+/// `order` contains the same hashes, oldest first.
+#[derive(Default)]
+struct CacheEntries {
+    valid: HashSet<Vec<u8>>,
+    order: VecDeque<Vec<u8>>,
+}
+
+impl SignatureCache {
+    pub fn new(capacity: usize) -> Self {
+        Self {
+            capacity,
+            entries: Mutex::new(CacheEntries::default()),
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        self.entries.lock().unwrap().valid.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// This method checks whether a transaction with the given signed hash was found valid.
+    fn contains(&self, signed_hash: &[u8]) -> bool {
+        self.entries.lock().unwrap().valid.contains(signed_hash)
+    }
+
+    /// This method records the signed hashes of transactions found valid, dropping the
+    /// oldest entries beyond the capacity.
+    fn insert_all(&self, signed_hashes: impl IntoIterator<Item = Vec<u8>>) {
+        if self.capacity == 0 {
+            return;
+        }
+        let mut entries = self.entries.lock().unwrap();
+        for signed_hash in signed_hashes {
+            if entries.valid.insert(signed_hash.clone()) {
+                entries.order.push_back(signed_hash);
+            }
+        }
+        while entries.order.len() > self.capacity {
+            let oldest = entries.order.pop_front().unwrap();
+            entries.valid.remove(&oldest);
+        }
+    }
+
+    /// This method verifies the amounts and signatures of a transaction unless it was
+    /// already found valid, and remembers it when it is.
+    pub fn verify(&self, transaction: &Transaction) -> Result<()> {
+        let signed_hash = transaction.signed_hash();
+        if !self.contains(&signed_hash) {
+            transaction.is_valid_transaction()?;
+            self.insert_all([signed_hash]);
+        }
+        Ok(())
+    }
+
+    /// This method verifies the amounts and signatures of transactions that were not found
+    /// valid before, splitting them among the available threads that each check their
+    /// share one by one, and remembers the valid ones.
+    ///
+    /// The error of the first invalid transaction is returned with its position, and
+    /// nothing is remembered then. A thread that panics fails the check as well.
+    pub fn verify_transactions(&self, transactions: &[Transaction]) -> Result<()> {
+        let unchecked: Vec<_> = transactions
+            .iter()
+            .enumerate()
+            .map(|(i, transaction)| (i, transaction.signed_hash()))
+            .filter(|(_, signed_hash)| !self.contains(signed_hash))
+            .collect();
+        if unchecked.is_empty() {
+            return Ok(());
+        }
+
+        let threads = thread::available_parallelism()
+            .map_or(1, usize::from)
+            .min(unchecked.len() / MIN_TRANSACTIONS_PER_THREAD)
+            .max(1);
+        let chunk_size = unchecked.len().div_ceil(threads);
+        // Chunks are contiguous, so the first failure of the earliest failing chunk is the
+        // first invalid transaction
+        let failure = thread::scope(|scope| {
+            let checks: Vec<_> = unchecked
+                .chunks(chunk_size)
+                .map(|chunk| {
+                    scope.spawn(move || {
+                        chunk.iter().find_map(|(i, _)| {
+                            transactions[*i]
+                                .is_valid_transaction()
+                                .err()
+                                .map(|e| e.at_tx(*i))
+                        })
+                    })
+                })
+                .collect();
+            checks.into_iter().find_map(|check| {
+                check
+                    .join()
+                    .unwrap_or_else(|_| Some(Error::validation("Signature check panicked")))
+            })
+        });
+        if let Some(e) = failure {
+            return Err(e);
+        }
+
+        self.insert_all(unchecked.into_iter().map(|(_, signed_hash)| signed_hash));
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{client::Client, transaction::Receiver};
+
+    fn signed_transactions(count: usize) -> Vec<Transaction> {
+        let sender = Client::new();
+        let receiver = Receiver::PublicKey(Client::new().public_key);
+        (0..count)
+            .map(|nonce| {
+                let mut transaction =
+                    Transaction::new(Some(sender.public_key.into()), receiver, 1.0, None);
+                transaction.nonce = Some(nonce as u64);
+                transaction.sign_transaction(&sender);
+                transaction
+            })
+            .collect()
+    }
+
+    #[test]
+    fn valid_transactions_are_remembered() {
+        let cache = SignatureCache::new(1000);
+        let transactions = signed_transactions(2 * MIN_TRANSACTIONS_PER_THREAD);
+        cache.verify_transactions(&transactions).unwrap();
+        assert_eq!(cache.len(), transactions.len());
+    }
+
+    #[test]
+    fn first_invalid_transaction_is_reported() {
+        let cache = SignatureCache::new(1000);
+        let mut transactions = signed_transactions(3 * MIN_TRANSACTIONS_PER_THREAD);
+        for i in [100, 150] {
+            transactions[i].amount = 2.0;
+        }
+
+        assert!(matches!(
+            cache.verify_transactions(&transactions),
+            Err(Error::Validation {
+                tx_index: Some(100),
+                ..
+            })
+        ));
+        assert!(cache.is_empty());
+    }
+
+    #[test]
+    fn oldest_entries_are_dropped() {
+        let cache = SignatureCache::new(2);
+        let transactions = signed_transactions(3);
+        for transaction in &transactions {
+            cache.verify(transaction).unwrap();
+        }
+        assert_eq!(cache.len(), 2);
+        assert!(!cache.contains(&transactions[0].signed_hash()));
+        assert!(cache.contains(&transactions[2].signed_hash()));
+    }
+}
@@ -0,0 +1,173 @@
+use crate::{
+    address::Address,
+    block::Block,
+    config::Network,
+    error::{Error, Result},
+    transaction::Sender,
+};
+use serde::{Deserialize, Serialize};
+use std::{
+    collections::{BTreeMap, HashMap},
+    path::Path,
+    str::FromStr,
+};
+
+/// A snapshot of the full account state at a block.
+///
+/// `network` contains the name of the network the snapshot belongs to.
+/// `block` contains the block the state was taken at, stored so a node can continue from it.
+/// `balances` contains the balance of every account, sorted by address, or by public key in
+/// snapshots taken before addresses existed.
+/// `nonces` contains the nonce the next transaction of every account must use, sorted by
+/// the public key or multisig policy of the account.
+/// `coinbases` contains the receiver and amount of the coinbase rewards of the blocks up to
+/// the snapshot that were still maturing, by height.
+/// `state_hash` contains the hash committing to all of the above.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Snapshot {
+    pub network: String,
+    pub block: Block,
+    pub balances: BTreeMap<String, f64>,
+    #[serde(default)]
+    pub nonces: BTreeMap<String, u64>,
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub coinbases: BTreeMap<usize, (String, f64)>,
+    pub state_hash: String,
+}
+
+impl Snapshot {
+    /// This method creates a new snapshot and computes its state hash.
+    pub fn new(
+        network: Network,
+        block: Block,
+        balances: &HashMap<Address, f64>,
+        nonces: &HashMap<Sender, u64>,
+        coinbases: &BTreeMap<usize, (Address, f64)>,
+    ) -> Self {
+        let mut snapshot = Self {
+            network: network.name().to_string(),
+            block,
+            balances: balances
+                .iter()
+                .map(|(address, balance)| (address.to_string(), *balance))
+                .collect(),
+            nonces: nonces
+                .iter()
+                .map(|(sender, nonce)| (sender.to_string(), *nonce))
+                .collect(),
+            coinbases: coinbases
+                .iter()
+                .map(|(height, (receiver, amount))| (*height, (receiver.to_string(), *amount)))
+                .collect(),
+            state_hash: String::new(),
+        };
+        snapshot.state_hash = snapshot.calculate_hash();
+        snapshot
+    }
+
+    /// This method returns the height the snapshot was taken at.
+    pub fn height(&self) -> usize {
+        self.block.index
+    }
+
+    /// This method calculates the hash of the snapshot state using SHA256.
+    ///
+    /// Nonces and coinbase rewards are only part of the hash when there are some, so
+    /// snapshots taken before they existed keep their hash.
+    pub fn calculate_hash(&self) -> String {
+        let balances = self
+            .balances
+            .iter()
+            .fold(String::new(), |acc, (public_key, balance)| {
+                acc + public_key + &balance.to_string()
+            });
+        let balances = self
+            .nonces
+            .iter()
+            .fold(balances, |acc, (public_key, nonce)| {
+                acc + "#" + public_key + &nonce.to_string()
+            });
+        let balances = self
+            .coinbases
+            .iter()
+            .fold(balances, |acc, (height, (receiver, amount))| {
+                acc + "|" + &height.to_string() + receiver + &amount.to_string()
+            });
+
+        crypto_hash::hex_digest(
+            crypto_hash::Algorithm::SHA256,
+            format!(
+                "{}{}{}{}",
+                self.network, self.block.index, self.block.block_hash, balances
+            )
+            .as_bytes(),
+        )
+    }
+
+    /// This method checks that the snapshot belongs to the network and matches its state hash.
+    ///
+    /// The state hash stored in the snapshot only detects corruption, since anyone editing
+    /// the state can recompute it: `expected_state_hash` must come from a trusted source.
+    pub fn verify(&self, network: Network, expected_state_hash: &str) -> Result<()> {
+        if self.network != network.name() {
+            return Err(Error::Serialization(format!(
+                "Snapshot belongs to network {}, expected {}",
+                self.network, network
+            )));
+        }
+        if self.calculate_hash() != self.state_hash {
+            return Err(Error::consensus(
+                &self.block.block_hash,
+                "Snapshot state hash is not valid",
+            ));
+        }
+        if self.state_hash != expected_state_hash {
+            return Err(Error::consensus(
+                &self.block.block_hash,
+                "Snapshot state hash does not match the expected one",
+            ));
+        }
+
+        Ok(())
+    }
+
+    /// This method returns the balances of the snapshot keyed by address, converting the
+    /// public keys of older snapshots to their address.
+    pub fn get_balances(&self) -> Result<HashMap<Address, f64>> {
+        let network = Network::from_str(&self.network).map_err(Error::Serialization)?;
+        self.balances
+            .iter()
+            .map(|(account, balance)| Ok((Address::from_legacy(account, network)?, *balance)))
+            .collect()
+    }
+
+    /// This method returns the maturing coinbase rewards of the snapshot by height.
+    pub fn get_coinbases(&self) -> Result<BTreeMap<usize, (Address, f64)>> {
+        let network = Network::from_str(&self.network).map_err(Error::Serialization)?;
+        self.coinbases
+            .iter()
+            .map(|(height, (receiver, amount))| {
+                Ok((*height, (Address::parse(receiver, network)?, *amount)))
+            })
+            .collect()
+    }
+
+    /// This method returns the nonces of the snapshot keyed by sender.
+    pub fn get_nonces(&self) -> Result<HashMap<Sender, u64>> {
+        self.nonces
+            .iter()
+            .map(|(sender, nonce)| Ok((Sender::from_str(sender)?, *nonce)))
+            .collect()
+    }
+
+    /// This method writes the snapshot to a file.
+    pub fn write(&self, path: &Path) -> Result<()> {
+        std::fs::write(path, serde_json::to_vec(self)?)?;
+        Ok(())
+    }
+
+    /// This method reads a snapshot from a file.
+    pub fn read(path: &Path) -> Result<Self> {
+        Ok(serde_json::from_slice(&std::fs::read(path)?)?)
+    }
+}
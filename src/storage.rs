@@ -1,108 +1,438 @@
+use crate::address::Address;
 use crate::block::*;
 use crate::client::*;
+use crate::codec;
+use crate::config::Network;
+use crate::error::{Error, Result};
+use crate::transaction::{Sender, Transaction};
 
-use rocksdb::DB;
-use serde_json;
+use rocksdb::{IteratorMode, WriteBatch, DB};
 use std::collections::HashMap;
 use std::str::FromStr;
 
-pub fn add_block(db: &DB, block: &Block) -> Result<(), &'static str> {
-    let json = serde_json::to_string(block).map_err(|e| e).unwrap();
-    db.put(block.block_hash.clone(), json)
-        .map_err(|e| e)
-        .unwrap();
-    return Ok(());
+pub fn add_block(db: &DB, block: &Block, compress: bool) -> Result<()> {
+    db.put(&block.block_hash, codec::encode_block(block, compress)?)?;
+    Ok(())
 }
 
-pub fn get_block(db: &DB, block_hash: &String) -> Result<Option<Block>, &'static str> {
-    match db.get(block_hash.clone()).expect("Error getting block") {
-        Some(block) => {
-            let block_s = String::from_utf8(block).map_err(|e| e).unwrap();
-            return Ok(Some(serde_json::from_str(&block_s).map_err(|e| e).unwrap()));
+/// This method returns the number of stored blocks.
+pub fn count_blocks(db: &DB) -> Result<usize> {
+    let mut count = 0;
+    for item in db.iterator(IteratorMode::Start) {
+        item?;
+        count += 1;
+    }
+    Ok(count)
+}
+
+/// This method re-encodes every stored block that is not in the requested binary format.
+/// Returns the number of blocks that were converted.
+pub fn convert_blocks(db: &DB, compress: bool) -> Result<usize> {
+    let mut converted = 0;
+    for item in db.iterator(IteratorMode::Start) {
+        let (block_hash, record) = item?;
+        if !codec::is_binary_record(&record, compress) {
+            let block = codec::decode_block(&record)?;
+            db.put(block_hash, codec::encode_block(&block, compress)?)?;
+            converted += 1;
         }
-        None => return Ok(None),
     }
+    Ok(converted)
 }
 
-pub fn get_latest_block_number(db: &DB) -> Result<usize, &'static str> {
-    match get_latest_block_hash(&db).unwrap() {
-        Some(block_hash) => {
-            let block_number = get_block_height(&db, &block_hash)?.unwrap();
-            return Ok(block_number);
+/// This method re-keys the balances stored by public key before addresses existed by their
+/// address on `network`. Returns the number of balances that were converted.
+pub fn convert_balances(db: &DB, network: Network) -> Result<usize> {
+    let mut batch = WriteBatch::default();
+    let mut converted = 0;
+    for item in db.iterator(IteratorMode::Start) {
+        let (key, balance) = item?;
+        if let Ok(public_key) = PublicKey::from_str(std::str::from_utf8(&key)?) {
+            batch.delete(&key);
+            batch.put(
+                Address::from_public_key(&public_key, network).to_string(),
+                balance,
+            );
+            converted += 1;
         }
-        None => return Ok(0),
     }
+    db.write(batch)?;
+    Ok(converted)
 }
 
-pub fn set_latest_block(db: &DB, block_hash: &String, height: usize) -> Result<(), &'static str> {
-    db.put(b"latest_block_hash", block_hash.clone())
-        .map_err(|e| e)
-        .unwrap();
-    db.put(block_hash.clone(), height.to_string())
-        .map_err(|e| e)
-        .unwrap();
-    return Ok(());
+/// This method rewrites the undo data and coinbase records written before addresses
+/// existed, replacing their public keys by their address on `network`.
+pub fn convert_block_records(db: &DB, network: Network) -> Result<()> {
+    let mut batch = WriteBatch::default();
+    for item in db.prefix_iterator(b"undo_") {
+        let (key, undo) = item?;
+        if !key.starts_with(b"undo_") {
+            break;
+        }
+        let undo: Vec<(String, Option<f64>)> = serde_json::from_slice(&undo)?;
+        let undo = undo
+            .into_iter()
+            .map(|(account, balance)| Ok((Address::from_legacy(&account, network)?, balance)))
+            .collect::<Result<BlockUndo>>()?;
+        batch.put(key, serde_json::to_vec(&undo)?);
+    }
+    for item in db.prefix_iterator(b"coinbase_") {
+        let (key, coinbase) = item?;
+        if !key.starts_with(b"coinbase_") {
+            break;
+        }
+        let (receiver, amount): (String, f64) = serde_json::from_slice(&coinbase)?;
+        let receiver = Address::from_legacy(&receiver, network)?;
+        batch.put(key, serde_json::to_vec(&(receiver, amount))?);
+    }
+    db.write(batch)?;
+    Ok(())
+}
+
+pub fn delete_block(db: &DB, block_hash: &str) -> Result<()> {
+    db.delete(block_hash)?;
+    Ok(())
+}
+
+pub fn get_block(db: &DB, block_hash: &str) -> Result<Option<Block>> {
+    match db.get(block_hash)? {
+        Some(block) => Ok(Some(codec::decode_block(&block)?)),
+        None => Ok(None),
+    }
+}
+
+pub fn get_latest_block_number(db: &DB) -> Result<usize> {
+    match get_latest_block_hash(db)? {
+        Some(block_hash) => get_block_height(db, &block_hash)?.ok_or_else(|| {
+            Error::Storage(format!("Height of latest block {} not found", block_hash))
+        }),
+        None => Ok(0),
+    }
 }
 
-pub fn get_latest_block_hash(db: &DB) -> Result<Option<String>, &'static str> {
-    match db
-        .get(b"latest_block_hash")
-        .expect("Error getting latest block hash")
-    {
-        Some(hash) => return Ok(Some(String::from_utf8(hash).map_err(|e| e).unwrap())),
-        None => return Ok(None),
-    };
+fn height_key(height: usize) -> String {
+    format!("height_{}", height)
 }
 
-pub fn get_block_height(db: &DB, block_hash: &String) -> Result<Option<usize>, &'static str> {
-    match db
-        .get(block_hash.clone())
-        .expect("Error getting block height")
-    {
+pub fn set_latest_block(db: &DB, block_hash: &str, height: usize) -> Result<()> {
+    db.put(b"latest_block_hash", block_hash)?;
+    db.put(block_hash, height.to_string())?;
+    db.put(height_key(height), block_hash)?;
+    Ok(())
+}
+
+pub fn get_latest_block_hash(db: &DB) -> Result<Option<String>> {
+    match db.get(b"latest_block_hash")? {
+        Some(hash) => Ok(Some(String::from_utf8(hash)?)),
+        None => Ok(None),
+    }
+}
+
+pub fn get_block_height(db: &DB, block_hash: &str) -> Result<Option<usize>> {
+    match db.get(block_hash)? {
         Some(height) => {
-            let height_s = String::from_utf8(height).map_err(|e| e).unwrap();
-            let height: usize = height_s.parse().unwrap();
-            return Ok(Some(height));
+            let height_s = String::from_utf8(height)?;
+            Ok(Some(height_s.parse()?))
         }
-        None => return Ok(None),
+        None => Ok(None),
+    }
+}
+
+/// This method removes a block from the height index.
+pub fn delete_block_height(db: &DB, block_hash: &str, height: usize) -> Result<()> {
+    db.delete(block_hash)?;
+    db.delete(height_key(height))?;
+    Ok(())
+}
+
+pub fn get_block_hash(db: &DB, height: usize) -> Result<Option<String>> {
+    match db.get(height_key(height))? {
+        Some(hash) => Ok(Some(String::from_utf8(hash)?)),
+        None => Ok(None),
     }
 }
 
-pub fn set_balance(db: &DB, public_key: PublicKey, balance: f64) -> Result<(), &'static str> {
-    db.put(public_key.to_string(), balance.to_string())
-        .map_err(|e| e)
-        .unwrap();
-    return Ok(());
+pub fn add_header(db: &DB, header: &BlockHeader) -> Result<()> {
+    db.put(
+        format!("header_{}", header.block_hash),
+        serde_json::to_vec(header)?,
+    )?;
+    Ok(())
 }
 
-pub fn get_balance(db: &DB, public_key: PublicKey) -> Result<Option<f64>, &'static str> {
-    match db
-        .get(public_key.to_string())
-        .expect("Error getting balance")
-    {
+pub fn get_header(db: &DB, block_hash: &str) -> Result<Option<BlockHeader>> {
+    match db.get(format!("header_{}", block_hash))? {
+        Some(header) => Ok(Some(serde_json::from_slice(&header)?)),
+        None => Ok(None),
+    }
+}
+
+pub fn delete_header(db: &DB, block_hash: &str) -> Result<()> {
+    db.delete(format!("header_{}", block_hash))?;
+    Ok(())
+}
+
+/// The balances a block overwrote, `None` for accounts it created.
+pub type BlockUndo = Vec<(Address, Option<f64>)>;
+
+/// This method stores the undo data of a block so the block can be disconnected later.
+pub fn set_undo(db: &DB, block_hash: &str, undo: &BlockUndo) -> Result<()> {
+    db.put(format!("undo_{}", block_hash), serde_json::to_vec(undo)?)?;
+    Ok(())
+}
+
+pub fn get_undo(db: &DB, block_hash: &str) -> Result<Option<BlockUndo>> {
+    match db.get(format!("undo_{}", block_hash))? {
+        Some(undo) => Ok(Some(serde_json::from_slice(&undo)?)),
+        None => Ok(None),
+    }
+}
+
+pub fn delete_undo(db: &DB, block_hash: &str) -> Result<()> {
+    db.delete(format!("undo_{}", block_hash))?;
+    Ok(())
+}
+
+fn coinbase_key(height: usize) -> String {
+    format!("coinbase_{}", height)
+}
+
+/// This method records the receiver and the amount of the coinbase of the block at
+/// `height`, kept until the block is disconnected so the reward can mature.
+pub fn set_coinbase(db: &DB, height: usize, receiver: Address, amount: f64) -> Result<()> {
+    db.put(
+        coinbase_key(height),
+        serde_json::to_vec(&(receiver, amount))?,
+    )?;
+    Ok(())
+}
+
+pub fn get_coinbase(db: &DB, height: usize) -> Result<Option<(Address, f64)>> {
+    match db.get(coinbase_key(height))? {
+        Some(coinbase) => Ok(Some(serde_json::from_slice(&coinbase)?)),
+        None => Ok(None),
+    }
+}
+
+pub fn delete_coinbase(db: &DB, height: usize) -> Result<()> {
+    db.delete(coinbase_key(height))?;
+    Ok(())
+}
+
+/// This method indexes the transactions of a block by txid and by the addresses on
+/// `network` they debit or credit.
+pub fn add_transactions(db: &DB, block: &Block, network: Network) -> Result<()> {
+    for transaction in block.verified_transactions.iter() {
+        let txid = transaction.txid();
+        db.put(format!("tx_{}", txid), &block.block_hash)?;
+        for address in transaction_addresses(transaction, network) {
+            db.put(
+                address_index_key(address, block.index, &txid),
+                &block.block_hash,
+            )?;
+        }
+    }
+    Ok(())
+}
+
+/// This method returns the hash of the block confirming a transaction.
+pub fn get_transaction_block(db: &DB, txid: &str) -> Result<Option<String>> {
+    match db.get(format!("tx_{}", txid))? {
+        Some(block_hash) => Ok(Some(String::from_utf8(block_hash)?)),
+        None => Ok(None),
+    }
+}
+
+pub fn delete_transactions(db: &DB, block: &Block, network: Network) -> Result<()> {
+    for transaction in block.verified_transactions.iter() {
+        let txid = transaction.txid();
+        db.delete(format!("tx_{}", txid))?;
+        for address in transaction_addresses(transaction, network) {
+            db.delete(address_index_key(address, block.index, &txid))?;
+        }
+    }
+    Ok(())
+}
+
+/// This method returns the height and txid of every confirmed transaction debiting or
+/// crediting an address, oldest first.
+pub fn get_address_transactions(db: &DB, address: Address) -> Result<Vec<(usize, String)>> {
+    let prefix = format!("addr_{}_", address);
+    let mut transactions = Vec::new();
+    for item in db.prefix_iterator(prefix.as_bytes()) {
+        let (key, _) = item?;
+        let entry = match std::str::from_utf8(&key)?.strip_prefix(&prefix) {
+            Some(entry) => entry,
+            None => break,
+        };
+        let (height, txid) = entry
+            .split_once('_')
+            .ok_or_else(|| Error::Storage(format!("Address index entry {} is not valid", entry)))?;
+        transactions.push((height.parse()?, txid.to_string()));
+    }
+    Ok(transactions)
+}
+
+/// This method returns the key of an entry of the address index, sorted by height.
+fn address_index_key(address: Address, height: usize, txid: &str) -> String {
+    format!("addr_{}_{:020}_{}", address, height, txid)
+}
+
+/// This method returns the addresses a transaction debits or credits on a network.
+fn transaction_addresses(transaction: &Transaction, network: Network) -> Vec<Address> {
+    let receiver = transaction.receiver.address(network);
+    match &transaction.sender {
+        Some(sender) if sender.address(network) != receiver => {
+            vec![sender.address(network), receiver]
+        }
+        _ => vec![receiver],
+    }
+}
+
+fn nonce_key(sender: &Sender) -> String {
+    format!("nonce_{}", sender)
+}
+
+/// This method stores the nonce the next transaction of an account must use.
+pub fn set_nonce(db: &DB, sender: &Sender, nonce: u64) -> Result<()> {
+    db.put(nonce_key(sender), nonce.to_string())?;
+    Ok(())
+}
+
+pub fn delete_nonce(db: &DB, sender: &Sender) -> Result<()> {
+    db.delete(nonce_key(sender))?;
+    Ok(())
+}
+
+/// This method returns the nonce the next transaction of an account must use, 0 for an
+/// account that never sent a transaction with a nonce.
+pub fn get_nonce(db: &DB, sender: &Sender) -> Result<u64> {
+    match db.get(nonce_key(sender))? {
+        Some(nonce) => Ok(String::from_utf8(nonce)?.parse()?),
+        None => Ok(0),
+    }
+}
+
+pub fn get_nonces(db: &DB) -> Result<HashMap<Sender, u64>> {
+    let mut nonces = HashMap::new();
+    for item in db.prefix_iterator(b"nonce_") {
+        let (key, value) = item?;
+        let sender = match std::str::from_utf8(&key)?.strip_prefix("nonce_") {
+            Some(sender) => Sender::from_str(sender)?,
+            None => break,
+        };
+        nonces.insert(sender, std::str::from_utf8(&value)?.parse()?);
+    }
+    Ok(nonces)
+}
+
+/// This method records the height of the first block whose body and state are known to
+/// the chain, which is the genesis block unless the chain was started from a snapshot
+/// or pruned.
+pub fn set_base_height(db: &DB, height: usize) -> Result<()> {
+    db.put(b"base_height", height.to_string())?;
+    Ok(())
+}
+
+pub fn get_base_height(db: &DB) -> Result<usize> {
+    match db.get(b"base_height")? {
+        Some(height) => Ok(String::from_utf8(height)?.parse()?),
+        None => Ok(0),
+    }
+}
+
+/// This method records the height of the first block subject to the coinbase maturity
+/// rule. Blocks below it were accepted before the rule and the emission schedule existed.
+pub fn set_maturity_height(db: &DB, height: usize) -> Result<()> {
+    db.put(b"maturity_height", height.to_string())?;
+    Ok(())
+}
+
+pub fn get_maturity_height(db: &DB) -> Result<usize> {
+    match db.get(b"maturity_height")? {
+        Some(height) => Ok(String::from_utf8(height)?.parse()?),
+        None => Ok(0),
+    }
+}
+
+/// This method records the sum of the stored balances, kept up to date as blocks are
+/// connected and disconnected so the supply check does not add up every balance.
+pub fn set_total_balance(db: &DB, total: f64) -> Result<()> {
+    db.put(b"total_balance", total.to_string())?;
+    Ok(())
+}
+
+/// This method returns the recorded sum of the stored balances, or `None` for databases
+/// written before it was recorded.
+pub fn get_total_balance(db: &DB) -> Result<Option<f64>> {
+    match db.get(b"total_balance")? {
+        Some(total) => Ok(Some(String::from_utf8(total)?.parse()?)),
+        None => Ok(None),
+    }
+}
+
+pub fn set_snapshot_hash(db: &DB, height: usize, state_hash: &str) -> Result<()> {
+    db.put(format!("snapshot_{}", height), state_hash)?;
+    Ok(())
+}
+
+pub fn get_snapshot_hash(db: &DB, height: usize) -> Result<Option<String>> {
+    match db.get(format!("snapshot_{}", height))? {
+        Some(state_hash) => Ok(Some(String::from_utf8(state_hash)?)),
+        None => Ok(None),
+    }
+}
+
+pub fn set_schema_version(db: &DB, version: u32) -> Result<()> {
+    db.put(b"schema_version", version.to_string())?;
+    Ok(())
+}
+
+pub fn get_schema_version(db: &DB) -> Result<Option<u32>> {
+    match db.get(b"schema_version")? {
+        Some(version) => Ok(Some(String::from_utf8(version)?.parse()?)),
+        None => Ok(None),
+    }
+}
+
+/// This method deletes every key of the store.
+pub fn clear(db: &DB) -> Result<()> {
+    let mut batch = WriteBatch::default();
+    for item in db.iterator(IteratorMode::Start) {
+        let (key, _) = item?;
+        batch.delete(key);
+    }
+    db.write(batch)?;
+    Ok(())
+}
+
+pub fn set_balance(db: &DB, address: Address, balance: f64) -> Result<()> {
+    db.put(address.to_string(), balance.to_string())?;
+    Ok(())
+}
+
+pub fn delete_balance(db: &DB, address: Address) -> Result<()> {
+    db.delete(address.to_string())?;
+    Ok(())
+}
+
+pub fn get_balance(db: &DB, address: Address) -> Result<Option<f64>> {
+    match db.get(address.to_string())? {
         Some(balance) => {
-            let balance_s = String::from_utf8(balance).map_err(|e| e).unwrap();
-            let balance: f64 = balance_s.parse().unwrap();
-            return Ok(Some(balance));
+            let balance_s = String::from_utf8(balance)?;
+            Ok(Some(balance_s.parse()?))
         }
-        None => return Ok(None),
-    };
+        None => Ok(None),
+    }
 }
 
-pub fn get_balances(db: &DB) -> Result<HashMap<PublicKey, f64>, &'static str> {
+pub fn get_balances(db: &DB) -> Result<HashMap<Address, f64>> {
     let mut balances = HashMap::new();
-    let mut iter = db.raw_iterator();
-    iter.seek_to_first();
-    while iter.valid() {
-        let public_key = String::from_utf8(iter.key().unwrap().to_vec())
-            .map_err(|e| e)
-            .unwrap();
-        let balance_s = String::from_utf8(iter.value().unwrap().to_vec())
-            .map_err(|e| e)
-            .unwrap();
-        let balance: f64 = balance_s.parse().unwrap();
-        balances.insert(PublicKey::from_str(&public_key).unwrap(), balance);
-        iter.next();
-    }
-    return Ok(balances);
+    for item in db.iterator(IteratorMode::Start) {
+        let (key, value) = item?;
+        let address = Address::from_str(std::str::from_utf8(&key)?)?;
+        let balance: f64 = std::str::from_utf8(&value)?.parse()?;
+        balances.insert(address, balance);
+    }
+    Ok(balances)
 }
@@ -1,42 +1,215 @@
+use crate::address::Address;
 use crate::client::*;
-use chrono::{DateTime, Utc};
+use crate::codec::{self, Reader};
+use crate::config::Network;
+use crate::error::{Error, Result};
+use crate::multisig::Multisig;
+use chrono::{DateTime, TimeZone, Utc};
 use secp256k1::Message;
 use serde::{Deserialize, Serialize};
-use std::{fmt::Debug, str::FromStr};
+use std::{collections::BTreeMap, fmt::Debug, str::FromStr};
+
+const HAS_SENDER: u8 = 0x01;
+const HAS_SIGNATURE: u8 = 0x02;
+const HAS_FEE: u8 = 0x04;
+const HAS_NONCE: u8 = 0x08;
+const HAS_EXPIRY_HEIGHT: u8 = 0x10;
+const HAS_ADDRESS_RECEIVER: u8 = 0x20;
+const HAS_MULTISIG_SENDER: u8 = 0x40;
+const HAS_SIGNATURES: u8 = 0x80;
+
+/// The sender of a transaction: a single key, or a multisig policy whose keys sign together.
+#[derive(Debug, Clone, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(untagged)]
+pub enum Sender {
+    PublicKey(PublicKey),
+    Multisig(Multisig),
+}
+
+impl Sender {
+    /// This method returns the address debited by the transaction on a network.
+    pub fn address(&self, network: Network) -> Address {
+        match self {
+            Sender::PublicKey(public_key) => Address::from_public_key(public_key, network),
+            Sender::Multisig(multisig) => multisig.address(network),
+        }
+    }
+
+    /// This method returns the key of a sender that is a single key.
+    pub fn public_key(&self) -> Option<PublicKey> {
+        match self {
+            Sender::PublicKey(public_key) => Some(*public_key),
+            Sender::Multisig(_) => None,
+        }
+    }
+
+    /// This method returns the keys able to sign for the sender.
+    pub fn public_keys(&self) -> &[PublicKey] {
+        match self {
+            Sender::PublicKey(public_key) => std::slice::from_ref(public_key),
+            Sender::Multisig(multisig) => multisig.public_keys(),
+        }
+    }
+}
+
+impl std::fmt::Display for Sender {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Sender::PublicKey(public_key) => write!(f, "{}", public_key),
+            Sender::Multisig(multisig) => write!(f, "{}", multisig),
+        }
+    }
+}
+
+impl FromStr for Sender {
+    type Err = Error;
+
+    /// This method parses a public key or a multisig policy written as `multi(...)`.
+    fn from_str(s: &str) -> Result<Self> {
+        if s.starts_with("multi(") {
+            Ok(Sender::Multisig(Multisig::from_str(s)?))
+        } else {
+            Ok(Sender::PublicKey(PublicKey::from_str(s)?))
+        }
+    }
+}
+
+impl From<PublicKey> for Sender {
+    fn from(public_key: PublicKey) -> Self {
+        Sender::PublicKey(public_key)
+    }
+}
+
+impl From<Multisig> for Sender {
+    fn from(multisig: Multisig) -> Self {
+        Sender::Multisig(multisig)
+    }
+}
+
+/// The receiver of a transaction: an address, or the public key paid by transactions
+/// created before addresses existed.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(untagged)]
+pub enum Receiver {
+    PublicKey(PublicKey),
+    Address(Address),
+}
+
+impl Receiver {
+    /// This method returns the address credited by the transaction on a network.
+    pub fn address(&self, network: Network) -> Address {
+        match self {
+            Receiver::PublicKey(public_key) => Address::from_public_key(public_key, network),
+            Receiver::Address(address) => *address,
+        }
+    }
+
+    /// This method checks whether the receiver is a public key or its address.
+    pub fn is_paid_to(&self, public_key: &PublicKey) -> bool {
+        match self {
+            Receiver::PublicKey(receiver) => receiver == public_key,
+            Receiver::Address(address) => address.is_address_of(public_key),
+        }
+    }
+
+    /// This method checks that a receiver address belongs to the network.
+    pub fn verify_network(&self, network: Network) -> Result<()> {
+        match self {
+            Receiver::Address(address) if address.network() != Some(network) => Err(
+                Error::validation("Receiver address belongs to another network"),
+            ),
+            _ => Ok(()),
+        }
+    }
+}
+
+impl std::fmt::Display for Receiver {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Receiver::PublicKey(public_key) => write!(f, "{}", public_key),
+            Receiver::Address(address) => write!(f, "{}", address),
+        }
+    }
+}
+
+impl From<Address> for Receiver {
+    fn from(address: Address) -> Self {
+        Receiver::Address(address)
+    }
+}
+
+impl From<PublicKey> for Receiver {
+    fn from(public_key: PublicKey) -> Self {
+        Receiver::PublicKey(public_key)
+    }
+}
 
 /// A transaction structure that can be used to record a transaction in the blockchain.
 ///
-/// `sender` contains the public key of the client that is sending the transaction.
-/// `receiver` contains the public key of the client that is receiving the transaction.
+/// `sender` contains the public key of the client that is sending the transaction, or the
+/// multisig policy of the address it spends from.
+/// `receiver` contains the address of the client that is receiving the transaction.
 /// `amount` contains the amount of money that is being sent.
+/// `fee` contains the amount paid by the sender to the miner of the block including it.
+/// `nonce` contains the position of the transaction among those of its sender, `None` for
+/// transactions created before nonces existed.
+/// `expiry_height` contains the height of the last block the transaction can be included in.
+/// `scheme` contains the scheme the signatures of the transaction are made with.
 /// `signature` contains the signature of the transaction.
+/// `signatures` contains the signatures gathered from the keys of a multisig sender.
 /// `timestamp` contains the time at which the transaction was created.
 #[derive(Clone, Serialize, Deserialize)]
 pub struct Transaction {
-    pub sender: Option<PublicKey>,
-    pub receiver: PublicKey,
+    pub sender: Option<Sender>,
+    pub receiver: Receiver,
     time: DateTime<Utc>,
     pub amount: f64,
+    #[serde(default)]
+    pub fee: f64,
+    #[serde(default)]
+    pub nonce: Option<u64>,
+    #[serde(default)]
+    pub expiry_height: Option<usize>,
+    #[serde(default, skip_serializing_if = "SignatureScheme::is_ecdsa")]
+    pub scheme: SignatureScheme,
     signature: Option<String>,
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    signatures: BTreeMap<PublicKey, String>,
 }
 
 impl Debug for Transaction {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
-        f.debug_struct("Transaction")
-            .field("sender", &format!("{}", self.sender.unwrap()))
+        let mut debug = f.debug_struct("Transaction");
+        debug
+            .field(
+                "sender",
+                &self.sender.as_ref().map(|sender| sender.to_string()),
+            )
             .field("receiver", &format!("{}", self.receiver))
             .field("time", &self.time)
             .field("amount", &self.amount)
-            .field("signature", &self.signature.as_ref().unwrap())
-            .finish()
+            .field("fee", &self.fee)
+            .field("nonce", &self.nonce)
+            .field("expiry_height", &self.expiry_height)
+            .field("scheme", &self.scheme)
+            .field("signature", &self.signature);
+        if !self.signatures.is_empty() {
+            let signatures: BTreeMap<_, _> = self
+                .signatures
+                .iter()
+                .map(|(public_key, signature)| (public_key.to_string(), signature))
+                .collect();
+            debug.field("signatures", &signatures);
+        }
+        debug.finish()
     }
 }
 
 impl Transaction {
     /// This method creates a new transaction.
     pub fn new(
-        sender: Option<PublicKey>,
-        receiver: PublicKey,
+        sender: Option<Sender>,
+        receiver: Receiver,
         amount: f64,
         signature: Option<String>,
     ) -> Self {
@@ -46,60 +219,403 @@ impl Transaction {
             time: Utc::now(),
             signature,
             amount,
+            fee: 0.0,
+            nonce: None,
+            expiry_height: None,
+            scheme: SignatureScheme::Ecdsa,
+            signatures: BTreeMap::new(),
         }
     }
 
-    /// This method creates a new transaction.
-    pub fn signed_new(sender: &Client, receiver: PublicKey, amount: f64) -> Self {
-        let mut transaction = Transaction::new(Some(sender.public_key), receiver, amount, None);
-        transaction.sign_transaction(&sender);
+    /// This method creates a new transaction paying `fee` and signs it.
+    pub fn signed_new(
+        sender: &Client,
+        receiver: Receiver,
+        amount: f64,
+        fee: f64,
+        nonce: Option<u64>,
+    ) -> Self {
+        let mut transaction =
+            Transaction::new(Some(sender.public_key.into()), receiver, amount, None);
+        transaction.fee = fee;
+        transaction.nonce = nonce;
+        transaction.sign_transaction(sender);
 
         transaction
     }
 
     /// This method serializes the transaction into a string.
+    ///
+    /// The fee, the nonce, the expiry height and a scheme other than ECDSA are only part of
+    /// the serialization when they are set, so transactions created before they existed
+    /// keep their hash and signature.
     pub fn serialize_transaction(&self) -> String {
         let sender = match &self.sender {
             Some(sender) => sender.to_string(),
             None => String::new(),
         };
+        let fee = if self.fee != 0.0 {
+            self.fee.to_string()
+        } else {
+            String::new()
+        };
+        let nonce = match self.nonce {
+            Some(nonce) => format!("#{}", nonce),
+            None => String::new(),
+        };
+        let expiry_height = match self.expiry_height {
+            Some(expiry_height) => format!("@{}", expiry_height),
+            None => String::new(),
+        };
+        let scheme = if self.scheme.is_ecdsa() {
+            String::new()
+        } else {
+            format!("/{}", self.scheme)
+        };
+
+        format!(
+            "{}{}{}{}{}{}{}{}",
+            sender, self.receiver, self.amount, self.time, fee, nonce, expiry_height, scheme
+        )
+    }
+
+    /// This method checks whether the transaction can no longer be included in a block at
+    /// the given height.
+    pub fn is_expired(&self, height: usize) -> bool {
+        self.expiry_height
+            .is_some_and(|expiry_height| height > expiry_height)
+    }
+
+    /// This method returns the total amount taken from the sender.
+    pub fn total_spent(&self) -> f64 {
+        self.amount + self.fee
+    }
+
+    /// This method returns the time the transaction was created at.
+    pub fn time(&self) -> DateTime<Utc> {
+        self.time
+    }
 
-        format!("{}{}{}{}", sender, self.receiver, self.amount, self.time,)
+    /// This method sets the time the transaction was created at. It must be called before
+    /// the transaction is signed.
+    pub fn set_time(&mut self, time: DateTime<Utc>) {
+        self.time = time;
+    }
+
+    /// This method returns the size of the binary encoding of the transaction.
+    pub fn size(&self) -> usize {
+        let mut buffer = Vec::new();
+        self.encode(&mut buffer);
+        buffer.len()
+    }
+
+    /// This method appends the binary encoding of the transaction to `buffer`.
+    ///
+    /// Keys are stored compressed, addresses as their version byte and hash, the signature
+    /// scheme as a byte and signatures as their raw DER or BIP340 bytes.
+    pub fn encode(&self, buffer: &mut Vec<u8>) {
+        let mut flags = 0;
+        match self.sender {
+            Some(Sender::PublicKey(_)) => flags |= HAS_SENDER,
+            Some(Sender::Multisig(_)) => flags |= HAS_MULTISIG_SENDER,
+            None => {}
+        }
+        if self.signature.is_some() {
+            flags |= HAS_SIGNATURE;
+        }
+        if self.fee != 0.0 {
+            flags |= HAS_FEE;
+        }
+        if self.nonce.is_some() {
+            flags |= HAS_NONCE;
+        }
+        if self.expiry_height.is_some() {
+            flags |= HAS_EXPIRY_HEIGHT;
+        }
+        if let Receiver::Address(_) = self.receiver {
+            flags |= HAS_ADDRESS_RECEIVER;
+        }
+        let signatures = self.indexed_signatures();
+        if !signatures.is_empty() {
+            flags |= HAS_SIGNATURES;
+        }
+
+        codec::put_u8(buffer, flags);
+        codec::put_u8(buffer, self.scheme.to_byte());
+        match &self.sender {
+            Some(Sender::PublicKey(public_key)) => buffer.extend(public_key.serialize()),
+            Some(Sender::Multisig(multisig)) => multisig.encode(buffer),
+            None => {}
+        }
+        match &self.receiver {
+            Receiver::PublicKey(public_key) => buffer.extend(public_key.serialize()),
+            Receiver::Address(address) => address.encode(buffer),
+        }
+        codec::put_i64(buffer, self.time.timestamp());
+        codec::put_u32(buffer, self.time.timestamp_subsec_nanos());
+        codec::put_f64(buffer, self.amount);
+        if self.fee != 0.0 {
+            codec::put_f64(buffer, self.fee);
+        }
+        if let Some(nonce) = self.nonce {
+            codec::put_u64(buffer, nonce);
+        }
+        if let Some(expiry_height) = self.expiry_height {
+            codec::put_u64(buffer, expiry_height as u64);
+        }
+        if let Some(signature) = &self.signature {
+            codec::put_hex(buffer, signature);
+        }
+        if !signatures.is_empty() {
+            codec::put_u8(buffer, signatures.len() as u8);
+            for (index, signature) in signatures {
+                codec::put_u8(buffer, index as u8);
+                codec::put_hex(buffer, signature);
+            }
+        }
+    }
+
+    /// This method returns the signatures of a multisig sender next to the position of
+    /// their key in its policy, leaving out the keys outside of it.
+    fn indexed_signatures(&self) -> Vec<(usize, &String)> {
+        let public_keys = match &self.sender {
+            Some(Sender::Multisig(multisig)) => multisig.public_keys(),
+            _ => return Vec::new(),
+        };
+        self.signatures
+            .iter()
+            .filter_map(|(public_key, signature)| {
+                let index = public_keys.iter().position(|key| key == public_key)?;
+                Some((index, signature))
+            })
+            .collect()
+    }
+
+    /// This method decodes a transaction written by `encode`, or written before the scheme
+    /// was stored when `has_scheme` is false: such transactions are signed with ECDSA.
+    pub fn decode(reader: &mut Reader, has_scheme: bool) -> Result<Self> {
+        let flags = reader.u8()?;
+        let scheme = if has_scheme {
+            SignatureScheme::from_byte(reader.u8()?)?
+        } else {
+            SignatureScheme::Ecdsa
+        };
+        let sender = if flags & HAS_SENDER != 0 {
+            Some(Sender::PublicKey(PublicKey::from_slice(reader.take(33)?)?))
+        } else if flags & HAS_MULTISIG_SENDER != 0 {
+            Some(Sender::Multisig(Multisig::decode(reader)?))
+        } else {
+            None
+        };
+        let receiver = if flags & HAS_ADDRESS_RECEIVER != 0 {
+            Receiver::Address(Address::decode(reader)?)
+        } else {
+            Receiver::PublicKey(PublicKey::from_slice(reader.take(33)?)?)
+        };
+        let seconds = reader.i64()?;
+        let nanoseconds = reader.u32()?;
+        let time = Utc
+            .timestamp_opt(seconds, nanoseconds)
+            .single()
+            .ok_or_else(|| Error::Serialization(String::from("Invalid transaction time")))?;
+        let amount = reader.f64()?;
+        let fee = if flags & HAS_FEE != 0 {
+            reader.f64()?
+        } else {
+            0.0
+        };
+        let nonce = if flags & HAS_NONCE != 0 {
+            Some(reader.u64()?)
+        } else {
+            None
+        };
+        let expiry_height = if flags & HAS_EXPIRY_HEIGHT != 0 {
+            Some(reader.u64()? as usize)
+        } else {
+            None
+        };
+        let signature = if flags & HAS_SIGNATURE != 0 {
+            Some(reader.hex()?)
+        } else {
+            None
+        };
+        let mut signatures = BTreeMap::new();
+        if flags & HAS_SIGNATURES != 0 {
+            let public_keys = match &sender {
+                Some(sender) => sender.public_keys(),
+                None => &[],
+            };
+            for _ in 0..reader.u8()? {
+                let public_key = public_keys.get(reader.u8()? as usize).ok_or_else(|| {
+                    Error::Serialization(String::from("Signature key index is not valid"))
+                })?;
+                signatures.insert(*public_key, reader.hex()?);
+            }
+        }
+
+        Ok(Self {
+            sender,
+            receiver,
+            time,
+            amount,
+            fee,
+            nonce,
+            expiry_height,
+            scheme,
+            signature,
+            signatures,
+        })
     }
 
     /// This method calculates the hash of the transaction using SHA256.
     pub fn calculate_hash(&self) -> Vec<u8> {
         crypto_hash::digest(
             crypto_hash::Algorithm::SHA256,
-            &self.serialize_transaction().as_bytes(),
+            self.serialize_transaction().as_bytes(),
+        )
+    }
+
+    /// This method calculates the hash of the transaction together with its signatures.
+    /// Unlike the txid, it changes when a signature is added or replaced.
+    pub fn signed_hash(&self) -> Vec<u8> {
+        let mut signed = self.serialize_transaction();
+        if let Some(signature) = &self.signature {
+            signed.push_str(&format!("|{}", signature));
+        }
+        for (public_key, signature) in &self.signatures {
+            signed.push_str(&format!("|{}:{}", public_key, signature));
+        }
+        crypto_hash::digest(crypto_hash::Algorithm::SHA256, signed.as_bytes())
+    }
+
+    /// This method returns the id of the transaction: the hex encoded hash of its content.
+    pub fn txid(&self) -> String {
+        crypto_hash::hex_digest(
+            crypto_hash::Algorithm::SHA256,
+            self.serialize_transaction().as_bytes(),
         )
     }
 
-    /// This method signs the transaction using the private key of the signer.
+    /// This method signs the transaction using the private key of the signer and the scheme
+    /// of the transaction. The signature of a key of a multisig sender is added to the ones
+    /// gathered so far.
     pub fn sign_transaction(&mut self, signer: &Client) {
-        self.signature = signer.sign(&self.calculate_hash()).to_string().into();
+        let signature = signer.sign_with(self.scheme, &self.calculate_hash());
+        match &self.sender {
+            Some(Sender::Multisig(_)) => {
+                self.signatures.insert(signer.public_key, signature);
+            }
+            _ => self.signature = Some(signature),
+        }
+    }
+
+    /// This method returns the number of signatures the transaction still needs before it
+    /// can be broadcast.
+    pub fn missing_signatures(&self) -> usize {
+        match &self.sender {
+            Some(Sender::Multisig(multisig)) => {
+                let signed = multisig
+                    .public_keys()
+                    .iter()
+                    .filter(|public_key| self.signatures.contains_key(public_key))
+                    .count();
+                multisig.threshold().saturating_sub(signed)
+            }
+            _ => usize::from(self.signature.is_none()),
+        }
     }
 
-    /// This method verifies the signature of the transaction.
-    pub fn is_valid_transaction(&self) -> Result<(), &'static str> {
+    /// This method checks whether a key already signed the transaction.
+    pub fn is_signed_by(&self, public_key: &PublicKey) -> bool {
+        match &self.sender {
+            Some(Sender::Multisig(_)) => self.signatures.contains_key(public_key),
+            Some(Sender::PublicKey(sender)) => sender == public_key && self.signature.is_some(),
+            None => false,
+        }
+    }
+
+    /// This method adds the signatures of another copy of the same transaction, so the keys
+    /// of a multisig sender can sign separate copies.
+    pub fn combine_signatures(&mut self, other: &Transaction) -> Result<()> {
+        if other.txid() != self.txid() {
+            return Err(Error::validation(
+                "Signatures belong to another transaction",
+            ));
+        }
         if self.signature.is_none() {
-            return Err("No signature found.");
+            self.signature = other.signature.clone();
+        }
+        for (public_key, signature) in &other.signatures {
+            self.signatures
+                .entry(*public_key)
+                .or_insert_with(|| signature.clone());
         }
+        Ok(())
+    }
 
-        let secp = Secp256k1::verification_only();
+    /// This method verifies the amounts and the signature of the transaction with its
+    /// signature scheme.
+    pub fn is_valid_transaction(&self) -> Result<()> {
+        if !self.total_spent().is_finite() || self.amount < 0.0 || self.fee < 0.0 {
+            return Err(Error::validation("Amount or fee is not valid"));
+        }
+        if let Some(Sender::Multisig(multisig)) = &self.sender {
+            if self.nonce.is_none() {
+                return Err(Error::validation("Multisig transaction must have a nonce"));
+            }
+            return self.verify_multisig_signatures(multisig);
+        }
+        if !self.signatures.is_empty() {
+            return Err(Error::validation(
+                "Only a multisig sender can gather several signatures",
+            ));
+        }
+
+        let signature = self
+            .signature
+            .as_ref()
+            .ok_or_else(|| Error::validation("No signature found"))?;
+        let sender = self
+            .sender
+            .as_ref()
+            .and_then(Sender::public_key)
+            .ok_or_else(|| Error::validation("No sender found"))?;
+
+        let secp = verification_context();
+
+        let unsigned_transaction_hash = Message::from_slice(self.calculate_hash().as_slice())?;
 
-        let unsigned_transaction_hash =
-            Message::from_slice(self.calculate_hash().as_slice()).unwrap();
+        self.scheme
+            .verify(secp, &unsigned_transaction_hash, signature, &sender)
+    }
 
-        let sig =
-            Signature::from_str(self.signature.as_ref().expect("No signature found.")).unwrap();
+    /// This method verifies the signatures gathered for a multisig sender: every one must be
+    /// valid and made by a key of the policy, and there must be at least its threshold.
+    fn verify_multisig_signatures(&self, multisig: &Multisig) -> Result<()> {
+        if self.signature.is_some() {
+            return Err(Error::validation(
+                "Multisig sender must sign with the keys of its policy",
+            ));
+        }
 
-        match secp.verify_ecdsa(&unsigned_transaction_hash, &sig, &self.sender.unwrap()) {
-            Ok(_) => Ok(()),
-            Err(_) => {
-                println!("Transaction is invalid: {:?}", self);
-                Err("Invalid signature.")
+        let secp = verification_context();
+        let message = Message::from_slice(self.calculate_hash().as_slice())?;
+        for (public_key, signature) in &self.signatures {
+            if !multisig.public_keys().contains(public_key) {
+                return Err(Error::validation(
+                    "Signature is made by a key outside of the multisig policy",
+                ));
             }
+            self.scheme.verify(secp, &message, signature, public_key)?;
+        }
+
+        if self.signatures.len() < multisig.threshold() {
+            return Err(Error::validation(format!(
+                "Multisig sender needs {} signatures, {} found",
+                multisig.threshold(),
+                self.signatures.len()
+            )));
         }
+        Ok(())
     }
 }
@@ -0,0 +1,814 @@
+use crate::{
+    address::Address,
+    client::{All, Client, PublicKey, Secp256k1},
+    codec,
+    config::Network,
+    error::{Error, Result},
+    hd::{self, ExtendedPublicKey, ExtendedSecretKey, HARDENED},
+    keystore::{KdfParams, Keystore, SeedKeystore},
+    transaction::{Receiver, Transaction},
+};
+use bip39::Mnemonic;
+use chrono::Utc;
+use serde::{Deserialize, Serialize};
+use std::{
+    collections::{BTreeMap, HashMap},
+    fs::rename,
+    path::{Path, PathBuf},
+    str::FromStr,
+};
+
+/// The name of the account of a new wallet, and of the single key of older wallets.
+pub const DEFAULT_ACCOUNT: &str = "default";
+/// The BIP44 purpose of the derivation paths of accounts.
+const PURPOSE: u32 = 44;
+/// The BIP44 coin type of the derivation paths of accounts, the one shared by test coins.
+const COIN_TYPE: u32 = 1;
+/// The number of consecutive unused receive keys after which a restore stops looking for
+/// more keys of an account.
+pub const GAP_LIMIT: u32 = 20;
+
+/// An account derived from the seed of the wallet, along the BIP44 path
+/// `m/44'/1'/index'/0/n`.
+///
+/// `index` contains the index of the account in its derivation path.
+/// `public_key` contains the public key of the receive chain of the account,
+/// `m/44'/1'/index'/0`.
+/// `chain_code` contains the chain code of the receive chain, hex encoded.
+/// `receive_keys` contains the receive keys handed out so far, the last one being the
+/// current one.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+struct DerivedAccount {
+    index: u32,
+    public_key: PublicKey,
+    chain_code: String,
+    receive_keys: Vec<PublicKey>,
+}
+
+impl DerivedAccount {
+    fn receive_chain(&self) -> Result<ExtendedPublicKey> {
+        let chain_code = codec::decode_hex(&self.chain_code)
+            .and_then(|chain_code| chain_code.try_into().ok())
+            .ok_or_else(|| Error::Wallet(String::from("Account chain code is not valid")))?;
+        Ok(ExtendedPublicKey {
+            public_key: self.public_key,
+            chain_code,
+        })
+    }
+}
+
+/// An account watched without its secret keys, such as keys kept on hardware elsewhere.
+///
+/// `watch_only` contains the public keys and addresses watched, in the order they were
+/// imported.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+struct WatchOnlyAccount {
+    watch_only: Vec<Receiver>,
+}
+
+/// An account of the wallet: derived from its seed, holding a single key created at
+/// random before wallets had a seed, or watching keys it cannot sign with.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(untagged)]
+enum Account {
+    Derived(DerivedAccount),
+    Imported(Keystore),
+    WatchOnly(WatchOnlyAccount),
+}
+
+impl Account {
+    /// This method returns the public key payments to the account are received on, `None`
+    /// for a watch-only account.
+    fn receive_key(&self) -> Option<PublicKey> {
+        match self {
+            Account::Derived(account) => account.receive_keys.last().copied(),
+            Account::Imported(keystore) => Some(keystore.public_key),
+            Account::WatchOnly(_) => None,
+        }
+    }
+
+    /// This method returns the receive key of the account, or the latest key or address
+    /// a watch-only account imported.
+    fn receiver(&self, name: &str) -> Result<Receiver> {
+        match self {
+            Account::WatchOnly(account) => account.watch_only.last().copied().ok_or_else(|| {
+                Error::Wallet(format!("Watch-only account {} watches nothing", name))
+            }),
+            account => account
+                .receive_key()
+                .map(Receiver::PublicKey)
+                .ok_or_else(|| Error::Wallet(format!("Account {} has no receive key", name))),
+        }
+    }
+
+    /// This method returns the keys the wallet can sign with for the account.
+    fn keys(&self) -> &[PublicKey] {
+        match self {
+            Account::Derived(account) => &account.receive_keys,
+            Account::Imported(keystore) => std::slice::from_ref(&keystore.public_key),
+            Account::WatchOnly(_) => &[],
+        }
+    }
+
+    /// This method returns the addresses of every key or address of the account on a
+    /// network.
+    fn addresses(&self, network: Network) -> Vec<Address> {
+        match self {
+            Account::WatchOnly(account) => account
+                .watch_only
+                .iter()
+                .map(|watched| watched.address(network))
+                .collect(),
+            account => account
+                .keys()
+                .iter()
+                .map(|public_key| Address::from_public_key(public_key, network))
+                .collect(),
+        }
+    }
+}
+
+/// The content of a wallet file.
+///
+/// `seed` contains the encrypted mnemonic every derived account comes from, `None` for
+/// wallets created before wallets had a seed.
+/// `accounts` contains every account, by name.
+/// `active` contains the name of the account payments are sent from by default.
+/// `coinbase` contains the name of the account receiving mining rewards.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+struct WalletFile {
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    seed: Option<SeedKeystore>,
+    accounts: BTreeMap<String, Account>,
+    active: String,
+    coinbase: String,
+}
+
+/// The decrypted state of an unlocked wallet.
+///
+/// `passphrase` contains the passphrase, kept to encrypt the keys of new accounts.
+/// `mnemonic` contains the mnemonic of the seed, when the wallet has one.
+/// `clients` contains the key pair of every key of every account, by public key.
+struct Unlocked {
+    passphrase: String,
+    mnemonic: Option<Mnemonic>,
+    clients: HashMap<PublicKey, Client>,
+}
+
+/// The wallet of a node: named accounts paying and receiving its transactions, derived
+/// from a seed backed up as a mnemonic and stored encrypted with a single passphrase.
+///
+/// `path` contains the path of the wallet file.
+/// `file` contains the accounts as stored in the wallet file.
+/// `unlocked` contains the decrypted key pairs while the wallet is unlocked.
+/// `secp` contains the secp256k1 context used to derive keys.
+pub struct Wallet {
+    path: PathBuf,
+    file: WalletFile,
+    unlocked: Option<Unlocked>,
+    secp: Secp256k1<All>,
+}
+
+impl Wallet {
+    /// This method opens the wallet stored at `path`, creating a new one from a new seed
+    /// with a single account protected by an empty passphrase when there is none.
+    ///
+    /// A wallet protected by an empty passphrase is unlocked right away, any other starts
+    /// locked. Wallet files of older versions holding a single keystore or a bare secret
+    /// key are converted to a wallet with a single account. Those holding the public key
+    /// older versions wrote by mistake never stored a usable secret key: they are kept
+    /// aside with a `lost` extension and a new wallet replaces them. Any other file is
+    /// refused and left in place.
+    pub fn open(path: &Path) -> Result<Self> {
+        let file = if path.exists() {
+            let contents = std::fs::read(path)?;
+            match serde_json::from_slice(&contents) {
+                Ok(file) => file,
+                Err(_) => Self::convert_legacy(path, &contents)?,
+            }
+        } else {
+            let file = Self::from_mnemonic(&hd::generate_mnemonic(), "", |_| false)?;
+            write_file(path, &file)?;
+            file
+        };
+
+        let mut wallet = Self {
+            path: path.to_path_buf(),
+            file,
+            unlocked: None,
+            secp: Secp256k1::new(),
+        };
+        match wallet.unlock("") {
+            Ok(()) | Err(Error::Wallet(_)) => Ok(wallet),
+            Err(e) => Err(e),
+        }
+    }
+
+    /// This method restores the wallet of a mnemonic at `path`, protected by `passphrase`,
+    /// keeping the wallet it replaces aside with a `bak` extension.
+    ///
+    /// Accounts are discovered in order and their receive keys are derived until
+    /// `GAP_LIMIT` consecutive keys were never `used` on the chain. The first account is
+    /// always restored, the others are named after their index.
+    pub fn restore(
+        path: &Path,
+        mnemonic: &Mnemonic,
+        passphrase: &str,
+        used: impl Fn(&PublicKey) -> bool,
+    ) -> Result<Self> {
+        let file = Self::from_mnemonic(mnemonic, passphrase, used)?;
+        if path.exists() {
+            rename(
+                path,
+                path.with_extension(format!("{}.bak", Utc::now().timestamp())),
+            )?;
+        }
+        write_file(path, &file)?;
+
+        let mut wallet = Self {
+            path: path.to_path_buf(),
+            file,
+            unlocked: None,
+            secp: Secp256k1::new(),
+        };
+        wallet.unlock(passphrase)?;
+        Ok(wallet)
+    }
+
+    /// This method creates the content of a wallet derived from a mnemonic, with the
+    /// accounts having keys `used` on the chain.
+    fn from_mnemonic(
+        mnemonic: &Mnemonic,
+        passphrase: &str,
+        used: impl Fn(&PublicKey) -> bool,
+    ) -> Result<WalletFile> {
+        let secp = Secp256k1::new();
+        let master = ExtendedSecretKey::from_seed(&mnemonic.to_seed(""))?;
+
+        let mut accounts = BTreeMap::new();
+        for index in 0.. {
+            let mut account = derive_account(&secp, &master, index)?;
+            let receive_chain = account.receive_chain()?;
+            let mut used_keys = Vec::new();
+            let mut unused_keys = Vec::new();
+            let mut key_index = 0;
+            while unused_keys.len() < GAP_LIMIT as usize {
+                let key = receive_chain.derive_child(&secp, key_index)?.public_key;
+                unused_keys.push(key);
+                if used(&key) {
+                    used_keys.append(&mut unused_keys);
+                }
+                key_index += 1;
+            }
+
+            if index > 0 && used_keys.is_empty() {
+                break;
+            }
+            // The current receive key is always one that was never used.
+            used_keys.push(unused_keys[0]);
+            account.receive_keys = used_keys;
+            let name = match index {
+                0 => DEFAULT_ACCOUNT.to_string(),
+                _ => format!("account{}", index),
+            };
+            accounts.insert(name, Account::Derived(account));
+        }
+
+        Ok(WalletFile {
+            seed: Some(SeedKeystore::encrypt(
+                &mnemonic.to_entropy(),
+                passphrase,
+                KdfParams::default(),
+            )?),
+            accounts,
+            active: DEFAULT_ACCOUNT.to_string(),
+            coinbase: DEFAULT_ACCOUNT.to_string(),
+        })
+    }
+
+    /// This method stores a key pair as the single account of a wallet without a seed,
+    /// protected by an empty passphrase.
+    fn with_single_key(path: &Path, client: Client) -> Result<WalletFile> {
+        let keystore = Keystore::encrypt(
+            &client.secret_key,
+            client.public_key,
+            "",
+            KdfParams::default(),
+        )?;
+        Self::with_single_account(path, keystore)
+    }
+
+    fn with_single_account(path: &Path, keystore: Keystore) -> Result<WalletFile> {
+        let file = WalletFile {
+            seed: None,
+            accounts: BTreeMap::from([(DEFAULT_ACCOUNT.to_string(), Account::Imported(keystore))]),
+            active: DEFAULT_ACCOUNT.to_string(),
+            coinbase: DEFAULT_ACCOUNT.to_string(),
+        };
+        write_file(path, &file)?;
+        Ok(file)
+    }
+
+    /// This method converts a wallet file written before named accounts existed.
+    fn convert_legacy(path: &Path, contents: &[u8]) -> Result<WalletFile> {
+        if let Ok(keystore) = serde_json::from_slice(contents) {
+            return Self::with_single_account(path, keystore);
+        }
+
+        let contents = String::from_utf8_lossy(contents);
+        if let Ok(client) = Client::from(contents.to_string()) {
+            return Self::with_single_key(path, client);
+        }
+        if PublicKey::from_str(contents.trim()).is_err() {
+            return Err(Error::Wallet(format!(
+                "Wallet file {} is in no known format",
+                path.display()
+            )));
+        }
+
+        rename(path, path.with_extension("lost"))?;
+        let file = Self::from_mnemonic(&hd::generate_mnemonic(), "", |_| false)?;
+        write_file(path, &file)?;
+        Ok(file)
+    }
+
+    /// This method returns the current receive key of the active account, known even
+    /// while the wallet is locked.
+    pub fn public_key(&self) -> Result<PublicKey> {
+        self.account_public_key(&self.file.active)
+    }
+
+    /// This method returns the name and current receive key of every account, or the
+    /// latest key or address imported by watch-only accounts, sorted by name.
+    pub fn accounts(&self) -> impl Iterator<Item = (&str, Result<Receiver>)> {
+        self.file
+            .accounts
+            .iter()
+            .map(|(name, account)| (name.as_str(), account.receiver(name)))
+    }
+
+    /// This method returns the current receive key of an account that is not watch-only.
+    pub fn account_public_key(&self, name: &str) -> Result<PublicKey> {
+        self.account(name)?
+            .receive_key()
+            .ok_or_else(|| watch_only(name))
+    }
+
+    /// This method returns every key of an account that is not watch-only, in the order
+    /// they were handed out.
+    pub fn account_keys(&self, name: &str) -> Result<&[PublicKey]> {
+        match self.account(name)? {
+            Account::WatchOnly(_) => Err(watch_only(name)),
+            account => Ok(account.keys()),
+        }
+    }
+
+    /// This method returns the addresses of every key or address of an account on a
+    /// network, watch-only accounts included.
+    pub fn account_addresses(&self, name: &str, network: Network) -> Result<Vec<Address>> {
+        self.account(name).map(|account| account.addresses(network))
+    }
+
+    /// This method checks whether an account only watches keys it cannot sign with.
+    pub fn is_watch_only(&self, name: &str) -> Result<bool> {
+        Ok(matches!(self.account(name)?, Account::WatchOnly(_)))
+    }
+
+    /// This method returns the name of the account owning a public key.
+    pub fn account_of(&self, public_key: &PublicKey) -> Option<&str> {
+        self.file
+            .accounts
+            .iter()
+            .find(|(_, account)| account.keys().contains(public_key))
+            .map(|(name, _)| name.as_str())
+    }
+
+    pub fn active_account(&self) -> &str {
+        &self.file.active
+    }
+
+    pub fn coinbase_account(&self) -> &str {
+        &self.file.coinbase
+    }
+
+    /// This method makes an account the one payments are sent from by default.
+    pub fn use_account(&mut self, name: &str) -> Result<()> {
+        self.account_public_key(name)?;
+        self.file.active = name.to_string();
+        write_file(&self.path, &self.file)
+    }
+
+    /// This method makes an account the one receiving mining rewards.
+    pub fn set_coinbase_account(&mut self, name: &str) -> Result<()> {
+        self.account_public_key(name)?;
+        self.file.coinbase = name.to_string();
+        write_file(&self.path, &self.file)
+    }
+
+    /// This method creates an account, derived from the seed of the wallet at the next
+    /// account index, or with a new random key pair for wallets without a seed. The
+    /// wallet must be unlocked.
+    pub fn new_account(&mut self, name: &str) -> Result<PublicKey> {
+        self.check_new_account_name(name)?;
+        let unlocked = self.unlocked.as_ref().ok_or_else(locked)?;
+
+        let (account, client) = match &unlocked.mnemonic {
+            Some(mnemonic) => {
+                let master = ExtendedSecretKey::from_seed(&mnemonic.to_seed(""))?;
+                let index = self
+                    .file
+                    .accounts
+                    .values()
+                    .filter_map(|account| match account {
+                        Account::Derived(account) => Some(account.index + 1),
+                        Account::Imported(_) | Account::WatchOnly(_) => None,
+                    })
+                    .max()
+                    .unwrap_or(0);
+                let account = derive_account(&self.secp, &master, index)?;
+                let client = Client::from_secret_key(
+                    master
+                        .derive_path(&self.secp, &receive_path(index, 0))?
+                        .secret_key,
+                );
+                (Account::Derived(account), client)
+            }
+            None => {
+                let kdf = match self.account(&self.file.active)? {
+                    Account::Imported(keystore) => keystore.kdf,
+                    Account::Derived(_) | Account::WatchOnly(_) => KdfParams::default(),
+                };
+                let client = Client::new();
+                let keystore = Keystore::encrypt(
+                    &client.secret_key,
+                    client.public_key,
+                    &unlocked.passphrase,
+                    kdf,
+                )?;
+                (Account::Imported(keystore), client)
+            }
+        };
+
+        let public_key = client.public_key;
+        self.file.accounts.insert(name.to_string(), account);
+        if let Err(e) = write_file(&self.path, &self.file) {
+            self.file.accounts.remove(name);
+            return Err(e);
+        }
+        if let Some(unlocked) = self.unlocked.as_mut() {
+            unlocked.clients.insert(public_key, client);
+        }
+
+        Ok(public_key)
+    }
+
+    /// This method adds a public key or an address to a watch-only account, creating the
+    /// account when it does not exist, and returns its address on `network`. The wallet
+    /// does not need to be unlocked.
+    pub fn watch(&mut self, name: &str, watched: Receiver, network: Network) -> Result<Address> {
+        let address = watched.address(network);
+        let mut file = self.file.clone();
+        match file.accounts.get_mut(name) {
+            Some(Account::WatchOnly(account)) => {
+                if account
+                    .watch_only
+                    .iter()
+                    .any(|other| other.address(network) == address)
+                {
+                    return Err(Error::Wallet(format!(
+                        "Account {} already watches {}",
+                        name, address
+                    )));
+                }
+                account.watch_only.push(watched);
+            }
+            Some(_) => {
+                return Err(Error::Wallet(format!(
+                    "Account {} holds keys of the wallet and cannot watch other keys",
+                    name
+                )))
+            }
+            None => {
+                self.check_new_account_name(name)?;
+                file.accounts.insert(
+                    name.to_string(),
+                    Account::WatchOnly(WatchOnlyAccount {
+                        watch_only: vec![watched],
+                    }),
+                );
+            }
+        }
+        write_file(&self.path, &file)?;
+        self.file = file;
+
+        Ok(address)
+    }
+
+    /// This method hands out a new receive key of a derived account, so every payment can
+    /// be received on a key of its own. The wallet does not need to be unlocked.
+    pub fn new_receive_key(&mut self, name: &str) -> Result<PublicKey> {
+        let account = match self.account(name)? {
+            Account::Derived(account) => account,
+            Account::Imported(_) => {
+                return Err(Error::Wallet(format!(
+                    "Account {} holds a single key created before wallets had a seed",
+                    name
+                )))
+            }
+            Account::WatchOnly(_) => return Err(watch_only(name)),
+        };
+        let key_index = account.receive_keys.len() as u32;
+        let public_key = account
+            .receive_chain()?
+            .derive_child(&self.secp, key_index)?
+            .public_key;
+        let client = match &self.unlocked {
+            Some(Unlocked {
+                mnemonic: Some(mnemonic),
+                ..
+            }) => Some(Client::from_secret_key(
+                ExtendedSecretKey::from_seed(&mnemonic.to_seed(""))?
+                    .derive_path(&self.secp, &receive_path(account.index, key_index))?
+                    .secret_key,
+            )),
+            _ => None,
+        };
+
+        let mut file = self.file.clone();
+        if let Some(Account::Derived(account)) = file.accounts.get_mut(name) {
+            account.receive_keys.push(public_key);
+        }
+        write_file(&self.path, &file)?;
+        self.file = file;
+        if let (Some(unlocked), Some(client)) = (self.unlocked.as_mut(), client) {
+            unlocked.clients.insert(public_key, client);
+        }
+
+        Ok(public_key)
+    }
+
+    /// This method returns the mnemonic backing up every derived account. The wallet must
+    /// be unlocked.
+    pub fn mnemonic(&self) -> Result<&Mnemonic> {
+        self.unlocked
+            .as_ref()
+            .ok_or_else(locked)?
+            .mnemonic
+            .as_ref()
+            .ok_or_else(|| {
+                Error::Wallet(String::from(
+                    "The wallet was created before wallets had a seed",
+                ))
+            })
+    }
+
+    pub fn is_locked(&self) -> bool {
+        self.unlocked.is_none()
+    }
+
+    /// This method decrypts the seed and the secret keys of the wallet and derives the key
+    /// pairs of every account, failing without any change on a wrong passphrase.
+    pub fn unlock(&mut self, passphrase: &str) -> Result<()> {
+        let mnemonic = match &self.file.seed {
+            Some(seed) => Some(
+                Mnemonic::from_entropy(&seed.decrypt(passphrase)?)
+                    .map_err(|_| Error::Wallet(String::from("Wallet seed is not valid")))?,
+            ),
+            None => None,
+        };
+        let master = mnemonic
+            .as_ref()
+            .map(|mnemonic| ExtendedSecretKey::from_seed(&mnemonic.to_seed("")))
+            .transpose()?;
+
+        let mut clients = HashMap::new();
+        for (name, account) in &self.file.accounts {
+            match (account, &master) {
+                (Account::Derived(account), Some(master)) => {
+                    let receive_chain =
+                        master.derive_path(&self.secp, &receive_path(account.index, 0)[..4])?;
+                    if receive_chain.public_key(&self.secp) != account.receive_chain()? {
+                        return Err(Error::Wallet(format!(
+                            "Account {} does not match the wallet seed",
+                            name
+                        )));
+                    }
+                    for (key_index, public_key) in account.receive_keys.iter().enumerate() {
+                        let key = receive_chain.derive_child(&self.secp, key_index as u32)?;
+                        clients.insert(*public_key, Client::from_secret_key(key.secret_key));
+                    }
+                }
+                (Account::Derived(_), None) => {
+                    return Err(Error::Wallet(format!(
+                        "Account {} is derived from a seed the wallet does not hold",
+                        name
+                    )))
+                }
+                (Account::Imported(keystore), _) => {
+                    let secret_key = keystore.decrypt(passphrase)?;
+                    clients.insert(keystore.public_key, Client::from_secret_key(secret_key));
+                }
+                (Account::WatchOnly(_), _) => {}
+            }
+        }
+
+        self.unlocked = Some(Unlocked {
+            passphrase: passphrase.to_string(),
+            mnemonic,
+            clients,
+        });
+        Ok(())
+    }
+
+    /// This method drops the decrypted seed, secret keys and passphrase from memory.
+    pub fn lock(&mut self) {
+        self.unlocked = None;
+    }
+
+    /// This method encrypts the seed and the secret keys of the wallet again with a new
+    /// passphrase. The wallet must be unlocked.
+    pub fn change_passphrase(&mut self, passphrase: &str) -> Result<()> {
+        let unlocked = self.unlocked.as_mut().ok_or_else(locked)?;
+        let mut file = self.file.clone();
+        if let (Some(seed), Some(mnemonic)) = (file.seed.as_mut(), &unlocked.mnemonic) {
+            *seed = SeedKeystore::encrypt(&mnemonic.to_entropy(), passphrase, seed.kdf)?;
+        }
+        for account in file.accounts.values_mut() {
+            if let Account::Imported(keystore) = account {
+                let client = &unlocked.clients[&keystore.public_key];
+                *keystore = Keystore::encrypt(
+                    &client.secret_key,
+                    client.public_key,
+                    passphrase,
+                    keystore.kdf,
+                )?;
+            }
+        }
+        write_file(&self.path, &file)?;
+
+        self.file = file;
+        unlocked.passphrase = passphrase.to_string();
+        Ok(())
+    }
+
+    /// This method returns the key pair of the current receive key of the active account
+    /// to sign with, failing while the wallet is locked.
+    pub fn client(&self) -> Result<&Client> {
+        self.key_client(&self.public_key()?)
+    }
+
+    /// This method returns the key pair of a key of the wallet to sign with, failing while
+    /// the wallet is locked.
+    pub fn key_client(&self, public_key: &PublicKey) -> Result<&Client> {
+        if self.account_of(public_key).is_none() {
+            return Err(Error::Wallet(format!(
+                "Key {} does not belong to the wallet",
+                public_key
+            )));
+        }
+        let unlocked = self.unlocked.as_ref().ok_or_else(locked)?;
+        unlocked.clients.get(public_key).ok_or_else(|| {
+            Error::Wallet(format!("The wallet holds no secret key for {}", public_key))
+        })
+    }
+
+    /// This method signs a transaction with every key of the wallet able to sign for its
+    /// sender that has not signed it yet, and returns the number of signatures added. The
+    /// wallet must be unlocked.
+    pub fn sign_transaction(&self, transaction: &mut Transaction) -> Result<usize> {
+        let sender = transaction
+            .sender
+            .clone()
+            .ok_or_else(|| Error::validation("No sender found"))?;
+        let keys: Vec<_> = sender
+            .public_keys()
+            .iter()
+            .filter(|public_key| self.account_of(public_key).is_some())
+            .collect();
+        if keys.is_empty() {
+            return Err(Error::Wallet(String::from(
+                "The wallet holds no key of the sender",
+            )));
+        }
+
+        let mut signed = 0;
+        for public_key in keys {
+            if !transaction.is_signed_by(public_key) {
+                transaction.sign_transaction(self.key_client(public_key)?);
+                signed += 1;
+            }
+        }
+        Ok(signed)
+    }
+
+    /// This method checks that a new account can be created with a name.
+    fn check_new_account_name(&self, name: &str) -> Result<()> {
+        if name.is_empty() || name.contains(char::is_whitespace) {
+            return Err(Error::Wallet(String::from(
+                "Account names must be a single word",
+            )));
+        }
+        if self.file.accounts.contains_key(name) {
+            return Err(Error::Wallet(format!("Account {} already exists", name)));
+        }
+        Ok(())
+    }
+
+    fn account(&self, name: &str) -> Result<&Account> {
+        self.file
+            .accounts
+            .get(name)
+            .ok_or_else(|| Error::Wallet(format!("Account {} not found", name)))
+    }
+}
+
+/// This method returns the derivation path of a receive key of an account.
+fn receive_path(account_index: u32, key_index: u32) -> [u32; 5] {
+    [
+        PURPOSE + HARDENED,
+        COIN_TYPE + HARDENED,
+        account_index + HARDENED,
+        0,
+        key_index,
+    ]
+}
+
+/// This method derives an account with its first receive key.
+fn derive_account(
+    secp: &Secp256k1<All>,
+    master: &ExtendedSecretKey,
+    index: u32,
+) -> Result<DerivedAccount> {
+    let receive_chain = master
+        .derive_path(secp, &receive_path(index, 0)[..4])?
+        .public_key(secp);
+    Ok(DerivedAccount {
+        index,
+        public_key: receive_chain.public_key,
+        chain_code: codec::encode_hex(&receive_chain.chain_code),
+        receive_keys: vec![receive_chain.derive_child(secp, 0)?.public_key],
+    })
+}
+
+fn watch_only(name: &str) -> Error {
+    Error::Wallet(format!(
+        "Account {} is watch-only, the wallet holds none of its secret keys",
+        name
+    ))
+}
+
+fn locked() -> Error {
+    Error::Wallet(String::from("Wallet is locked"))
+}
+
+/// This method writes a wallet file, replacing it atomically.
+fn write_file(path: &Path, file: &WalletFile) -> Result<()> {
+    let tmp_path = path.with_extension("tmp");
+    std::fs::write(&tmp_path, serde_json::to_vec_pretty(file)?)?;
+    rename(tmp_path, path)?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn wallet_path(name: &str) -> PathBuf {
+        let dir = std::env::temp_dir().join(format!("wallet-{}-{}", name, std::process::id()));
+        let _ = std::fs::remove_dir_all(&dir);
+        std::fs::create_dir_all(&dir).unwrap();
+        dir.join("wallet.json")
+    }
+
+    #[test]
+    fn legacy_secret_key_is_converted() {
+        let path = wallet_path("secret");
+        let client = Client::new();
+        std::fs::write(&path, client.secret_key.display_secret().to_string()).unwrap();
+
+        let wallet = Wallet::open(&path).unwrap();
+        assert_eq!(wallet.public_key().unwrap(), client.public_key);
+        assert!(!wallet.is_locked());
+    }
+
+    #[test]
+    fn legacy_public_key_is_kept_aside() {
+        let path = wallet_path("public");
+        let client = Client::new();
+        std::fs::write(&path, client.identify()).unwrap();
+
+        let wallet = Wallet::open(&path).unwrap();
+        assert_ne!(wallet.public_key().unwrap(), client.public_key);
+        assert!(path.with_extension("lost").exists());
+    }
+
+    #[test]
+    fn unknown_file_is_left_in_place() {
+        let path = wallet_path("unknown");
+        std::fs::write(&path, "{\"version\": 99}").unwrap();
+
+        assert!(matches!(Wallet::open(&path), Err(Error::Wallet(_))));
+        assert_eq!(std::fs::read(&path).unwrap(), b"{\"version\": 99}");
+        assert!(!path.with_extension("lost").exists());
+    }
+}
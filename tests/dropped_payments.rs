@@ -0,0 +1,100 @@
+use chrono::{Duration, TimeZone, Utc};
+use simple_blockchain_rs::{
+    blockchain::{Blockchain, DropReason},
+    client::Client,
+    clock::FixedClock,
+    config::{BlockchainConfig, Network},
+};
+use std::sync::Arc;
+
+fn blockchain(name: &str, max_transactions: usize) -> (Blockchain, Arc<FixedClock>) {
+    let dir = std::env::temp_dir().join(format!("dropped-{}-{}", name, std::process::id()));
+    let _ = std::fs::remove_dir_all(&dir);
+    let mut config = BlockchainConfig::new(dir, Network::Regtest);
+    config.coinbase_maturity = 0;
+    config.mempool.max_transactions = max_transactions;
+    let clock = Arc::new(FixedClock::new(
+        Utc.timestamp_opt(1_700_000_000, 0).unwrap(),
+    ));
+    let mut blockchain = Blockchain::with_clock(config, clock.clone()).unwrap();
+    blockchain.start_blockchain().unwrap();
+    (blockchain, clock)
+}
+
+/// This method pays 10 to a new client from the wallet and confirms it.
+fn funded_client(blockchain: &mut Blockchain) -> Client {
+    let client = Client::new();
+    blockchain
+        .send_transaction(blockchain.address(&client.public_key), 10.0, 0.0, None)
+        .unwrap();
+    blockchain.mine().unwrap();
+    client
+}
+
+/// This method broadcasts a payment of 1 from `client` paying `fee`.
+fn broadcast_payment(blockchain: &mut Blockchain, client: &Client, fee: f64) {
+    let receiver = blockchain.address(&Client::new().public_key);
+    let mut transaction = blockchain
+        .unsigned_transaction(client.public_key.into(), receiver, 1.0, fee, None)
+        .unwrap();
+    transaction.sign_transaction(client);
+    blockchain.broadcast_transaction(transaction).unwrap();
+}
+
+#[test]
+fn replaced_payment_is_reported() {
+    let (mut blockchain, _) = blockchain("replaced", 1);
+    let receiver = blockchain.address(&Client::new().public_key);
+    let payment = blockchain
+        .send_transaction(receiver, 1.0, 0.0, None)
+        .unwrap();
+    blockchain.bump_fee(&payment.txid(), None).unwrap();
+
+    let dropped = blockchain.take_dropped_payments();
+    assert_eq!(dropped.len(), 1);
+    assert_eq!(dropped[0].reason, DropReason::Replaced);
+    assert_eq!(dropped[0].transaction.txid(), payment.txid());
+    assert!(blockchain.take_dropped_payments().is_empty());
+}
+
+#[test]
+fn evicted_payment_is_reported() {
+    let (mut blockchain, _) = blockchain("evicted", 1);
+    let other = funded_client(&mut blockchain);
+    let receiver = blockchain.address(&Client::new().public_key);
+    let payment = blockchain
+        .send_transaction(receiver, 1.0, 0.0, None)
+        .unwrap();
+    broadcast_payment(&mut blockchain, &other, 0.5);
+
+    let dropped = blockchain.take_dropped_payments();
+    assert_eq!(dropped.len(), 1);
+    assert_eq!(dropped[0].reason, DropReason::Evicted);
+    assert_eq!(dropped[0].transaction.txid(), payment.txid());
+}
+
+#[test]
+fn payment_following_an_expired_one_is_reported_apart() {
+    let (mut blockchain, clock) = blockchain("expired", 10);
+    let other = funded_client(&mut blockchain);
+    let receiver = blockchain.address(&Client::new().public_key);
+    let expired = blockchain
+        .send_transaction(receiver, 1.0, 0.0, None)
+        .unwrap();
+    clock.advance(Duration::days(1));
+    let dependent = blockchain
+        .send_transaction(receiver, 1.0, 0.0, None)
+        .unwrap();
+
+    // Only the first payment is older than the mempool keeps
+    let max_age = Duration::from_std(blockchain.config.mempool.max_age).unwrap();
+    clock.advance(max_age - Duration::days(1) + Duration::seconds(1));
+    broadcast_payment(&mut blockchain, &other, 0.0);
+
+    let dropped = blockchain.take_dropped_payments();
+    assert_eq!(dropped.len(), 2);
+    assert_eq!(dropped[0].reason, DropReason::Expired);
+    assert_eq!(dropped[0].transaction.txid(), expired.txid());
+    assert_eq!(dropped[1].reason, DropReason::ParentExpired);
+    assert_eq!(dropped[1].transaction.txid(), dependent.txid());
+}
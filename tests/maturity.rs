@@ -0,0 +1,98 @@
+use chrono::Utc;
+use simple_blockchain_rs::{
+    block::Block,
+    blockchain::Blockchain,
+    client::Client,
+    config::{BlockchainConfig, Network},
+    transaction::Transaction,
+};
+
+const MATURITY: usize = 3;
+
+fn blockchain(name: &str) -> Blockchain {
+    let dir = std::env::temp_dir().join(format!("maturity-{}-{}", name, std::process::id()));
+    let _ = std::fs::remove_dir_all(&dir);
+    let mut config = BlockchainConfig::new(dir, Network::Regtest);
+    config.coinbase_maturity = MATURITY;
+    let mut blockchain = Blockchain::new(config).unwrap();
+    blockchain.start_blockchain().unwrap();
+    blockchain
+}
+
+fn wallet_immature(blockchain: &Blockchain) -> f64 {
+    let address = blockchain.address(&blockchain.wallet.public_key().unwrap());
+    blockchain
+        .immature_balances()
+        .unwrap()
+        .get(&address)
+        .copied()
+        .unwrap_or(0.0)
+}
+
+#[test]
+fn rewards_mature_after_the_maturity_depth() {
+    let mut blockchain = blockchain("depth");
+    // The genesis reward is spendable right away
+    assert_eq!(wallet_immature(&blockchain), 0.0);
+    blockchain.mine().unwrap();
+    blockchain.mine().unwrap();
+    assert_eq!(wallet_immature(&blockchain), 100.0);
+
+    let receiver = blockchain.address(&Client::new().public_key);
+    let error = blockchain
+        .send_transaction(receiver, 60.0, 0.0, None)
+        .unwrap_err();
+    assert!(error.to_string().contains("not mature"), "{}", error);
+    blockchain
+        .send_transaction(receiver, 40.0, 0.0, None)
+        .unwrap();
+
+    // The reward of block 1 can be spent from block 4 on
+    blockchain.mine().unwrap();
+    assert_eq!(wallet_immature(&blockchain), 100.0);
+    blockchain
+        .send_transaction(receiver, 50.0, 0.0, None)
+        .unwrap();
+}
+
+#[test]
+fn block_spending_an_immature_reward_is_refused() {
+    let mut blockchain = blockchain("block");
+    blockchain.mine().unwrap();
+    let latest = blockchain.get_block_at_height(1).unwrap().unwrap();
+    let client = blockchain.wallet.client().unwrap();
+    let mut spend = Transaction::new(
+        Some(client.public_key.into()),
+        blockchain.address(&Client::new().public_key).into(),
+        60.0,
+        None,
+    );
+    spend.nonce = Some(0);
+    spend.sign_transaction(client);
+
+    let mut block = Block::new(2, latest.block_hash);
+    block.time = Some(Utc::now());
+    block.verified_transactions = vec![
+        blockchain
+            .coinbase_transaction(blockchain.config.block_reward(2))
+            .unwrap(),
+        spend,
+    ];
+    let block = block.mine_block(blockchain.config.difficulty).unwrap();
+    assert!(blockchain.process_block(&block).is_err());
+    assert_eq!(blockchain.verify_chain(None).unwrap(), 2);
+}
+
+#[test]
+fn disconnected_rewards_are_no_longer_maturing() {
+    let mut blockchain = blockchain("disconnect");
+    blockchain.mine().unwrap();
+    blockchain.mine().unwrap();
+    assert_eq!(wallet_immature(&blockchain), 100.0);
+
+    blockchain.disconnect_tip().unwrap();
+    assert_eq!(wallet_immature(&blockchain), 50.0);
+    blockchain.reindex().unwrap();
+    assert_eq!(wallet_immature(&blockchain), 50.0);
+    blockchain.verify_chain(None).unwrap();
+}
@@ -0,0 +1,202 @@
+use rocksdb::{IteratorMode, DB};
+use secp256k1::PublicKey;
+use simple_blockchain_rs::{
+    blockchain::Blockchain,
+    codec,
+    config::{BlockchainConfig, Network},
+    migration, storage,
+};
+use std::{collections::BTreeMap, path::PathBuf, str::FromStr};
+
+fn data_dir(name: &str) -> PathBuf {
+    let dir = std::env::temp_dir().join(format!("migration-{}-{}", name, std::process::id()));
+    let _ = std::fs::remove_dir_all(&dir);
+    dir
+}
+
+/// This method writes the records of `fixtures/baseline_chain.json` into the stores of
+/// `config`. The fixture holds 102 blocks mined and stored by version 0, with payments
+/// at heights 1, 50 and 60.
+fn write_baseline_chain(config: &BlockchainConfig) {
+    let chain_dir = config.chain_dir();
+    std::fs::create_dir_all(&chain_dir).unwrap();
+    let fixture: BTreeMap<String, BTreeMap<String, String>> =
+        serde_json::from_str(include_str!("fixtures/baseline_chain.json")).unwrap();
+    for (store, records) in fixture.iter() {
+        if store == "_wallets" {
+            std::fs::write(chain_dir.join(store), &records["contents"]).unwrap();
+            continue;
+        }
+        let db = DB::open_default(chain_dir.join(store)).unwrap();
+        for (key, value) in records.iter() {
+            db.put(key, value).unwrap();
+        }
+    }
+}
+
+#[test]
+fn baseline_chain_keeps_its_rewards() {
+    let config = BlockchainConfig::new(data_dir("baseline"), Network::Mainnet);
+    write_baseline_chain(&config);
+
+    // Version 0 paid every block the reward of the next height
+    let mut blockchain = Blockchain::new(config.clone()).unwrap();
+    let block_hash = storage::get_block_hash(&blockchain.block_metadata, 99)
+        .unwrap()
+        .unwrap();
+    let block = storage::get_block(&blockchain.blocks, &block_hash)
+        .unwrap()
+        .unwrap();
+    assert_eq!(
+        block.verified_transactions[0].amount,
+        config.block_reward(100)
+    );
+    assert_eq!(blockchain.verify_chain(None).unwrap(), 102);
+    assert_eq!(blockchain.reindex().unwrap(), 102);
+    blockchain.verify_chain(None).unwrap();
+
+    // New blocks follow the schedule
+    blockchain.start_blockchain().unwrap();
+    let block = blockchain.mine().unwrap();
+    assert_eq!(block.index, 102);
+    assert_eq!(
+        block.verified_transactions[0].amount,
+        config.block_reward(102)
+    );
+    blockchain.verify_chain(None).unwrap();
+}
+
+#[test]
+fn legacy_database_with_immature_spends_is_upgraded() {
+    // Under this rule block 50 of the fixture spends immature rewards
+    let mut config = BlockchainConfig::new(data_dir("legacy"), Network::Mainnet);
+    config.coinbase_maturity = 100;
+    write_baseline_chain(&config);
+    let fixture: BTreeMap<String, BTreeMap<String, String>> =
+        serde_json::from_str(include_str!("fixtures/baseline_chain.json")).unwrap();
+
+    let mut blockchain = Blockchain::new(config.clone()).unwrap();
+    assert_eq!(
+        storage::get_schema_version(&blockchain.block_metadata).unwrap(),
+        Some(migration::SCHEMA_VERSION)
+    );
+    assert_eq!(
+        storage::get_latest_block_number(&blockchain.block_metadata).unwrap(),
+        101
+    );
+    assert_eq!(
+        storage::get_latest_block_hash(&blockchain.block_metadata).unwrap(),
+        Some(fixture["_blocks_metadata"]["latest_block_hash"].clone())
+    );
+    for item in blockchain.blocks.iterator(IteratorMode::Start) {
+        let (_, record) = item.unwrap();
+        assert!(codec::is_binary_record(&record, false));
+    }
+
+    // Balances were kept by public key with their `f64` text
+    let balances = storage::get_balances(&blockchain.balances).unwrap();
+    assert_eq!(balances.len(), fixture["_balances"].len());
+    for (public_key, balance) in fixture["_balances"].iter() {
+        let address = blockchain.address(&PublicKey::from_str(public_key).unwrap());
+        assert_eq!(balances[&address], balance.parse::<f64>().unwrap());
+        assert!(
+            !storage::get_address_transactions(&blockchain.block_metadata, address)
+                .unwrap()
+                .is_empty()
+        );
+    }
+    assert_eq!(blockchain.verify_chain(None).unwrap(), 102);
+    assert_eq!(blockchain.reindex().unwrap(), 102);
+    assert_eq!(
+        storage::get_balances(&blockchain.balances).unwrap(),
+        balances
+    );
+    blockchain.verify_chain(None).unwrap();
+
+    // New blocks are still subject to the rule
+    blockchain.start_blockchain().unwrap();
+    let block = blockchain.mine().unwrap();
+    assert_eq!(block.index, 102);
+    let receiver = blockchain.address(&simple_blockchain_rs::client::Client::new().public_key);
+    let available = blockchain.account_balance("default").unwrap();
+    assert!(blockchain
+        .send_transaction(receiver, available + 1.0, 0.0, None)
+        .is_err());
+}
+
+#[test]
+fn version_4_records_are_rekeyed_by_address() {
+    let config = BlockchainConfig::new(data_dir("v4"), Network::Regtest);
+    let (address, balance) = {
+        let mut blockchain = Blockchain::new(config.clone()).unwrap();
+        blockchain.start_blockchain().unwrap();
+        blockchain.mine().unwrap();
+        let public_key = blockchain.wallet.public_key().unwrap();
+        let address = blockchain.address(&public_key);
+        let balance = storage::get_balance(&blockchain.balances, address)
+            .unwrap()
+            .unwrap();
+
+        // Version 4 kept balances and coinbase records by public key
+        storage::delete_balance(&blockchain.balances, address).unwrap();
+        blockchain
+            .balances
+            .put(public_key.to_string(), balance.to_string())
+            .unwrap();
+        blockchain
+            .block_metadata
+            .put(
+                "coinbase_1",
+                serde_json::to_vec(&(public_key.to_string(), 50.0)).unwrap(),
+            )
+            .unwrap();
+        storage::set_schema_version(&blockchain.block_metadata, 4).unwrap();
+        (address, balance)
+    };
+
+    let blockchain = Blockchain::new(config).unwrap();
+    assert_eq!(
+        storage::get_balances(&blockchain.balances).unwrap()[&address],
+        balance
+    );
+    assert_eq!(
+        storage::get_coinbase(&blockchain.block_metadata, 1).unwrap(),
+        Some((address, 50.0))
+    );
+    assert!(
+        !storage::get_address_transactions(&blockchain.block_metadata, address)
+            .unwrap()
+            .is_empty()
+    );
+    blockchain.verify_chain(None).unwrap();
+}
+
+#[test]
+fn legacy_layout_is_moved_into_the_mainnet_directory() {
+    let data_dir = data_dir("layout");
+    let config = BlockchainConfig::new(&data_dir, Network::Mainnet);
+    std::fs::create_dir_all(data_dir.join("_blocks")).unwrap();
+    std::fs::write(data_dir.join("_wallets"), "").unwrap();
+
+    migration::migrate_legacy_layout(&config).unwrap();
+    assert!(config.blocks_path().exists());
+    assert!(config.wallet_path().exists());
+    assert!(!data_dir.join("_blocks").exists());
+}
+
+#[test]
+fn newer_schema_is_refused() {
+    let config = BlockchainConfig::new(data_dir("newer"), Network::Regtest);
+    {
+        let mut blockchain = Blockchain::new(config.clone()).unwrap();
+        blockchain.start_blockchain().unwrap();
+        storage::set_schema_version(&blockchain.block_metadata, migration::SCHEMA_VERSION + 1)
+            .unwrap();
+    }
+
+    let error = Blockchain::new(config).err().unwrap();
+    assert!(matches!(
+        error,
+        simple_blockchain_rs::error::Error::UnsupportedSchema { .. }
+    ));
+}
@@ -0,0 +1,73 @@
+use chrono::Utc;
+use simple_blockchain_rs::{
+    block::Block,
+    blockchain::Blockchain,
+    client::Client,
+    config::{BlockchainConfig, Network},
+    transaction::Transaction,
+};
+
+fn blockchain(name: &str) -> Blockchain {
+    let dir = std::env::temp_dir().join(format!("replay-{}-{}", name, std::process::id()));
+    let _ = std::fs::remove_dir_all(&dir);
+    let mut blockchain = Blockchain::new(BlockchainConfig::new(dir, Network::Regtest)).unwrap();
+    blockchain.start_blockchain().unwrap();
+    blockchain.mine().unwrap();
+    blockchain.mine().unwrap();
+    blockchain
+}
+
+/// This method signs a payment from the wallet key without a nonce, as transactions were
+/// before nonces existed.
+fn nonceless_payment(blockchain: &Blockchain) -> Transaction {
+    let receiver = blockchain.address(&Client::new().public_key);
+    let client = blockchain.wallet.client().unwrap();
+    let mut transaction =
+        Transaction::new(Some(client.public_key.into()), receiver.into(), 1.0, None);
+    transaction.sign_transaction(client);
+    transaction
+}
+
+/// This method mines a block holding `transactions` after the latest block.
+fn mine_block(blockchain: &Blockchain, transactions: Vec<Transaction>) -> Block {
+    let latest = (0..)
+        .map_while(|height| blockchain.get_block_at_height(height).unwrap())
+        .last()
+        .unwrap();
+    let mut block = Block::new(latest.index + 1, latest.block_hash);
+    block.time = Some(Utc::now());
+    block.verified_transactions = vec![blockchain
+        .coinbase_transaction(blockchain.config.block_reward(latest.index + 1))
+        .unwrap()];
+    block.verified_transactions.extend(transactions);
+    block.mine_block(blockchain.config.difficulty).unwrap()
+}
+
+#[test]
+fn mempool_requires_a_nonce() {
+    let mut blockchain = blockchain("mempool");
+    let transaction = nonceless_payment(&blockchain);
+    let error = blockchain.broadcast_transaction(transaction).unwrap_err();
+    assert!(error.to_string().contains("nonce"), "{}", error);
+}
+
+#[test]
+fn nonceless_transaction_cannot_be_confirmed_twice() {
+    let mut blockchain = blockchain("twice");
+    let transaction = nonceless_payment(&blockchain);
+    let block = mine_block(&blockchain, vec![transaction.clone()]);
+    blockchain.process_block(&block).unwrap();
+
+    let replay = mine_block(&blockchain, vec![transaction]);
+    let error = blockchain.process_block(&replay).unwrap_err();
+    assert!(error.to_string().contains("already confirmed"), "{}", error);
+}
+
+#[test]
+fn nonceless_transaction_cannot_appear_twice_in_a_block() {
+    let mut blockchain = blockchain("duplicate");
+    let transaction = nonceless_payment(&blockchain);
+    let block = mine_block(&blockchain, vec![transaction.clone(), transaction]);
+    let error = blockchain.process_block(&block).unwrap_err();
+    assert!(error.to_string().contains("already confirmed"), "{}", error);
+}
@@ -0,0 +1,138 @@
+use simple_blockchain_rs::{
+    blockchain::Blockchain,
+    client::Client,
+    config::{BlockchainConfig, Network},
+    snapshot::Snapshot,
+};
+use std::path::PathBuf;
+
+fn data_dir(name: &str) -> PathBuf {
+    let dir = std::env::temp_dir().join(format!("snapshot-{}-{}", name, std::process::id()));
+    let _ = std::fs::remove_dir_all(&dir);
+    dir
+}
+
+fn config(name: &str) -> BlockchainConfig {
+    let mut config = BlockchainConfig::new(data_dir(name), Network::Regtest);
+    config.coinbase_maturity = 10;
+    config.snapshot_interval = None;
+    config
+}
+
+/// This method mines a chain of 5 blocks and snapshots it.
+fn snapshot_chain(name: &str) -> (Blockchain, Snapshot) {
+    let mut blockchain = Blockchain::new(config(name)).unwrap();
+    blockchain.start_blockchain().unwrap();
+    for _ in 0..5 {
+        blockchain.mine().unwrap();
+    }
+    let snapshot = blockchain.create_snapshot().unwrap();
+    (blockchain, snapshot)
+}
+
+/// This method edits a snapshot and recomputes its state hash, as anyone can.
+fn tamper(snapshot: &Snapshot, edit: impl FnOnce(&mut Snapshot)) -> Snapshot {
+    let mut tampered = snapshot.clone();
+    edit(&mut tampered);
+    tampered.state_hash = tampered.calculate_hash();
+    tampered
+}
+
+#[test]
+fn snapshot_keeps_maturing_rewards() {
+    let (source, snapshot) = snapshot_chain("source");
+    assert_eq!(snapshot.coinbases.len(), 5);
+
+    let mut blockchain = Blockchain::new(config("bootstrap")).unwrap();
+    blockchain
+        .load_snapshot(&snapshot, &snapshot.state_hash)
+        .unwrap();
+    assert_eq!(
+        blockchain.immature_balances().unwrap(),
+        source.immature_balances().unwrap()
+    );
+    blockchain.mine().unwrap();
+    blockchain.verify_chain(None).unwrap();
+}
+
+#[test]
+fn verification_keeps_the_maturing_rewards_of_the_snapshot() {
+    let (source, snapshot) = snapshot_chain("verify");
+
+    // A node without the maturity rule spends the rewards of the snapshot right away
+    let strict_config = config("verify-load");
+    let mut lax_config = strict_config.clone();
+    lax_config.coinbase_maturity = 0;
+    std::fs::create_dir_all(lax_config.chain_dir()).unwrap();
+    std::fs::copy(source.config.wallet_path(), lax_config.wallet_path()).unwrap();
+    {
+        let mut blockchain = Blockchain::new(lax_config).unwrap();
+        blockchain
+            .load_snapshot(&snapshot, &snapshot.state_hash)
+            .unwrap();
+        let receiver = blockchain.address(&Client::new().public_key);
+        let balance = blockchain.account_balance("default").unwrap();
+        blockchain
+            .send_transaction(receiver, balance, 0.0, None)
+            .unwrap();
+        blockchain.mine().unwrap();
+    }
+
+    let blockchain = Blockchain::new(strict_config).unwrap();
+    let error = blockchain.verify_chain(None).unwrap_err();
+    assert!(error.to_string().contains("not mature"), "{}", error);
+}
+
+#[test]
+fn snapshot_needs_the_expected_state_hash() {
+    let (_, snapshot) = snapshot_chain("expected");
+    let tampered = tamper(&snapshot, |tampered| {
+        for balance in tampered.balances.values_mut() {
+            *balance /= 2.0;
+        }
+    });
+
+    let mut blockchain = Blockchain::new(config("expected-load")).unwrap();
+    assert!(blockchain
+        .load_snapshot(&tampered, &snapshot.state_hash)
+        .is_err());
+    assert!(blockchain
+        .load_snapshot(&snapshot, &tampered.state_hash)
+        .is_err());
+}
+
+#[test]
+fn snapshot_cannot_exceed_the_supply() {
+    let (_, snapshot) = snapshot_chain("supply");
+    let inflated = tamper(&snapshot, |tampered| {
+        for balance in tampered.balances.values_mut() {
+            *balance *= 2.0;
+        }
+    });
+
+    let mut blockchain = Blockchain::new(config("supply-load")).unwrap();
+    let error = blockchain
+        .load_snapshot(&inflated, &inflated.state_hash)
+        .unwrap_err();
+    assert!(error.to_string().contains("supply"), "{}", error);
+}
+
+#[test]
+fn snapshot_without_maturing_rewards_is_refused() {
+    let (_, snapshot) = snapshot_chain("coinbases");
+    let stripped = tamper(&snapshot, |tampered| tampered.coinbases.clear());
+
+    let mut blockchain = Blockchain::new(config("coinbases-load")).unwrap();
+    let error = blockchain
+        .load_snapshot(&stripped, &stripped.state_hash)
+        .unwrap_err();
+    assert!(error.to_string().contains("coinbase"), "{}", error);
+}
+
+#[test]
+fn pruning_without_snapshots_is_refused() {
+    let mut config = BlockchainConfig::new(data_dir("prune"), Network::Regtest);
+    config.prune_depth = Some(10);
+    config.snapshot_interval = None;
+    assert!(Blockchain::new(config).is_err());
+}
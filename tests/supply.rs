@@ -0,0 +1,47 @@
+use simple_blockchain_rs::{
+    blockchain::Blockchain,
+    client::Client,
+    config::{BlockchainConfig, Network},
+    storage,
+};
+
+fn assert_total_matches(blockchain: &Blockchain) {
+    let total: f64 = storage::get_balances(&blockchain.balances)
+        .unwrap()
+        .values()
+        .sum();
+    let recorded = storage::get_total_balance(&blockchain.block_metadata)
+        .unwrap()
+        .unwrap();
+    assert!((total - recorded).abs() < 1e-9, "{} != {}", total, recorded);
+}
+
+#[test]
+fn total_balance_follows_the_chain() {
+    let dir = std::env::temp_dir().join(format!("supply-{}", std::process::id()));
+    let _ = std::fs::remove_dir_all(&dir);
+    let mut config = BlockchainConfig::new(dir, Network::Regtest);
+    config.coinbase_maturity = 0;
+    let mut blockchain = Blockchain::new(config).unwrap();
+    blockchain.start_blockchain().unwrap();
+    blockchain.mine().unwrap();
+    blockchain.mine().unwrap();
+    assert_total_matches(&blockchain);
+
+    let receiver = blockchain.address(&Client::new().public_key);
+    blockchain
+        .send_transaction(receiver, 10.0, 1.5, None)
+        .unwrap();
+    blockchain.mine().unwrap();
+    assert_total_matches(&blockchain);
+    blockchain.verify_chain(None).unwrap();
+
+    blockchain.disconnect_tip().unwrap();
+    assert_total_matches(&blockchain);
+    blockchain.disconnect_tip().unwrap();
+    assert_total_matches(&blockchain);
+
+    blockchain.reindex().unwrap();
+    assert_total_matches(&blockchain);
+    blockchain.verify_chain(None).unwrap();
+}
@@ -0,0 +1,56 @@
+use chrono::{Duration, TimeZone, Utc};
+use simple_blockchain_rs::{
+    blockchain::Blockchain,
+    client::Client,
+    clock::{Clock, FixedClock},
+    config::{BlockchainConfig, Network},
+    transaction::Transaction,
+};
+use std::sync::Arc;
+
+fn blockchain(name: &str) -> (Blockchain, Arc<FixedClock>) {
+    let dir = std::env::temp_dir().join(format!("time-{}-{}", name, std::process::id()));
+    let _ = std::fs::remove_dir_all(&dir);
+    let mut config = BlockchainConfig::new(dir, Network::Regtest);
+    config.coinbase_maturity = 0;
+    let clock = Arc::new(FixedClock::new(
+        Utc.timestamp_opt(1_700_000_000, 0).unwrap(),
+    ));
+    let mut blockchain = Blockchain::with_clock(config, clock.clone()).unwrap();
+    blockchain.start_blockchain().unwrap();
+    blockchain.mine().unwrap();
+    (blockchain, clock)
+}
+
+/// This method signs a payment from the wallet key dated `age` before the clock.
+fn payment(blockchain: &Blockchain, clock: &FixedClock, age: Duration) -> Transaction {
+    let client = blockchain.wallet.client().unwrap();
+    let receiver = blockchain.address(&Client::new().public_key);
+    let mut transaction = blockchain
+        .unsigned_transaction(client.public_key.into(), receiver, 1.0, 0.0, None)
+        .unwrap();
+    transaction.set_time(clock.now() - age);
+    transaction.sign_transaction(client);
+    transaction
+}
+
+#[test]
+fn transaction_older_than_the_mempool_keeps_is_refused() {
+    let (mut blockchain, clock) = blockchain("old");
+    let max_age = Duration::from_std(blockchain.config.mempool.max_age).unwrap();
+
+    let old = payment(&blockchain, &clock, max_age + Duration::seconds(1));
+    let error = blockchain.broadcast_transaction(old).unwrap_err();
+    assert!(error.to_string().contains("older"), "{}", error);
+
+    let recent = payment(&blockchain, &clock, max_age - Duration::seconds(1));
+    blockchain.broadcast_transaction(recent).unwrap();
+}
+
+#[test]
+fn transaction_from_the_future_is_refused() {
+    let (mut blockchain, clock) = blockchain("future");
+    let transaction = payment(&blockchain, &clock, -Duration::days(1));
+    let error = blockchain.broadcast_transaction(transaction).unwrap_err();
+    assert!(error.to_string().contains("future"), "{}", error);
+}